@@ -0,0 +1,172 @@
+use crate::fdsn_source_identifier::FdsnSourceIdentifier;
+
+/// Corner period, in seconds, dividing short period from long period instruments when
+/// choosing a band code.
+pub const BAND_CORNER_PERIOD: f64 = 10.0;
+
+/// Response of the instrument, which selects between the band codes for sample rates
+/// of 10 Hz and above.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InstrumentType {
+    /// Corner period less than 10 seconds.
+    ShortPeriod,
+    /// Corner period 10 seconds or more, for example a broadband seismometer.
+    LongPeriod,
+}
+
+impl InstrumentType {
+    pub fn from_corner_period(seconds: f64) -> InstrumentType {
+        if seconds < BAND_CORNER_PERIOD {
+            InstrumentType::ShortPeriod
+        } else {
+            InstrumentType::LongPeriod
+        }
+    }
+}
+
+/// Band codes for sample rates, lowest rate first: the minimum rate in Hz, whether the
+/// rate must be strictly greater than the minimum, and the band codes for short and long
+/// period instruments. The FDSN only gives approximate rates for W, U, V and L, so the
+/// boundaries between them are taken halfway on a log scale.
+const BANDS: [(f64, bool, &str, &str); 14] = [
+    (0.0, false, "Q", "Q"),
+    (0.000_001, false, "T", "T"),
+    (0.000_01, false, "P", "P"),
+    (0.000_1, false, "R", "R"),
+    (0.001, false, "W", "W"),
+    (0.003_162_277_66, false, "U", "U"),
+    (0.031_622_776_6, false, "V", "V"),
+    (0.316_227_766, false, "L", "L"),
+    // M is more than 1 Hz, exactly 1 Hz is L
+    (1.0, true, "M", "M"),
+    (10.0, false, "S", "B"),
+    (80.0, false, "E", "H"),
+    (250.0, false, "D", "C"),
+    (1000.0, false, "G", "F"),
+    // J is more than 5000 Hz, exactly 5000 Hz is G or F
+    (5000.0, true, "J", "J"),
+];
+
+/// Suggests the FDSN band code for the sample rate, as in the header sample_rate_period
+/// field, positive for a rate in Hz and negative for a period in seconds, and the type of
+/// instrument. None if there is no sample rate, as the band code `I` for irregularly
+/// sampled data cannot be inferred from the rate.
+///
+/// See <http://docs.fdsn.org/projects/source-identifiers/en/v1.0/channel-codes.html>
+///
+/// #Example
+///
+/// ```
+/// use mseed3::{suggest_band_code, InstrumentType};
+/// assert_eq!(suggest_band_code(40.0, InstrumentType::LongPeriod), Some("B"));
+/// assert_eq!(suggest_band_code(40.0, InstrumentType::ShortPeriod), Some("S"));
+/// assert_eq!(suggest_band_code(-10.0, InstrumentType::LongPeriod), Some("V"));
+/// ```
+pub fn suggest_band_code(
+    sample_rate_period: f64,
+    instrument: InstrumentType,
+) -> Option<&'static str> {
+    let rate = sample_rate_hz(sample_rate_period)?;
+    let (_, _, short, long) = BANDS
+        .iter()
+        .rev()
+        .find(|(min, exclusive, _, _)| rate > *min || (!exclusive && rate == *min))?;
+    match instrument {
+        InstrumentType::ShortPeriod => Some(short),
+        InstrumentType::LongPeriod => Some(long),
+    }
+}
+
+/// Checks the band code of the identifier against the sample rate, returning a
+/// description of the problem if it is not one of the codes for the rate. The check is
+/// skipped, returning None, for an empty band, band codes not tied to a sample rate, such
+/// as `I` for irregularly sampled, and records without a sample rate.
+pub fn check_band_code(
+    identifier: &FdsnSourceIdentifier,
+    sample_rate_period: f64,
+) -> Option<String> {
+    let band = identifier.band.as_str();
+    if !BANDS
+        .iter()
+        .any(|(_, _, short, long)| band == *short || band == *long)
+    {
+        return None;
+    }
+    let short = suggest_band_code(sample_rate_period, InstrumentType::ShortPeriod)?;
+    let long = suggest_band_code(sample_rate_period, InstrumentType::LongPeriod)?;
+    if band == short || band == long {
+        return None;
+    }
+    let expected = if short == long {
+        String::from(short)
+    } else {
+        format!("{} or {}", short, long)
+    };
+    Some(format!(
+        "band code {} of {} does not match sample rate {} Hz, expected {}",
+        band,
+        identifier,
+        sample_rate_hz(sample_rate_period).unwrap_or(0.0),
+        expected
+    ))
+}
+
+fn sample_rate_hz(sample_rate_period: f64) -> Option<f64> {
+    let rate = if sample_rate_period < 0.0 {
+        -1.0 / sample_rate_period
+    } else {
+        sample_rate_period
+    };
+    if rate > 0.0 && rate.is_finite() {
+        Some(rate)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn suggest_and_check() {
+        let long = InstrumentType::LongPeriod;
+        let short = InstrumentType::from_corner_period(1.0);
+        assert_eq!(short, InstrumentType::ShortPeriod);
+        assert_eq!(suggest_band_code(100.0, long), Some("H"));
+        assert_eq!(suggest_band_code(100.0, short), Some("E"));
+        assert_eq!(suggest_band_code(6000.0, long), Some("J"));
+        assert_eq!(suggest_band_code(1000.0, short), Some("G"));
+        assert_eq!(suggest_band_code(5.0, long), Some("M"));
+        assert_eq!(suggest_band_code(1.0, long), Some("L"));
+        assert_eq!(suggest_band_code(-100.0, long), Some("U"));
+        assert_eq!(suggest_band_code(-3600.0, long), Some("R"));
+        assert_eq!(suggest_band_code(-86400.0 * 30.0, long), Some("Q"));
+        assert_eq!(suggest_band_code(0.0, long), None);
+
+        // boundaries
+        assert_eq!(suggest_band_code(5000.0, long), Some("F"));
+        assert_eq!(suggest_band_code(5000.0, short), Some("G"));
+        assert_eq!(suggest_band_code(5000.001, short), Some("J"));
+        assert_eq!(suggest_band_code(1.000_001, long), Some("M"));
+        assert_eq!(suggest_band_code(0.0032, long), Some("U"));
+        assert_eq!(suggest_band_code(0.0031, long), Some("W"));
+        assert_eq!(suggest_band_code(-1000.0, long), Some("W"));
+        assert_eq!(suggest_band_code(-1001.0, long), Some("R"));
+        assert_eq!(suggest_band_code(0.032, long), Some("V"));
+        assert_eq!(suggest_band_code(0.031, long), Some("U"));
+
+        let mut sid = FdsnSourceIdentifier::create_fake_channel();
+        assert_eq!(check_band_code(&sid, 20.0), None);
+        assert_eq!(check_band_code(&sid, 0.0), None);
+        let msg = check_band_code(&sid, 100.0).unwrap();
+        assert!(msg.contains("expected E or H"), "{}", msg);
+        sid.band = String::from("L");
+        assert!(check_band_code(&sid, 1.0).is_none());
+        assert!(check_band_code(&sid, -10.0).unwrap().contains("expected V"));
+        sid.band = String::from("I");
+        assert_eq!(check_band_code(&sid, 1.0), None);
+        sid.band = String::new();
+        assert_eq!(check_band_code(&sid, 1.0), None);
+    }
+}
@@ -0,0 +1,53 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fmt::Formatter;
+
+/// Timeseries data after decoding, either by uncompressing or converting from little endian
+/// bytes, into primitive values. Steim compressed data decodes as Int32.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum DecodedTimeseries {
+    Int16(Vec<i16>),
+    Int32(Vec<i32>),
+    Float32(Vec<f32>),
+    Float64(Vec<f64>),
+    Text(String),
+}
+
+impl DecodedTimeseries {
+    /// Number of samples, or for Text the number of bytes.
+    pub fn len(&self) -> usize {
+        match self {
+            DecodedTimeseries::Int16(v) => v.len(),
+            DecodedTimeseries::Int32(v) => v.len(),
+            DecodedTimeseries::Float32(v) => v.len(),
+            DecodedTimeseries::Float64(v) => v.len(),
+            DecodedTimeseries::Text(s) => s.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl fmt::Display for DecodedTimeseries {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodedTimeseries::Int16(v) => {
+                write!(f, "Int16, {} samples", v.len())
+            }
+            DecodedTimeseries::Int32(v) => {
+                write!(f, "Int32, {} samples", v.len())
+            }
+            DecodedTimeseries::Float32(v) => {
+                write!(f, "Float32, {} samples", v.len())
+            }
+            DecodedTimeseries::Float64(v) => {
+                write!(f, "Float64, {} samples", v.len())
+            }
+            DecodedTimeseries::Text(s) => {
+                write!(f, "Text, {} bytes", s.len())
+            }
+        }
+    }
+}
@@ -0,0 +1,335 @@
+use chrono::{DateTime, Utc};
+use lazy_static::lazy_static;
+use serde_json::map::Map;
+use serde_json::{json, Number};
+
+use crate::data_encoding::DataEncoding;
+use crate::encoded_timeseries::EncodedTimeseries;
+use crate::extra_header_schema::ExtraHeaderValidator;
+use crate::fdsn_headers::{FdsnDetection, FdsnEvent};
+use crate::fdsn_source_identifier::SourceIdentifier;
+use crate::header::MSeed3Header;
+use crate::mseed_error::MSeedError;
+use crate::record::{MSeed3Record, FDSN_EXTRA_HEADERS};
+
+/// Detection type for the Murdock-Hutt event detector.
+pub const MURDOCK_DETECTION: &str = "MURDOCK";
+/// Detection type for any other event detector.
+pub const GENERIC_DETECTION: &str = "GENERIC";
+
+lazy_static! {
+    static ref FDSN_SCHEMA_VALIDATOR: ExtraHeaderValidator = ExtraHeaderValidator::new();
+}
+
+impl FdsnDetection {
+    /// Create a detection with the type, usually MURDOCK_DETECTION or GENERIC_DETECTION, the
+    /// name of the detector and the onset time. Other fields can be set directly.
+    pub fn new(detection_type: &str, detector: &str, onset_time: DateTime<Utc>) -> FdsnDetection {
+        let mut detection = FdsnDetection {
+            detection_type: Some(String::from(detection_type)),
+            detector: Some(String::from(detector)),
+            ..Default::default()
+        };
+        detection.set_onset_time(onset_time);
+        detection
+    }
+
+    /// Onset time parsed from the ISO8601 string, None if not set.
+    pub fn get_onset_time(&self) -> Result<Option<DateTime<Utc>>, MSeedError> {
+        match &self.onset_time {
+            Some(s) => Ok(Some(s.parse::<DateTime<Utc>>()?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn set_onset_time(&mut self, onset_time: DateTime<Utc>) {
+        self.onset_time = Some(onset_time.format("%Y-%m-%dT%H:%M:%S%.9fZ").to_string());
+    }
+
+    /// Sets the six Murdock-Hutt signal to noise ratio values.
+    pub fn set_med_snr(&mut self, snr: [u8; 6]) {
+        self.med_snr = Some(snr.iter().map(|&v| v as i64).collect());
+    }
+
+    /// Checks the fields against the detection schema in the bundled FDSN extra header
+    /// schema, an ExtraHeaderSchema error lists all violations.
+    pub fn validate(&self) -> Result<(), MSeedError> {
+        let mut extra_headers = Map::new();
+        extra_headers.insert(
+            String::from(FDSN_EXTRA_HEADERS),
+            json!({"Event": {"Detection": [serde_json::to_value(self)?]}}),
+        );
+        let violations = FDSN_SCHEMA_VALIDATOR.validate(&extra_headers);
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(MSeedError::ExtraHeaderSchema(violations))
+        }
+    }
+}
+
+impl MSeed3Record {
+    /// Create a detection only record, with no samples, carrying the detections in
+    /// `FDSN.Event.Detection`. The record start is the earliest onset time, so at least
+    /// one of the detections must have one.
+    pub fn from_detections(
+        identifier: SourceIdentifier,
+        detections: Vec<FdsnDetection>,
+    ) -> Result<MSeed3Record, MSeedError> {
+        let mut start = None;
+        for d in &detections {
+            d.validate()?;
+            if let Some(onset) = d.get_onset_time()? {
+                start = Some(start.map_or(onset, |s: DateTime<Utc>| s.min(onset)));
+            }
+        }
+        let start = start.ok_or_else(|| {
+            MSeedError::ExtraHeaderParse(String::from(
+                "detection only record needs a detection with an onset time",
+            ))
+        })?;
+        let header = MSeed3Header::new(start, DataEncoding::TEXT, 0.0, 0);
+        let mut rec = MSeed3Record::new(header, identifier, None, EncodedTimeseries::Raw(vec![]));
+        rec.set_fdsn_event(&FdsnEvent {
+            detection: Some(detections),
+            ..Default::default()
+        })?;
+        Ok(rec)
+    }
+
+    /// Appends a detection to `FDSN.Event.Detection`, keeping other event headers.
+    pub fn add_detection(&mut self, detection: FdsnDetection) -> Result<(), MSeedError> {
+        detection.validate()?;
+        let mut event = self.get_fdsn_event()?.unwrap_or_default();
+        event.detection.get_or_insert_with(Vec::new).push(detection);
+        self.set_fdsn_event(&event)
+    }
+
+    /// The detections in `FDSN.Event.Detection`, empty if there are none.
+    pub fn get_detections(&self) -> Result<Vec<FdsnDetection>, MSeedError> {
+        Ok(self
+            .get_fdsn_event()?
+            .and_then(|event| event.detection)
+            .unwrap_or_default())
+    }
+
+    /// True if the record has no samples and carries only detections.
+    pub fn is_detection_only(&self) -> bool {
+        self.header.num_samples == 0
+            && self
+                .get_fdsn_headers()
+                .and_then(|fdsn| fdsn.get("Event"))
+                .and_then(|event| event.get("Detection"))
+                .is_some_and(|d| d != &json!([]))
+    }
+}
+
+/// A detection along with the identifier and start time of the record it came from.
+#[derive(Debug, Clone)]
+pub struct RecordDetection {
+    pub identifier: SourceIdentifier,
+    pub record_start: DateTime<Utc>,
+    pub detection: FdsnDetection,
+}
+
+/// Criteria to select detections, each that is Some must match. Onset times are compared
+/// with start inclusive and end exclusive, detections without an onset never match a
+/// time range.
+#[derive(Debug, Clone, Default)]
+pub struct DetectionQuery {
+    pub detection_type: Option<String>,
+    pub detector: Option<String>,
+    pub start: Option<DateTime<Utc>>,
+    pub end: Option<DateTime<Utc>>,
+    pub min_signal_amplitude: Option<f64>,
+}
+
+impl DetectionQuery {
+    pub fn new() -> DetectionQuery {
+        DetectionQuery::default()
+    }
+
+    pub fn matches(&self, detection: &FdsnDetection) -> Result<bool, MSeedError> {
+        if self.detection_type.is_some() && self.detection_type != detection.detection_type {
+            return Ok(false);
+        }
+        if self.detector.is_some() && self.detector != detection.detector {
+            return Ok(false);
+        }
+        if let Some(min) = self.min_signal_amplitude {
+            match detection.signal_amplitude.as_ref().and_then(Number::as_f64) {
+                Some(amp) if amp.abs() >= min => (),
+                _ => return Ok(false),
+            }
+        }
+        if self.start.is_some() || self.end.is_some() {
+            let onset = match detection.get_onset_time()? {
+                Some(onset) => onset,
+                None => return Ok(false),
+            };
+            if self.start.is_some_and(|start| onset < start)
+                || self.end.is_some_and(|end| onset >= end)
+            {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Lists the detections matching this query from all the records.
+    pub fn filter<'a, I>(&self, records: I) -> Result<Vec<RecordDetection>, MSeedError>
+    where
+        I: IntoIterator<Item = &'a MSeed3Record>,
+    {
+        let mut found = Vec::new();
+        for rec in records {
+            for detection in rec.get_detections()? {
+                if self.matches(&detection)? {
+                    found.push(RecordDetection {
+                        identifier: rec.identifier.clone(),
+                        record_start: rec.header.get_start_as_utc(),
+                        detection,
+                    });
+                }
+            }
+        }
+        Ok(found)
+    }
+}
+
+/// Lists all detections from the records.
+pub fn list_detections<'a, I>(records: I) -> Result<Vec<RecordDetection>, MSeedError>
+where
+    I: IntoIterator<Item = &'a MSeed3Record>,
+{
+    DetectionQuery::new().filter(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufReader, BufWriter, Write};
+
+    fn onset(s: &str) -> DateTime<Utc> {
+        s.parse::<DateTime<Utc>>().unwrap()
+    }
+
+    #[test]
+    fn detection_only_round_trip() -> Result<(), MSeedError> {
+        let mut murdock = FdsnDetection::new(
+            MURDOCK_DETECTION,
+            "Z_SPWWSS",
+            onset("2004-07-28T20:28:06.185Z"),
+        );
+        murdock.signal_amplitude = Number::from_f64(80.0);
+        murdock.signal_period = Number::from_f64(0.4);
+        murdock.background_estimate = Number::from_f64(18.0);
+        murdock.wave = Some(String::from("DILATATION"));
+        murdock.set_med_snr([1, 2, 3, 4, 5, 6]);
+        murdock.med_lookback = Some(2);
+        murdock.med_pick_algorithm = Some(0);
+        let rec = MSeed3Record::from_detections(
+            SourceIdentifier::from("FDSN:XX_TEST__L_H_Z"),
+            vec![murdock.clone()],
+        )?;
+        assert!(rec.is_detection_only());
+        assert_eq!(
+            rec.header.get_start_as_utc(),
+            onset("2004-07-28T20:28:06.185Z")
+        );
+        let no_onset = FdsnDetection {
+            detector: Some(String::from("Z_SPWWSS")),
+            ..Default::default()
+        };
+        assert!(matches!(
+            MSeed3Record::from_detections(
+                SourceIdentifier::from("FDSN:XX_TEST__L_H_Z"),
+                vec![no_onset],
+            ),
+            Err(MSeedError::ExtraHeaderParse(_))
+        ));
+
+        let mut bytes = Vec::new();
+        {
+            let mut buf_writer = BufWriter::new(&mut bytes);
+            rec.write_to(&mut buf_writer)?;
+            buf_writer.flush()?;
+        }
+        let read = MSeed3Record::from_reader(&mut BufReader::new(&bytes[..]))?;
+        assert_eq!(read.get_detections()?, vec![murdock]);
+        assert_eq!(
+            read.get_fdsn_headers().unwrap()["Event"]["Detection"][0]["MEDSNR"],
+            json!([1, 2, 3, 4, 5, 6])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn query_detections() -> Result<(), MSeedError> {
+        let start = onset("2020-01-01T00:00:00Z");
+        let mut a = MSeed3Record::from_ints(start, 10.0, vec![1, 2, 3]);
+        let mut generic =
+            FdsnDetection::new(GENERIC_DETECTION, "stalta", onset("2020-01-01T00:00:01Z"));
+        generic.signal_amplitude = Some(Number::from(-50));
+        a.add_detection(generic)?;
+        a.add_detection(FdsnDetection::new(
+            MURDOCK_DETECTION,
+            "med",
+            onset("2020-01-01T00:00:05Z"),
+        ))?;
+        assert!(!a.is_detection_only());
+        let b = MSeed3Record::from_detections(
+            SourceIdentifier::from("FDSN:XX_OTHER_00_B_H_Z"),
+            vec![FdsnDetection::new(
+                MURDOCK_DETECTION,
+                "med",
+                onset("2020-01-01T00:01:00Z"),
+            )],
+        )?;
+        let records = vec![a, b];
+        assert_eq!(list_detections(&records)?.len(), 3);
+
+        let murdock = DetectionQuery {
+            detection_type: Some(String::from(MURDOCK_DETECTION)),
+            ..Default::default()
+        };
+        let found = murdock.filter(&records)?;
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[1].identifier.to_string(), "FDSN:XX_OTHER_00_B_H_Z");
+
+        let window = DetectionQuery {
+            start: Some(onset("2020-01-01T00:00:01Z")),
+            end: Some(onset("2020-01-01T00:00:05Z")),
+            ..Default::default()
+        };
+        let found = window.filter(&records)?;
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].detection.detector.as_deref(), Some("stalta"));
+
+        let loud = DetectionQuery {
+            min_signal_amplitude: Some(10.0),
+            ..Default::default()
+        };
+        assert_eq!(loud.filter(&records)?.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_detection() {
+        let mut d = FdsnDetection::new(GENERIC_DETECTION, "x", onset("2020-01-01T00:00:00Z"));
+        d.wave = Some(String::from("DILATATION"));
+        d.med_snr = Some(vec![1, 2]);
+        assert!(d.validate().is_ok());
+        d.onset_time = Some(String::from("noon"));
+        match d.validate() {
+            Err(MSeedError::ExtraHeaderSchema(v)) => {
+                assert_eq!(v.len(), 1);
+                assert_eq!(v[0].path, "/FDSN/Event/Detection/0/OnsetTime");
+            }
+            _ => panic!("expected schema error"),
+        }
+        d.onset_time = None;
+        d.other.insert(String::from("SignalPeriod"), json!("long"));
+        assert!(d.validate().is_err());
+    }
+}
@@ -1,11 +1,14 @@
-use byteorder::{LittleEndian, WriteBytesExt};
+use byteorder::{ByteOrder, LittleEndian, WriteBytesExt};
 use serde::{Deserialize, Serialize};
 use std::fmt;
 use std::fmt::Formatter;
 use std::io::prelude::*;
 use std::io::BufWriter;
 
+use crate::data_encoding::DataEncoding;
+use crate::decoded_timeseries::DecodedTimeseries;
 use crate::mseed_error::MSeedError;
+use crate::{steim1, steim2};
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum EncodedTimeseries {
@@ -103,6 +106,96 @@ impl EncodedTimeseries {
             EncodedTimeseries::Opaque(_) => header_num_sample,
         }
     }
+
+    /// Decodes the timeseries into primitive values, uncompressing Steim data. Raw bytes are
+    /// interpreted using the given encoding, usually from the header, and the other
+    /// types ignore it. The number of samples is needed for compressed data and to
+    /// check the length of Raw primitive data.
+    pub fn decode(
+        &self,
+        encoding: &DataEncoding,
+        num_samples: u32,
+    ) -> Result<DecodedTimeseries, MSeedError> {
+        match self {
+            EncodedTimeseries::Raw(v) => decode_raw(v, encoding, num_samples),
+            EncodedTimeseries::Int16(v) => Ok(DecodedTimeseries::Int16(v.clone())),
+            EncodedTimeseries::Int32(v) => Ok(DecodedTimeseries::Int32(v.clone())),
+            EncodedTimeseries::Float32(v) => Ok(DecodedTimeseries::Float32(v.clone())),
+            EncodedTimeseries::Float64(v) => Ok(DecodedTimeseries::Float64(v.clone())),
+            EncodedTimeseries::Steim1(v) => {
+                Ok(DecodedTimeseries::Int32(steim1::decode(v, num_samples)?))
+            }
+            EncodedTimeseries::Steim2(v) => {
+                Ok(DecodedTimeseries::Int32(steim2::decode(v, num_samples)?))
+            }
+            EncodedTimeseries::Steim3(_) => Err(MSeedError::Steim3Unsupported),
+            EncodedTimeseries::Opaque(_) => Err(MSeedError::OpaqueData),
+        }
+    }
+}
+
+/// Decodes raw bytes based on the encoding. Primitive types are little endian.
+fn decode_raw(
+    bytes: &[u8],
+    encoding: &DataEncoding,
+    num_samples: u32,
+) -> Result<DecodedTimeseries, MSeedError> {
+    let sample_size = match encoding {
+        DataEncoding::INT16 => 2,
+        DataEncoding::INT32 | DataEncoding::FLOAT32 => 4,
+        DataEncoding::FLOAT64 => 8,
+        _ => 0,
+    };
+    if sample_size > 0 && bytes.len() != sample_size * num_samples as usize {
+        let expected =
+            (sample_size as u32)
+                .checked_mul(num_samples)
+                .ok_or(MSeedError::DataLengthOverflow(
+                    num_samples,
+                    encoding.value(),
+                ))?;
+        return Err(MSeedError::DataLength(
+            expected,
+            num_samples,
+            encoding.value(),
+            bytes.len() as u32,
+        ));
+    }
+    let n = num_samples as usize;
+    match encoding {
+        DataEncoding::TEXT => Ok(DecodedTimeseries::Text(String::from_utf8(bytes.to_vec())?)),
+        DataEncoding::INT16 => {
+            let mut v = vec![0; n];
+            LittleEndian::read_i16_into(bytes, &mut v);
+            Ok(DecodedTimeseries::Int16(v))
+        }
+        DataEncoding::INT32 => {
+            let mut v = vec![0; n];
+            LittleEndian::read_i32_into(bytes, &mut v);
+            Ok(DecodedTimeseries::Int32(v))
+        }
+        DataEncoding::FLOAT32 => {
+            let mut v = vec![0.0; n];
+            LittleEndian::read_f32_into(bytes, &mut v);
+            Ok(DecodedTimeseries::Float32(v))
+        }
+        DataEncoding::FLOAT64 => {
+            let mut v = vec![0.0; n];
+            LittleEndian::read_f64_into(bytes, &mut v);
+            Ok(DecodedTimeseries::Float64(v))
+        }
+        DataEncoding::STEIM1 => Ok(DecodedTimeseries::Int32(steim1::decode(
+            bytes,
+            num_samples,
+        )?)),
+        DataEncoding::STEIM2 => Ok(DecodedTimeseries::Int32(steim2::decode(
+            bytes,
+            num_samples,
+        )?)),
+        DataEncoding::STEIM3 => Err(MSeedError::Steim3Unsupported),
+        DataEncoding::OPAQUE => Err(MSeedError::OpaqueData),
+        DataEncoding::UNKNOWN(val) => Err(MSeedError::UnknownEncoding(*val)),
+    }
 }
 
 impl fmt::Display for EncodedTimeseries {
@@ -138,3 +231,71 @@ impl fmt::Display for EncodedTimeseries {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn to_raw(ts: &EncodedTimeseries) -> EncodedTimeseries {
+        let mut out = Vec::new();
+        {
+            let mut buf_writer = BufWriter::new(&mut out);
+            ts.write_to(&mut buf_writer).unwrap();
+            buf_writer.flush().unwrap();
+        }
+        EncodedTimeseries::Raw(out)
+    }
+
+    #[test]
+    fn decode_raw_primitives() -> Result<(), MSeedError> {
+        let ts = EncodedTimeseries::Int16(vec![1, -2, 300]);
+        let decoded = to_raw(&ts).decode(&DataEncoding::INT16, 3)?;
+        assert_eq!(decoded, DecodedTimeseries::Int16(vec![1, -2, 300]));
+        let ts = EncodedTimeseries::Int32(vec![1, -2, 300_000]);
+        let decoded = to_raw(&ts).decode(&DataEncoding::INT32, 3)?;
+        assert_eq!(decoded, DecodedTimeseries::Int32(vec![1, -2, 300_000]));
+        let ts = EncodedTimeseries::Float32(vec![1.5, -2.25]);
+        let decoded = to_raw(&ts).decode(&DataEncoding::FLOAT32, 2)?;
+        assert_eq!(decoded, DecodedTimeseries::Float32(vec![1.5, -2.25]));
+        let ts = EncodedTimeseries::Float64(vec![1.5, -2.25]);
+        let decoded = to_raw(&ts).decode(&DataEncoding::FLOAT64, 2)?;
+        assert_eq!(decoded, DecodedTimeseries::Float64(vec![1.5, -2.25]));
+        let ts = EncodedTimeseries::Raw(Vec::from("hello".as_bytes()));
+        let decoded = ts.decode(&DataEncoding::TEXT, 5)?;
+        assert_eq!(decoded, DecodedTimeseries::Text(String::from("hello")));
+        Ok(())
+    }
+
+    #[test]
+    fn decode_raw_steim() -> Result<(), MSeedError> {
+        let data = vec![10, 12, 9, 300, -4000, 0];
+        let enc = steim1::encode(&data, 0)?.get_encoded_data()?;
+        let decoded = EncodedTimeseries::Raw(enc).decode(&DataEncoding::STEIM1, 6)?;
+        assert_eq!(decoded, DecodedTimeseries::Int32(data.clone()));
+        let enc = steim2::encode(&data, 0)?.get_encoded_data()?;
+        let decoded = EncodedTimeseries::Steim2(enc).decode(&DataEncoding::STEIM2, 6)?;
+        assert_eq!(decoded, DecodedTimeseries::Int32(data));
+        Ok(())
+    }
+
+    #[test]
+    fn decode_errors() {
+        let raw = EncodedTimeseries::Raw(vec![0; 6]);
+        assert!(matches!(
+            raw.decode(&DataEncoding::INT32, 2),
+            Err(MSeedError::DataLength(8, 2, 3, 6))
+        ));
+        assert!(matches!(
+            raw.decode(&DataEncoding::STEIM3, 2),
+            Err(MSeedError::Steim3Unsupported)
+        ));
+        assert!(matches!(
+            raw.decode(&DataEncoding::OPAQUE, 2),
+            Err(MSeedError::OpaqueData)
+        ));
+        assert!(matches!(
+            raw.decode(&DataEncoding::UNKNOWN(42), 2),
+            Err(MSeedError::UnknownEncoding(42))
+        ));
+    }
+}
@@ -0,0 +1,658 @@
+use chrono::{DateTime, Utc};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::map::Map;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::mseed_error::MSeedError;
+use crate::record::{parse_headers, MSeed3Record, UnparsedMSeed3Record, FDSN_EXTRA_HEADERS};
+
+/// The FDSN reserved extra headers JSON schema, bundled with the crate, from the FDSN miniSEED 3
+/// repository at <https://github.com/FDSN/miniSEED3>. It describes the whole extra headers
+/// object, the schema for the FDSN namespace is its FDSN property.
+pub const FDSN_EXTRA_HEADERS_SCHEMA: &str = include_str!("schema/ExtraHeaders-FDSN.schema.json");
+
+/// Version of the bundled FDSN extra headers schema.
+pub const FDSN_EXTRA_HEADERS_SCHEMA_VERSION: &str = "1.0";
+
+/// Schema keywords that are checked by the validator.
+const SUPPORTED_KEYWORDS: &[&str] = &[
+    "type",
+    "enum",
+    "const",
+    "properties",
+    "required",
+    "additionalProperties",
+    "items",
+    "minItems",
+    "maxItems",
+    "minimum",
+    "maximum",
+    "exclusiveMinimum",
+    "exclusiveMaximum",
+    "minLength",
+    "maxLength",
+    "pattern",
+    "format",
+    "$ref",
+    "$defs",
+    "definitions",
+];
+
+/// Schema keywords that do not affect validation.
+const ANNOTATION_KEYWORDS: &[&str] = &[
+    "$schema",
+    "$id",
+    "$comment",
+    "title",
+    "description",
+    "default",
+    "examples",
+    "deprecated",
+    "readOnly",
+    "writeOnly",
+];
+
+/// A single place where the extra headers do not match the schema for their namespace.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SchemaViolation {
+    /// The top level key of the extra headers, like FDSN.
+    pub namespace: String,
+    /// JSON pointer to the invalid value, like /FDSN/Time/Quality.
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for SchemaViolation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.message)
+    }
+}
+
+/// Validates extra headers against a JSON schema for each namespace, for example the FDSN
+/// reserved headers. Namespaces without a registered schema are not checked.
+///
+/// Supports the parts of JSON schema used by the FDSN schema: type, enum, const, properties,
+/// required, additionalProperties, items, minItems, maxItems, minimum, maximum,
+/// exclusiveMinimum, exclusiveMaximum, minLength, maxLength, pattern, the date-time format and
+/// local $ref into $defs or definitions. Registering a schema that uses any other keyword,
+/// like oneOf, anyOf, allOf or if, is an error, as is a pattern the regex crate cannot
+/// compile, for example one with lookaround, or a $ref that leads back to itself through
+/// other $refs, like `{"$ref": "#"}`.
+#[derive(Debug, Clone)]
+pub struct ExtraHeaderValidator {
+    schemas: HashMap<String, Value>,
+    /// Compiled regex for each pattern in the registered schemas.
+    patterns: HashMap<String, Regex>,
+}
+
+impl ExtraHeaderValidator {
+    /// Validator with the bundled schema for the FDSN namespace.
+    pub fn new() -> ExtraHeaderValidator {
+        let mut validator = ExtraHeaderValidator::empty();
+        let schema: Value = serde_json::from_str(FDSN_EXTRA_HEADERS_SCHEMA)
+            .expect("bundled FDSN schema is valid json");
+        let mut fdsn = schema["properties"][FDSN_EXTRA_HEADERS].clone();
+        // keep any local $ref targets resolvable from the namespace schema
+        for defs in ["$defs", "definitions"] {
+            if let (Some(d), Value::Object(f)) = (schema.get(defs), &mut fdsn) {
+                f.insert(String::from(defs), d.clone());
+            }
+        }
+        validator
+            .register(FDSN_EXTRA_HEADERS, fdsn)
+            .expect("bundled FDSN schema is supported");
+        validator
+    }
+
+    /// Validator with no schemas registered.
+    pub fn empty() -> ExtraHeaderValidator {
+        ExtraHeaderValidator {
+            schemas: HashMap::new(),
+            patterns: HashMap::new(),
+        }
+    }
+
+    /// Registers the schema for the value of a namespace, replacing any existing one. Fails if
+    /// the schema uses keywords the validator does not support or has a $ref cycle.
+    pub fn register(&mut self, namespace: &str, schema: Value) -> Result<(), MSeedError> {
+        match schema {
+            Value::Object(_) | Value::Bool(_) => {
+                let mut patterns = HashMap::new();
+                check_supported(&schema, "#", &mut patterns)
+                    .and_then(|_| check_ref_cycles(&schema, &schema))
+                    .map_err(|msg| {
+                        MSeedError::ExtraHeaderParse(format!("schema for {}: {}", namespace, msg))
+                    })?;
+                self.patterns.extend(patterns);
+                self.schemas.insert(String::from(namespace), schema);
+                Ok(())
+            }
+            _ => Err(MSeedError::ExtraHeaderParse(format!(
+                "schema for {} must be an object or boolean",
+                namespace
+            ))),
+        }
+    }
+
+    /// Registers a schema from a JSON string.
+    pub fn register_str(&mut self, namespace: &str, schema: &str) -> Result<(), MSeedError> {
+        self.register(namespace, serde_json::from_str(schema)?)
+    }
+
+    pub fn schema(&self, namespace: &str) -> Option<&Value> {
+        self.schemas.get(namespace)
+    }
+
+    /// All violations in the extra headers, empty if valid.
+    pub fn validate(&self, extra_headers: &Map<String, Value>) -> Vec<SchemaViolation> {
+        let mut violations = Vec::new();
+        let mut namespaces: Vec<&String> = extra_headers.keys().collect();
+        namespaces.sort();
+        for namespace in namespaces {
+            if let Some(schema) = self.schemas.get(namespace) {
+                let mut checker = Checker {
+                    root: schema,
+                    patterns: &self.patterns,
+                    namespace,
+                    violations: &mut violations,
+                };
+                checker.check(
+                    schema,
+                    &extra_headers[namespace],
+                    &format!("/{}", escape_pointer(namespace)),
+                );
+            }
+        }
+        violations
+    }
+
+    /// Validates the extra headers of the record, an ExtraHeaderSchema error lists all
+    /// violations.
+    pub fn validate_record(&self, record: &MSeed3Record) -> Result<(), MSeedError> {
+        let violations = self.validate(&record.extra_headers);
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(MSeedError::ExtraHeaderSchema(violations))
+        }
+    }
+
+    /// Parses the extra headers of the record, like parse_headers, and then validates them.
+    pub fn parse_headers(&self, raw_rec: UnparsedMSeed3Record) -> Result<MSeed3Record, MSeedError> {
+        let record = parse_headers(raw_rec)?;
+        self.validate_record(&record)?;
+        Ok(record)
+    }
+}
+
+impl Default for ExtraHeaderValidator {
+    fn default() -> Self {
+        ExtraHeaderValidator::new()
+    }
+}
+
+struct Checker<'a> {
+    root: &'a Value,
+    patterns: &'a HashMap<String, Regex>,
+    namespace: &'a str,
+    violations: &'a mut Vec<SchemaViolation>,
+}
+
+impl<'a> Checker<'a> {
+    fn violation(&mut self, path: &str, message: String) {
+        self.violations.push(SchemaViolation {
+            namespace: String::from(self.namespace),
+            path: String::from(path),
+            message,
+        });
+    }
+
+    fn check(&mut self, schema: &'a Value, value: &Value, path: &str) {
+        let schema = match schema {
+            Value::Bool(true) => return,
+            Value::Bool(false) => {
+                self.violation(path, String::from("not allowed by schema"));
+                return;
+            }
+            Value::Object(schema) => schema,
+            _ => return,
+        };
+        if let Some(Value::String(reference)) = schema.get("$ref") {
+            match self.resolve(reference) {
+                Some(target) => self.check(target, value, path),
+                None => self.violation(path, format!("unresolved schema $ref {}", reference)),
+            }
+        }
+        if let Some(types) = schema.get("type") {
+            let allowed: Vec<&str> = match types {
+                Value::String(t) => vec![t.as_str()],
+                Value::Array(ts) => ts.iter().filter_map(|t| t.as_str()).collect(),
+                _ => vec![],
+            };
+            if !allowed.is_empty() && !allowed.iter().any(|t| is_type(value, t)) {
+                self.violation(
+                    path,
+                    format!(
+                        "expected {} but was {}",
+                        allowed.join(" or "),
+                        type_name(value)
+                    ),
+                );
+                return;
+            }
+        }
+        if let Some(Value::Array(options)) = schema.get("enum") {
+            if !options.iter().any(|o| json_equal(o, value)) {
+                self.violation(
+                    path,
+                    format!("{} is not one of {}", value, Value::Array(options.clone())),
+                );
+            }
+        }
+        if let Some(expected) = schema.get("const") {
+            if !json_equal(expected, value) {
+                self.violation(path, format!("{} is not {}", value, expected));
+            }
+        }
+        match value {
+            Value::Object(map) => self.check_object(schema, map, path),
+            Value::Array(items) => self.check_array(schema, items, path),
+            Value::Number(n) => self.check_number(schema, n.as_f64().unwrap_or(f64::NAN), path),
+            Value::String(s) => self.check_string(schema, s, path),
+            _ => (),
+        }
+    }
+
+    fn check_object(
+        &mut self,
+        schema: &'a Map<String, Value>,
+        map: &Map<String, Value>,
+        path: &str,
+    ) {
+        if let Some(Value::Array(required)) = schema.get("required") {
+            for key in required.iter().filter_map(|k| k.as_str()) {
+                if !map.contains_key(key) {
+                    self.violation(path, format!("missing required key {}", key));
+                }
+            }
+        }
+        let properties = match schema.get("properties") {
+            Some(Value::Object(p)) => Some(p),
+            _ => None,
+        };
+        for (key, v) in map {
+            let child_path = format!("{}/{}", path, escape_pointer(key));
+            match properties.and_then(|p| p.get(key)) {
+                Some(property) => self.check(property, v, &child_path),
+                None => {
+                    if let Some(additional) = schema.get("additionalProperties") {
+                        if additional == &Value::Bool(false) {
+                            self.violation(&child_path, format!("unknown key {}", key));
+                        } else {
+                            self.check(additional, v, &child_path);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn check_array(&mut self, schema: &'a Map<String, Value>, items: &[Value], path: &str) {
+        if let Some(min) = schema.get("minItems").and_then(|m| m.as_u64()) {
+            if (items.len() as u64) < min {
+                self.violation(path, format!("{} items, fewer than {}", items.len(), min));
+            }
+        }
+        if let Some(max) = schema.get("maxItems").and_then(|m| m.as_u64()) {
+            if (items.len() as u64) > max {
+                self.violation(path, format!("{} items, more than {}", items.len(), max));
+            }
+        }
+        if let Some(item_schema) = schema.get("items") {
+            for (i, item) in items.iter().enumerate() {
+                self.check(item_schema, item, &format!("{}/{}", path, i));
+            }
+        }
+    }
+
+    fn check_number(&mut self, schema: &'a Map<String, Value>, n: f64, path: &str) {
+        let limit = |key: &str| schema.get(key).and_then(|v| v.as_f64());
+        if let Some(min) = limit("minimum") {
+            if n < min {
+                self.violation(path, format!("{} less than minimum {}", n, min));
+            }
+        }
+        if let Some(max) = limit("maximum") {
+            if n > max {
+                self.violation(path, format!("{} greater than maximum {}", n, max));
+            }
+        }
+        if let Some(min) = limit("exclusiveMinimum") {
+            if n <= min {
+                self.violation(path, format!("{} not greater than {}", n, min));
+            }
+        }
+        if let Some(max) = limit("exclusiveMaximum") {
+            if n >= max {
+                self.violation(path, format!("{} not less than {}", n, max));
+            }
+        }
+    }
+
+    fn check_string(&mut self, schema: &'a Map<String, Value>, s: &str, path: &str) {
+        let len = s.chars().count() as u64;
+        if let Some(min) = schema.get("minLength").and_then(|m| m.as_u64()) {
+            if len < min {
+                self.violation(path, format!("length {} shorter than {}", len, min));
+            }
+        }
+        if let Some(max) = schema.get("maxLength").and_then(|m| m.as_u64()) {
+            if len > max {
+                self.violation(path, format!("length {} longer than {}", len, max));
+            }
+        }
+        if let Some(Value::String(pattern)) = schema.get("pattern") {
+            match self.patterns.get(pattern) {
+                Some(re) if re.is_match(s) => (),
+                Some(_) => self.violation(path, format!("'{}' does not match {}", s, pattern)),
+                None => self.violation(path, format!("schema pattern {} not compiled", pattern)),
+            }
+        }
+        if schema.get("format").and_then(|f| f.as_str()) == Some("date-time")
+            && s.parse::<DateTime<Utc>>().is_err()
+        {
+            self.violation(path, format!("'{}' is not a date-time", s));
+        }
+    }
+
+    /// Resolves a local reference like #/$defs/name within the namespace schema.
+    fn resolve(&self, reference: &str) -> Option<&'a Value> {
+        let pointer = reference.strip_prefix('#')?;
+        self.root.pointer(pointer)
+    }
+}
+
+/// Checks that the schema, and all schemas nested in it, only use supported keywords, and
+/// compiles each pattern into patterns.
+fn check_supported(
+    schema: &Value,
+    path: &str,
+    patterns: &mut HashMap<String, Regex>,
+) -> Result<(), String> {
+    let schema = match schema {
+        Value::Object(schema) => schema,
+        Value::Bool(_) => return Ok(()),
+        _ => return Err(format!("{} must be an object or boolean", path)),
+    };
+    for (key, value) in schema {
+        let child_path = format!("{}/{}", path, escape_pointer(key));
+        match key.as_str() {
+            "properties" | "$defs" | "definitions" => match value {
+                Value::Object(children) => {
+                    for (name, child) in children {
+                        check_supported(
+                            child,
+                            &format!("{}/{}", child_path, escape_pointer(name)),
+                            patterns,
+                        )?;
+                    }
+                }
+                _ => return Err(format!("{} must be an object", child_path)),
+            },
+            "additionalProperties" | "items" => check_supported(value, &child_path, patterns)?,
+            "pattern" => {
+                let pattern = value
+                    .as_str()
+                    .ok_or_else(|| format!("{} must be a string", child_path))?;
+                let re = Regex::new(pattern)
+                    .map_err(|e| format!("unsupported pattern at {}: {}", child_path, e))?;
+                patterns.insert(String::from(pattern), re);
+            }
+            k if SUPPORTED_KEYWORDS.contains(&k) || ANNOTATION_KEYWORDS.contains(&k) => (),
+            k => return Err(format!("unsupported keyword {} at {}", k, path)),
+        }
+    }
+    Ok(())
+}
+
+/// Checks that following $ref from any schema nested in value never leads back to a schema
+/// already visited without first descending into the value being checked, which would
+/// recurse forever.
+fn check_ref_cycles(root: &Value, value: &Value) -> Result<(), String> {
+    match value {
+        Value::Object(map) => {
+            if let Some(Value::String(reference)) = map.get("$ref") {
+                let mut seen: Vec<&str> = Vec::new();
+                let mut next = Some(reference.as_str());
+                while let Some(reference) = next {
+                    if seen.contains(&reference) {
+                        return Err(format!("$ref cycle through {}", seen.join(", ")));
+                    }
+                    seen.push(reference);
+                    next = reference
+                        .strip_prefix('#')
+                        .and_then(|pointer| root.pointer(pointer))
+                        .and_then(|target| target.get("$ref"))
+                        .and_then(|r| r.as_str());
+                }
+            }
+            // values of these keywords are data, not schemas
+            map.iter()
+                .filter(|(k, _)| !matches!(k.as_str(), "enum" | "const" | "default" | "examples"))
+                .try_for_each(|(_, v)| check_ref_cycles(root, v))
+        }
+        Value::Array(items) => items.iter().try_for_each(|v| check_ref_cycles(root, v)),
+        _ => Ok(()),
+    }
+}
+
+fn is_type(value: &Value, t: &str) -> bool {
+    match t {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "boolean" => value.is_boolean(),
+        "null" => value.is_null(),
+        "number" => value.is_number(),
+        "integer" => match value {
+            Value::Number(n) => {
+                n.is_i64() || n.is_u64() || n.as_f64().is_some_and(|f| f.fract() == 0.0)
+            }
+            _ => false,
+        },
+        _ => true,
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+/// Equality where numbers compare by value, so 1 and 1.0 are equal.
+fn json_equal(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Number(x), Value::Number(y)) => x.as_f64() == y.as_f64(),
+        _ => a == b,
+    }
+}
+
+fn escape_pointer(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{DateTime, Utc};
+    use serde_json::json;
+
+    fn record_with(extra_headers: Value) -> MSeed3Record {
+        let start = "2014-11-28T12:00:09Z".parse::<DateTime<Utc>>().unwrap();
+        let mut rec = MSeed3Record::from_ints(start, 10.0, vec![1, 2, 3]);
+        if let Value::Object(map) = extra_headers {
+            rec.extra_headers = map;
+        }
+        rec
+    }
+
+    #[test]
+    fn valid_fdsn() -> Result<(), MSeedError> {
+        let validator = ExtraHeaderValidator::new();
+        let rec = record_with(json!({
+            "FDSN": {
+                "Time": {"Quality": 100, "Correction": -0.5},
+                "Event": {"Detection": [{"Type": "MURDOCK", "MEDSNR": [1, 2, 3, 4, 5, 6],
+                    "OnsetTime": "2022-01-01T00:00:00.5Z"}]},
+                "Flags": {"Spikes": true},
+                "Sequence": 3
+            },
+            "Other": {"anything": [1]}
+        }));
+        validator.validate_record(&rec)
+    }
+
+    #[test]
+    fn invalid_fdsn() {
+        let validator = ExtraHeaderValidator::new();
+        let rec = record_with(json!({
+            "FDSN": {
+                "Time": {"Quality": 101, "LeapSecond": 0.5},
+                "Event": {"Begin": "yes", "Detection": [{"MEDSNR": [1.5], "OnsetTime": "noon"}]},
+                "Flags": {"Spikes": 1},
+                "Sequence": -1
+            }
+        }));
+        let violations = validator.validate(&rec.extra_headers);
+        let paths: Vec<&str> = violations.iter().map(|v| v.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            vec![
+                "/FDSN/Event/Begin",
+                "/FDSN/Event/Detection/0/MEDSNR/0",
+                "/FDSN/Event/Detection/0/OnsetTime",
+                "/FDSN/Flags/Spikes",
+                "/FDSN/Sequence",
+                "/FDSN/Time/LeapSecond",
+                "/FDSN/Time/Quality",
+            ]
+        );
+        assert!(violations.iter().all(|v| v.namespace == "FDSN"));
+        match validator.validate_record(&rec) {
+            Err(MSeedError::ExtraHeaderSchema(v)) => assert_eq!(v, violations),
+            _ => panic!("expected schema error"),
+        }
+    }
+
+    #[test]
+    fn user_schema() -> Result<(), MSeedError> {
+        let mut validator = ExtraHeaderValidator::empty();
+        validator.register_str(
+            "ACME",
+            r##"{
+                "type": "object",
+                "required": ["Gain"],
+                "additionalProperties": false,
+                "properties": {
+                    "Gain": {"$ref": "#/$defs/positive"},
+                    "Site": {"type": "string", "pattern": "^[A-Z]+$"}
+                },
+                "$defs": {"positive": {"type": "number", "exclusiveMinimum": 0}}
+            }"##,
+        )?;
+        let ok =
+            record_with(json!({"ACME": {"Gain": 2.5, "Site": "ABC"}, "FDSN": {"Sequence": "x"}}));
+        validator.validate_record(&ok)?;
+        let bad = record_with(json!({"ACME": {"Gain": 0, "Site": "abc", "Extra": 1}}));
+        let violations = validator.validate(&bad.extra_headers);
+        assert_eq!(violations.len(), 3);
+        let missing = record_with(json!({"ACME": {}}));
+        assert_eq!(
+            validator.validate(&missing.extra_headers)[0].message,
+            "missing required key Gain"
+        );
+        assert!(validator.register("ACME", json!(3)).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn unsupported_keywords() {
+        let mut validator = ExtraHeaderValidator::empty();
+        for schema in [
+            json!({"oneOf": [{"type": "string"}, {"type": "number"}]}),
+            json!({"properties": {"Gain": {"anyOf": [{"minimum": 0}]}}}),
+            json!({"items": {"allOf": [{"type": "string"}]}}),
+            json!({"if": {"type": "string"}, "then": {"minLength": 1}}),
+            json!({"$defs": {"site": {"not": {"type": "null"}}}}),
+            json!({"properties": {"Site": {"type": "string", "pattern": "^(?=A)"}}}),
+        ] {
+            assert!(
+                matches!(
+                    validator.register("ACME", schema.clone()),
+                    Err(MSeedError::ExtraHeaderParse(_))
+                ),
+                "{}",
+                schema
+            );
+        }
+        assert!(validator.schema("ACME").is_none());
+    }
+
+    #[test]
+    fn ref_cycles() {
+        let mut validator = ExtraHeaderValidator::empty();
+        for schema in [
+            json!({"$ref": "#"}),
+            json!({
+                "properties": {"Gain": {"$ref": "#/$defs/a"}},
+                "$defs": {"a": {"$ref": "#/$defs/b"}, "b": {"$ref": "#/$defs/a"}}
+            }),
+        ] {
+            assert!(
+                matches!(
+                    validator.register("ACME", schema.clone()),
+                    Err(MSeedError::ExtraHeaderParse(_))
+                ),
+                "{}",
+                schema
+            );
+        }
+        // recursion through properties ends with the value
+        validator
+            .register(
+                "ACME",
+                json!({
+                    "type": "object",
+                    "properties": {"Name": {"type": "string"}, "Child": {"$ref": "#"}}
+                }),
+            )
+            .unwrap();
+        let rec = record_with(json!({"ACME": {"Child": {"Child": {"Name": 1}}}}));
+        let violations = validator.validate(&rec.extra_headers);
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].path, "/ACME/Child/Child/Name");
+    }
+
+    #[test]
+    fn bundled_schema() {
+        let schema: Value = serde_json::from_str(FDSN_EXTRA_HEADERS_SCHEMA).unwrap();
+        assert!(schema["description"]
+            .as_str()
+            .unwrap()
+            .contains(FDSN_EXTRA_HEADERS_SCHEMA_VERSION));
+        let validator = ExtraHeaderValidator::new();
+        assert_eq!(
+            validator.schema(FDSN_EXTRA_HEADERS),
+            Some(&schema["properties"]["FDSN"])
+        );
+    }
+}
@@ -0,0 +1,516 @@
+//! Typed structs for the reserved extra headers in the `FDSN` namespace. See
+//! <https://docs.fdsn.org/projects/miniseed3/en/latest/fdsn-reserved-headers.html>
+//!
+//! Every struct keeps keys it does not know about in `other`, so a record that is read,
+//! modified through these types and written again does not lose any extra headers. Fields
+//! the schema types as number are kept as a JSON Number, so integer and floating point
+//! values are written back as they were read, and explicit nulls in the existing headers
+//! are kept when writing.
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::map::Map;
+use serde_json::{Number, Value};
+
+use crate::mseed_error::MSeedError;
+use crate::record::{MSeed3Record, FDSN_EXTRA_HEADERS};
+
+/// All headers in the `FDSN` namespace.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(rename_all = "PascalCase")]
+pub struct FdsnHeaders {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub time: Option<FdsnTime>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub event: Option<FdsnEvent>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub calibration: Option<FdsnCalibration>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub clock: Option<FdsnClock>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub flags: Option<FdsnFlags>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data_quality: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub recenter: Option<FdsnRecenter>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sequence: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub provenance: Option<Vec<FdsnProvenance>>,
+    #[serde(flatten)]
+    pub other: Map<String, Value>,
+}
+
+/// `FDSN.Time`, timing quality and corrections.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(rename_all = "PascalCase")]
+pub struct FdsnTime {
+    /// Timing quality, 0 to 100 percent.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quality: Option<Number>,
+    /// Time correction in seconds that has been applied to the record start time.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub correction: Option<Number>,
+    /// A leap second occurs during the record, +1 inserted or -1 removed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub leap_second: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exception: Option<Vec<FdsnTimeException>>,
+    /// Maximum estimated timing error in seconds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_estimated_error: Option<Number>,
+    #[serde(flatten)]
+    pub other: Map<String, Value>,
+}
+
+/// An entry in `FDSN.Time.Exception`, equivalent to a miniSEED 2 blockette 500.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(rename_all = "PascalCase")]
+pub struct FdsnTimeException {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub time: Option<String>,
+    #[serde(rename = "VCOCorrection", skip_serializing_if = "Option::is_none")]
+    pub vco_correction: Option<Number>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reception_quality: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub count: Option<u64>,
+    #[serde(rename = "Type", skip_serializing_if = "Option::is_none")]
+    pub exception_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub clock_status: Option<String>,
+    #[serde(flatten)]
+    pub other: Map<String, Value>,
+}
+
+/// `FDSN.Event`, event flags and detections.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(rename_all = "PascalCase")]
+pub struct FdsnEvent {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub begin: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub in_progress: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detection: Option<Vec<FdsnDetection>>,
+    #[serde(flatten)]
+    pub other: Map<String, Value>,
+}
+
+/// An entry in `FDSN.Event.Detection`, equivalent to miniSEED 2 blockettes 200 and 201.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(rename_all = "PascalCase")]
+pub struct FdsnDetection {
+    /// Detector type, usually MURDOCK or GENERIC.
+    #[serde(rename = "Type", skip_serializing_if = "Option::is_none")]
+    pub detection_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signal_amplitude: Option<Number>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signal_period: Option<Number>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub background_estimate: Option<Number>,
+    /// Detection wave, DILATATION or COMPRESSION.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub wave: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub units: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub onset_time: Option<String>,
+    /// Signal to noise ratio values for the Murdock detector.
+    #[serde(rename = "MEDSNR", skip_serializing_if = "Option::is_none")]
+    pub med_snr: Option<Vec<i64>>,
+    #[serde(rename = "MEDLookback", skip_serializing_if = "Option::is_none")]
+    pub med_lookback: Option<i64>,
+    #[serde(rename = "MEDPickAlgorithm", skip_serializing_if = "Option::is_none")]
+    pub med_pick_algorithm: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detector: Option<String>,
+    #[serde(flatten)]
+    pub other: Map<String, Value>,
+}
+
+/// `FDSN.Calibration`, calibrations performed during the record.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(rename_all = "PascalCase")]
+pub struct FdsnCalibration {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sequence: Option<Vec<FdsnCalibrationSequence>>,
+    #[serde(flatten)]
+    pub other: Map<String, Value>,
+}
+
+/// An entry in `FDSN.Calibration.Sequence`, equivalent to miniSEED 2 blockettes 300 to 395.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(rename_all = "PascalCase")]
+pub struct FdsnCalibrationSequence {
+    /// Calibration type, STEP, SINE, PSEUDORANDOM or GENERIC.
+    #[serde(rename = "Type", skip_serializing_if = "Option::is_none")]
+    pub calibration_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub begin_time: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end_time: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub steps: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub step_first_pulse_positive: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub step_alternate_sign: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub step_duration: Option<Number>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub step_between: Option<Number>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub amplitude: Option<Number>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub input_units: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub amplitude_range: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration: Option<Number>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sine_period: Option<Number>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reference_amplitude: Option<Number>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub coupling: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rolloff: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub noise: Option<String>,
+    #[serde(flatten)]
+    pub other: Map<String, Value>,
+}
+
+/// `FDSN.Clock`, the timing system.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(rename_all = "PascalCase")]
+pub struct FdsnClock {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub locked: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(flatten)]
+    pub other: Map<String, Value>,
+}
+
+/// `FDSN.Flags`, data quality and I/O flags carried over from miniSEED 2.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(rename_all = "PascalCase")]
+pub struct FdsnFlags {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub station_volume_parity_error: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub long_record_read: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub short_record_read: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start_of_time_series: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end_of_time_series: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub amplifier_saturation: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub digitizer_clipping: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub spikes: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub glitches: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub missing_data: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub telemetry_sync_error: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub filter_charging: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mass_position_offscale: Option<bool>,
+    #[serde(flatten)]
+    pub other: Map<String, Value>,
+}
+
+/// `FDSN.Recenter`, mass or gimbal recentering during the record.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(rename_all = "PascalCase")]
+pub struct FdsnRecenter {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sequence: Option<Vec<FdsnRecenterSequence>>,
+    #[serde(flatten)]
+    pub other: Map<String, Value>,
+}
+
+/// An entry in `FDSN.Recenter.Sequence`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(rename_all = "PascalCase")]
+pub struct FdsnRecenterSequence {
+    /// Recenter type, MASS, GIMBAL or another value.
+    #[serde(rename = "Type", skip_serializing_if = "Option::is_none")]
+    pub recenter_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub begin_time: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end_time: Option<String>,
+    /// AUTOMATIC or MANUAL.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trigger: Option<String>,
+    #[serde(flatten)]
+    pub other: Map<String, Value>,
+}
+
+/// An entry in `FDSN.Provenance`, describing software that created or modified the record.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(rename_all = "PascalCase")]
+pub struct FdsnProvenance {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub time: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(flatten)]
+    pub other: Map<String, Value>,
+}
+
+impl MSeed3Record {
+    /// All headers in the FDSN namespace as typed structs, default if there are none.
+    pub fn get_fdsn_typed_headers(&self) -> Result<FdsnHeaders, MSeedError> {
+        match self.get_fdsn_headers() {
+            Some(map) => Ok(serde_json::from_value(Value::Object(map.clone()))?),
+            None => Ok(FdsnHeaders::default()),
+        }
+    }
+
+    /// Replaces all headers in the FDSN namespace, removing the namespace if empty.
+    pub fn set_fdsn_typed_headers(&mut self, headers: &FdsnHeaders) -> Result<(), MSeedError> {
+        let mut value = serde_json::to_value(headers)?;
+        if let Some(old) = self.get_fdsn_headers() {
+            keep_nulls(&Value::Object(old.clone()), &mut value);
+        }
+        match value {
+            Value::Object(map) if map.is_empty() => {
+                self.extra_headers.remove(FDSN_EXTRA_HEADERS);
+            }
+            Value::Object(map) => {
+                *self.mut_fdsn_headers() = map;
+            }
+            v => return Err(MSeedError::ExtraHeaderNotObject(v)),
+        }
+        Ok(())
+    }
+
+    pub fn get_fdsn_time(&self) -> Result<Option<FdsnTime>, MSeedError> {
+        self.get_fdsn_header("Time")
+    }
+
+    pub fn set_fdsn_time(&mut self, time: &FdsnTime) -> Result<(), MSeedError> {
+        self.set_fdsn_header("Time", time)
+    }
+
+    pub fn get_fdsn_event(&self) -> Result<Option<FdsnEvent>, MSeedError> {
+        self.get_fdsn_header("Event")
+    }
+
+    pub fn set_fdsn_event(&mut self, event: &FdsnEvent) -> Result<(), MSeedError> {
+        self.set_fdsn_header("Event", event)
+    }
+
+    pub fn get_fdsn_calibration(&self) -> Result<Option<FdsnCalibration>, MSeedError> {
+        self.get_fdsn_header("Calibration")
+    }
+
+    pub fn set_fdsn_calibration(&mut self, cal: &FdsnCalibration) -> Result<(), MSeedError> {
+        self.set_fdsn_header("Calibration", cal)
+    }
+
+    pub fn get_fdsn_clock(&self) -> Result<Option<FdsnClock>, MSeedError> {
+        self.get_fdsn_header("Clock")
+    }
+
+    pub fn set_fdsn_clock(&mut self, clock: &FdsnClock) -> Result<(), MSeedError> {
+        self.set_fdsn_header("Clock", clock)
+    }
+
+    pub fn get_fdsn_flags(&self) -> Result<Option<FdsnFlags>, MSeedError> {
+        self.get_fdsn_header("Flags")
+    }
+
+    pub fn set_fdsn_flags(&mut self, flags: &FdsnFlags) -> Result<(), MSeedError> {
+        self.set_fdsn_header("Flags", flags)
+    }
+
+    pub fn get_fdsn_data_quality(&self) -> Result<Option<String>, MSeedError> {
+        self.get_fdsn_header("DataQuality")
+    }
+
+    pub fn set_fdsn_data_quality(&mut self, quality: &str) -> Result<(), MSeedError> {
+        self.set_fdsn_header("DataQuality", &quality)
+    }
+
+    pub fn get_fdsn_recenter(&self) -> Result<Option<FdsnRecenter>, MSeedError> {
+        self.get_fdsn_header("Recenter")
+    }
+
+    pub fn set_fdsn_recenter(&mut self, recenter: &FdsnRecenter) -> Result<(), MSeedError> {
+        self.set_fdsn_header("Recenter", recenter)
+    }
+
+    pub fn get_fdsn_sequence(&self) -> Result<Option<u64>, MSeedError> {
+        self.get_fdsn_header("Sequence")
+    }
+
+    pub fn set_fdsn_sequence(&mut self, sequence: u64) -> Result<(), MSeedError> {
+        self.set_fdsn_header("Sequence", &sequence)
+    }
+
+    pub fn get_fdsn_provenance(&self) -> Result<Option<Vec<FdsnProvenance>>, MSeedError> {
+        self.get_fdsn_header("Provenance")
+    }
+
+    pub fn set_fdsn_provenance(&mut self, provenance: &[FdsnProvenance]) -> Result<(), MSeedError> {
+        self.set_fdsn_header("Provenance", &provenance)
+    }
+
+    fn get_fdsn_header<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>, MSeedError> {
+        match self.get_fdsn_headers().and_then(|fdsn| fdsn.get(key)) {
+            Some(v) => Ok(Some(serde_json::from_value(v.clone())?)),
+            None => Ok(None),
+        }
+    }
+
+    fn set_fdsn_header<T: Serialize + ?Sized>(
+        &mut self,
+        key: &str,
+        value: &T,
+    ) -> Result<(), MSeedError> {
+        let mut v = serde_json::to_value(value)?;
+        if let Some(old) = self.get_fdsn_headers().and_then(|fdsn| fdsn.get(key)) {
+            keep_nulls(old, &mut v);
+        }
+        self.mut_fdsn_headers().insert(String::from(key), v);
+        Ok(())
+    }
+}
+
+/// Puts back explicit nulls from the old value for keys missing from the new one. The typed
+/// structs read a null as None and skip None when writing, so without this a null would be
+/// lost on a round trip.
+fn keep_nulls(old: &Value, new: &mut Value) {
+    match (old, new) {
+        (Value::Object(old), Value::Object(new)) => {
+            for (key, old_value) in old {
+                match new.get_mut(key) {
+                    Some(new_value) => keep_nulls(old_value, new_value),
+                    None if old_value.is_null() => {
+                        new.insert(key.clone(), Value::Null);
+                    }
+                    None => (),
+                }
+            }
+        }
+        (Value::Array(old), Value::Array(new)) => {
+            for (old_value, new_value) in old.iter().zip(new.iter_mut()) {
+                keep_nulls(old_value, new_value);
+            }
+        }
+        _ => (),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{DateTime, Utc};
+    use serde_json::json;
+
+    #[test]
+    fn round_trip_unknown_keys() -> Result<(), MSeedError> {
+        let start = "2014-11-28T12:00:09Z".parse::<DateTime<Utc>>()?;
+        let mut rec = MSeed3Record::from_ints(start, 10.0, vec![1, 2, 3]);
+        let fdsn = json!({
+            "Time": {"Quality": 80, "Correction": 0.25, "Future": [1, 2]},
+            "Event": {"Begin": true, "Detection": [{"Type": "MURDOCK", "MEDSNR": [1, 2, 3, 4, 5, 6]}]},
+            "Clock": {"Model": "Acme GPS"},
+            "Sequence": 12,
+            "Other": "kept"
+        });
+        rec.extra_headers.insert(String::from("FDSN"), fdsn.clone());
+        let typed = rec.get_fdsn_typed_headers()?;
+        let time = typed.time.as_ref().unwrap();
+        assert_eq!(time.quality, Some(Number::from(80)));
+        assert_eq!(time.other["Future"], json!([1, 2]));
+        assert_eq!(typed.sequence, Some(12));
+        let detection = &typed.event.as_ref().unwrap().detection.as_ref().unwrap()[0];
+        assert_eq!(detection.med_snr, Some(vec![1, 2, 3, 4, 5, 6]));
+        assert_eq!(typed.other["Other"], json!("kept"));
+
+        rec.set_fdsn_typed_headers(&typed)?;
+        assert_eq!(rec.extra_headers["FDSN"], fdsn);
+        Ok(())
+    }
+
+    #[test]
+    fn round_trip_numbers_and_nulls() -> Result<(), MSeedError> {
+        let start = "2014-11-28T12:00:09Z".parse::<DateTime<Utc>>()?;
+        let mut rec = MSeed3Record::from_ints(start, 10.0, vec![1, 2, 3]);
+        let fdsn = json!({
+            "Time": {"Quality": 99.5, "Correction": 1, "MaxEstimatedError": 2.0, "LeapSecond": null},
+            "Event": {"Detection": [{"SignalAmplitude": 80, "SignalPeriod": 0.5, "MEDSNR": [-1, 300], "Units": null}]},
+            "Calibration": {"Sequence": [{"Amplitude": 1, "Steps": 3, "Duration": 1.0}]},
+            "Clock": null
+        });
+        rec.extra_headers.insert(String::from("FDSN"), fdsn.clone());
+        let typed = rec.get_fdsn_typed_headers()?;
+        let time = typed.time.as_ref().unwrap();
+        assert_eq!(time.quality.as_ref().and_then(Number::as_f64), Some(99.5));
+        assert_eq!(time.leap_second, None);
+        let detection = &typed.event.as_ref().unwrap().detection.as_ref().unwrap()[0];
+        assert_eq!(detection.med_snr, Some(vec![-1, 300]));
+
+        rec.set_fdsn_typed_headers(&typed)?;
+        assert_eq!(rec.extra_headers["FDSN"], fdsn);
+        // integers stay integers and floats stay floats when written
+        let written = rec.extra_headers["FDSN"].to_string();
+        assert!(written.contains(r#""Correction":1,"#), "{}", written);
+        assert!(
+            written.contains(r#""MaxEstimatedError":2.0"#),
+            "{}",
+            written
+        );
+
+        let time = rec.get_fdsn_time()?.unwrap();
+        rec.set_fdsn_time(&time)?;
+        assert_eq!(rec.extra_headers["FDSN"], fdsn);
+        Ok(())
+    }
+
+    #[test]
+    fn typed_setters() -> Result<(), MSeedError> {
+        let start = "2014-11-28T12:00:09Z".parse::<DateTime<Utc>>()?;
+        let mut rec = MSeed3Record::from_ints(start, 10.0, vec![1, 2, 3]);
+        assert_eq!(rec.get_fdsn_time()?, None);
+        let time = FdsnTime {
+            leap_second: Some(1),
+            max_estimated_error: Number::from_f64(0.001),
+            ..Default::default()
+        };
+        rec.set_fdsn_time(&time)?;
+        rec.set_fdsn_sequence(5)?;
+        rec.set_fdsn_data_quality("D")?;
+        assert_eq!(
+            rec.extra_headers["FDSN"],
+            json!({"Time": {"LeapSecond": 1, "MaxEstimatedError": 0.001}, "Sequence": 5, "DataQuality": "D"})
+        );
+        assert_eq!(rec.get_fdsn_time()?, Some(time));
+        assert_eq!(rec.get_fdsn_sequence()?, Some(5));
+
+        rec.mut_fdsn_headers()
+            .insert(String::from("Sequence"), json!("x"));
+        assert!(rec.get_fdsn_sequence().is_err());
+        rec.set_fdsn_typed_headers(&FdsnHeaders::default())?;
+        assert!(rec.extra_headers.is_empty());
+        Ok(())
+    }
+}
@@ -3,6 +3,7 @@ use lazy_static::lazy_static;
 use regex::{Captures, Regex};
 use serde::de::{self, Visitor};
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::cmp::Ordering;
 use std::convert::TryFrom;
 use std::fmt;
 
@@ -23,7 +24,9 @@ lazy_static! {
 
 pub const PREFIX: &str = "FDSN:";
 
-#[derive(Debug, Clone)]
+/// Ordered by the FDSN components, see FdsnSourceIdentifier, with Raw identifiers after
+/// all FDSN ones and ordered by string.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum SourceIdentifier {
     Raw(String),
     Fdsn(FdsnSourceIdentifier),
@@ -44,6 +47,23 @@ impl SourceIdentifier {
     }
 }
 
+impl Ord for SourceIdentifier {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (SourceIdentifier::Fdsn(a), SourceIdentifier::Fdsn(b)) => a.cmp(b),
+            (SourceIdentifier::Raw(a), SourceIdentifier::Raw(b)) => a.cmp(b),
+            (SourceIdentifier::Fdsn(_), SourceIdentifier::Raw(_)) => Ordering::Less,
+            (SourceIdentifier::Raw(_), SourceIdentifier::Fdsn(_)) => Ordering::Greater,
+        }
+    }
+}
+
+impl PartialOrd for SourceIdentifier {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
 impl From<String> for SourceIdentifier {
     fn from(s: String) -> Self {
         let sid = FdsnSourceIdentifier::parse(&s);
@@ -95,7 +115,9 @@ impl Serialize for SourceIdentifier {
 
 /// An FDSN Source Identifier string parsed into its component parts
 /// See the specification at <http://docs.fdsn.org/projects/source-identifiers/en/v1.0/index.html>
-#[derive(Debug, Clone)]
+///
+/// Ordered by network, station, location, band, source and subsource.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct FdsnSourceIdentifier {
     pub network: String,
     pub station: String,
@@ -147,6 +169,92 @@ impl FdsnSourceIdentifier {
         Ok(sid)
     }
 
+    /// Creates an identifier from SEED network, station, location and channel codes. Codes
+    /// are trimmed of the space padding used in SEED, and a location of `--`, as used in
+    /// requests for an empty location, becomes empty. A three character channel code is
+    /// split into single character band, source and subsource. A channel code with
+    /// underscores, like `B_HH_Z`, gives the three parts directly, and any other channel
+    /// code is used as the source with empty band and subsource.
+    pub fn from_nslc(
+        network: &str,
+        station: &str,
+        location: &str,
+        channel: &str,
+    ) -> Result<FdsnSourceIdentifier, MSeedError> {
+        let location = match location.trim() {
+            "--" => "",
+            loc => loc,
+        };
+        for (code, name) in [
+            (network, "network"),
+            (station, "station"),
+            (location, "location"),
+        ] {
+            if code.contains('_') {
+                return Err(MSeedError::IdentifierParse(
+                    code.to_string(),
+                    String::from(name),
+                ));
+            }
+        }
+        let channel = channel.trim();
+        let parts: Vec<&str> = channel.split('_').collect();
+        let (band, source, subsource) = if parts.len() == 3 {
+            (
+                parts[0].to_string(),
+                parts[1].to_string(),
+                parts[2].to_string(),
+            )
+        } else if channel.chars().count() == 3 {
+            let chars: Vec<String> = channel.chars().map(String::from).collect();
+            (chars[0].clone(), chars[1].clone(), chars[2].clone())
+        } else {
+            (String::new(), channel.to_string(), String::new())
+        };
+        let sid = format!(
+            "{}{}_{}_{}_{}_{}_{}",
+            PREFIX,
+            network.trim(),
+            station.trim(),
+            location,
+            band,
+            source,
+            subsource
+        );
+        FdsnSourceIdentifier::parse(&sid)
+    }
+
+    /// The SEED channel code, either the single character band, source and subsource or,
+    /// when band and subsource are empty, a source of up to three characters. Errors if
+    /// the identifier has no three character channel code.
+    pub fn channel_code(&self) -> Result<String, MSeedError> {
+        let single = |s: &str| s.chars().count() == 1;
+        if single(&self.band) && single(&self.source) && single(&self.subsource) {
+            Ok(format!("{}{}{}", self.band, self.source, self.subsource))
+        } else if self.band.is_empty()
+            && self.subsource.is_empty()
+            && self.source.chars().count() <= 3
+        {
+            Ok(self.source.clone())
+        } else {
+            Err(MSeedError::IdentifierParse(
+                self.to_string(),
+                String::from("SEED channel code"),
+            ))
+        }
+    }
+
+    /// The SEED network, station, location and channel codes, see channel_code. Codes
+    /// are not checked against the shorter SEED 2 field lengths.
+    pub fn to_nslc(&self) -> Result<(String, String, String, String), MSeedError> {
+        Ok((
+            self.network.clone(),
+            self.station.clone(),
+            self.location.clone(),
+            self.channel_code()?,
+        ))
+    }
+
     pub fn create_fake_channel() -> FdsnSourceIdentifier {
         FdsnSourceIdentifier {
             network: String::from("XX"),
@@ -231,6 +339,67 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn ordering() {
+        let mut ids: Vec<SourceIdentifier> = [
+            "XX.RAW",
+            "FDSN:IU_ANMO_10_B_H_Z",
+            "FDSN:CO_JSC_00_H_H_Z",
+            "FDSN:IU_ANMO_00_L_H_Z",
+            "FDSN:IU_ANMO_00_B_H_Z",
+            "AA.RAW",
+        ]
+        .iter()
+        .map(|s| SourceIdentifier::from(*s))
+        .collect();
+        ids.sort();
+        let sorted: Vec<String> = ids.iter().map(|id| id.to_string()).collect();
+        assert_eq!(
+            sorted,
+            [
+                "FDSN:CO_JSC_00_H_H_Z",
+                "FDSN:IU_ANMO_00_B_H_Z",
+                "FDSN:IU_ANMO_00_L_H_Z",
+                "FDSN:IU_ANMO_10_B_H_Z",
+                "AA.RAW",
+                "XX.RAW",
+            ]
+        );
+        assert_eq!(ids[1], SourceIdentifier::from("FDSN:IU_ANMO_00_B_H_Z"));
+        assert_ne!(ids[1], ids[2]);
+        let set: std::collections::HashSet<SourceIdentifier> =
+            ids.iter().chain(ids.iter()).cloned().collect();
+        assert_eq!(set.len(), ids.len());
+    }
+
+    #[test]
+    fn nslc() -> Result<(), MSeedError> {
+        let sid = FdsnSourceIdentifier::from_nslc("IU", "ANMO", "00", "BHZ")?;
+        assert_eq!(sid.to_string(), "FDSN:IU_ANMO_00_B_H_Z");
+        assert_eq!(
+            sid.to_nslc()?,
+            (
+                String::from("IU"),
+                String::from("ANMO"),
+                String::from("00"),
+                String::from("BHZ")
+            )
+        );
+        let sid = FdsnSourceIdentifier::from_nslc("IU ", "ANMO ", "--", "LHZ")?;
+        assert_eq!(sid.to_string(), "FDSN:IU_ANMO__L_H_Z");
+        let sid = FdsnSourceIdentifier::from_nslc("IU", "ANMO", "", "HZ")?;
+        assert_eq!(sid.to_string(), "FDSN:IU_ANMO___HZ_");
+        assert_eq!(sid.channel_code()?, "HZ");
+        let sid = FdsnSourceIdentifier::from_nslc("XX", "TEST", "", "B_HH_Z")?;
+        assert_eq!(sid.source, "HH");
+        assert!(sid.channel_code().is_err());
+
+        assert!(FdsnSourceIdentifier::from_nslc("IU", "ANMO", "00", "").is_err());
+        assert!(FdsnSourceIdentifier::from_nslc("iu", "ANMO", "00", "BHZ").is_err());
+        assert!(FdsnSourceIdentifier::from_nslc("IU", "AN_MO", "00", "BHZ").is_err());
+        Ok(())
+    }
+
     #[test]
     fn calc_len() -> Result<(), MSeedError> {
         let id = String::from("FDSN:IU_COLA_00_B_H_Z");
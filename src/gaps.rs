@@ -0,0 +1,265 @@
+use chrono::{DateTime, Duration, Utc};
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::fdsn_source_identifier::SourceIdentifier;
+use crate::header::{sample_offset, MSeed3Header};
+use crate::reader::MSeed3RecordSummary;
+use crate::record::MSeed3Record;
+
+/// Default tolerance, as a fraction of the sample period, for a time difference between
+/// records to be reported.
+pub const DEFAULT_GAP_TOLERANCE: f64 = 0.5;
+
+/// A gap or overlap between a record and the earlier records of a channel.
+#[derive(Debug, Clone)]
+pub struct GapOverlap {
+    pub identifier: SourceIdentifier,
+    pub publication_version: u8,
+    /// Time of the last sample before the gap or overlap.
+    pub last_sample: DateTime<Utc>,
+    /// Time of the first sample after the gap or overlap.
+    pub next_sample: DateTime<Utc>,
+    /// Difference between the next sample and the time it was expected, one sample period
+    /// after the last sample. Positive for a gap and negative for an overlap. An overlap is
+    /// at most the length of the overlapping record, as for msi.
+    pub duration: Duration,
+    /// Duration as a number of sample periods, negative for an overlap.
+    pub samples: f64,
+}
+
+impl GapOverlap {
+    pub fn is_gap(&self) -> bool {
+        self.duration > Duration::zero()
+    }
+
+    pub fn is_overlap(&self) -> bool {
+        self.duration < Duration::zero()
+    }
+}
+
+impl fmt::Display for GapOverlap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let seconds = self.duration.num_nanoseconds().unwrap_or(i64::MAX) as f64 / 1e9;
+        write!(
+            f,
+            "{} {:>3} {} {} {:>12.6}s {:>10.1}",
+            self.identifier,
+            self.publication_version,
+            self.last_sample.format("%Y-%m-%dT%H:%M:%S%.6fZ"),
+            self.next_sample.format("%Y-%m-%dT%H:%M:%S%.6fZ"),
+            seconds,
+            self.samples
+        )
+    }
+}
+
+/// Gaps and overlaps found across a set of records, similar to `msi -G` or `dataselect -G`.
+/// Records are grouped by identifier and publication version, and sorted by start time
+/// within each group, so need not be in order. Each record is compared with the latest end
+/// time of the records before it, so a record contained in, or a duplicate of, an earlier
+/// one is an overlap and does not hide a following gap.
+#[derive(Debug, Clone, Default)]
+pub struct GapReport {
+    /// Sorted by identifier, publication version and then time.
+    pub entries: Vec<GapOverlap>,
+}
+
+impl GapReport {
+    pub fn gaps(&self) -> impl Iterator<Item = &GapOverlap> {
+        self.entries.iter().filter(|g| g.is_gap())
+    }
+
+    pub fn overlaps(&self) -> impl Iterator<Item = &GapOverlap> {
+        self.entries.iter().filter(|g| g.is_overlap())
+    }
+}
+
+impl fmt::Display for GapReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "Source, Pub Version, Last Sample, Next Sample, Gap, Samples"
+        )?;
+        for entry in &self.entries {
+            writeln!(f, "{}", entry)?;
+        }
+        write!(
+            f,
+            "Total: {} gap(s), {} overlap(s)",
+            self.gaps().count(),
+            self.overlaps().count()
+        )
+    }
+}
+
+/// Finds gaps and overlaps using only the record headers, start time, num_samples and
+/// sample_rate_period, so the data is never decoded. Records without samples or with
+/// a zero sample rate are ignored.
+#[derive(Debug, Clone)]
+pub struct GapDetector {
+    /// Time differences up to this fraction of the sample period are not reported.
+    pub tolerance: f64,
+}
+
+impl GapDetector {
+    pub fn new(tolerance: f64) -> GapDetector {
+        GapDetector { tolerance }
+    }
+
+    pub fn report<'a, I>(&self, records: I) -> GapReport
+    where
+        I: IntoIterator<Item = &'a MSeed3Record>,
+    {
+        self.report_headers(records.into_iter().map(|r| (&r.identifier, &r.header)))
+    }
+
+    /// Report from header summaries, for example from a MSeed3SummaryReader scan.
+    pub fn report_summaries<'a, I>(&self, summaries: I) -> GapReport
+    where
+        I: IntoIterator<Item = &'a MSeed3RecordSummary>,
+    {
+        self.report_headers(summaries.into_iter().map(|s| (&s.identifier, &s.header)))
+    }
+
+    pub fn report_headers<'a, I>(&self, headers: I) -> GapReport
+    where
+        I: IntoIterator<Item = (&'a SourceIdentifier, &'a MSeed3Header)>,
+    {
+        // (start, num_samples, sample_rate_period) for each record, per channel
+        type Span = (DateTime<Utc>, usize, f64);
+        let mut channels: BTreeMap<(&SourceIdentifier, u8), Vec<Span>> = BTreeMap::new();
+        for (identifier, header) in headers {
+            if header.num_samples == 0 || header.sample_rate_period == 0.0 {
+                continue;
+            }
+            channels
+                .entry((identifier, header.publication_version))
+                .or_default()
+                .push((
+                    header.get_start_as_utc(),
+                    header.num_samples as usize,
+                    header.sample_rate_period,
+                ));
+        }
+        let mut entries = Vec::new();
+        for ((identifier, publication_version), mut spans) in channels {
+            spans.sort_by_key(|span| span.0);
+            // end time and sample_rate_period of the record reaching furthest so far
+            let mut furthest: Option<(DateTime<Utc>, f64)> = None;
+            for (next_sample, num_samples, next_rate) in spans {
+                let end = next_sample + sample_offset(next_rate, num_samples);
+                let (expected, rate) = match furthest {
+                    Some(f) => f,
+                    None => {
+                        furthest = Some((end, next_rate));
+                        continue;
+                    }
+                };
+                if end > expected {
+                    furthest = Some((end, next_rate));
+                }
+                let period = sample_offset(rate, 1);
+                let duration = (next_sample - expected).max(next_sample - end);
+                let period_ns = period.num_nanoseconds().unwrap_or(i64::MAX) as f64;
+                let samples = duration.num_nanoseconds().unwrap_or(i64::MAX) as f64 / period_ns;
+                if samples.abs() <= self.tolerance {
+                    continue;
+                }
+                entries.push(GapOverlap {
+                    identifier: identifier.clone(),
+                    publication_version,
+                    last_sample: expected - period,
+                    next_sample,
+                    duration,
+                    samples,
+                });
+            }
+        }
+        GapReport { entries }
+    }
+}
+
+impl Default for GapDetector {
+    fn default() -> Self {
+        GapDetector::new(DEFAULT_GAP_TOLERANCE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gaps_and_overlaps() {
+        let start = "2020-01-01T00:00:00Z".parse::<DateTime<Utc>>().unwrap();
+        let ms = Duration::milliseconds;
+        let mut other = MSeed3Record::from_ints(start, 10.0, vec![1, 2, 3]);
+        other.identifier = SourceIdentifier::from("FDSN:XX_OTHER_00_B_H_Z");
+        let mut republished = MSeed3Record::from_ints(start + ms(300), 10.0, vec![1]);
+        republished.header.publication_version = 2;
+        let records = vec![
+            // out of order, after a 1 second gap
+            MSeed3Record::from_ints(start + ms(1500), 10.0, vec![1, 2]),
+            MSeed3Record::from_ints(start, 10.0, vec![1, 2, 3]),
+            // within tolerance
+            MSeed3Record::from_ints(start + ms(320), 10.0, vec![1, 2]),
+            // overlaps previous by one sample
+            MSeed3Record::from_ints(start + ms(1600), 10.0, vec![1, 2, 3]),
+            other,
+            republished,
+        ];
+        let report = GapDetector::default().report(&records);
+        assert_eq!(report.entries.len(), 2);
+        let gap = &report.entries[0];
+        assert!(gap.is_gap());
+        assert_eq!(gap.last_sample, start + ms(420));
+        assert_eq!(gap.duration, ms(980));
+        assert!((gap.samples - 9.8).abs() < 1e-9);
+        let overlap = &report.entries[1];
+        assert!(overlap.is_overlap());
+        assert_eq!(overlap.next_sample, start + ms(1600));
+        assert!((overlap.samples + 1.0).abs() < 1e-9);
+        assert_eq!(report.gaps().count(), 1);
+
+        // tighter tolerance reports the small gap too
+        let report = GapDetector::new(0.1).report(&records);
+        assert_eq!(report.entries.len(), 3);
+        assert!(report
+            .to_string()
+            .ends_with("Total: 2 gap(s), 1 overlap(s)"));
+    }
+
+    #[test]
+    fn contained_and_duplicate() {
+        let start = "2020-01-01T00:00:00Z".parse::<DateTime<Utc>>().unwrap();
+        let ms = Duration::milliseconds;
+        let long = MSeed3Record::from_ints(start, 10.0, (0..20).collect());
+        let records = vec![
+            long.clone(),
+            // contained in the first, ending well before it
+            MSeed3Record::from_ints(start + ms(500), 10.0, vec![1, 2]),
+            long,
+            // 1 second gap after the end of the first, not after the contained record
+            MSeed3Record::from_ints(start + ms(3000), 10.0, vec![1, 2]),
+        ];
+        let report = GapDetector::default().report(&records);
+        assert_eq!(report.entries.len(), 3, "{}", report);
+        // sorted by start, so the duplicate comes before the contained record
+        let duplicate = &report.entries[0];
+        assert_eq!(duplicate.next_sample, start);
+        assert_eq!(duplicate.duration, ms(-2000));
+        let contained = &report.entries[1];
+        assert_eq!(contained.next_sample, start + ms(500));
+        assert_eq!(contained.last_sample, start + ms(1900));
+        assert_eq!(contained.duration, ms(-200));
+        assert!((contained.samples + 2.0).abs() < 1e-9);
+        let gap = &report.entries[2];
+        assert!(gap.is_gap());
+        assert_eq!(gap.last_sample, start + ms(1900));
+        assert_eq!(gap.duration, ms(1000));
+        assert!(report
+            .to_string()
+            .ends_with("Total: 1 gap(s), 2 overlap(s)"));
+    }
+}
@@ -1,6 +1,6 @@
 use byteorder::{LittleEndian, WriteBytesExt};
 use chrono::prelude::*;
-use chrono::Utc;
+use chrono::{Duration, Utc};
 use serde::{Deserialize, Serialize};
 use std::convert::{TryFrom, TryInto};
 use std::fmt;
@@ -8,6 +8,7 @@ use std::io::prelude::*;
 use std::io::BufWriter;
 
 use crate::data_encoding::DataEncoding;
+use crate::header_flags::HeaderFlags;
 use crate::mseed_error::MSeedError;
 
 /// Size in bytes of the fixed header. This does not include the identifier, extra headers, or data.
@@ -126,15 +127,59 @@ impl MSeed3Header {
         Ok(())
     }
 
-    /// Start time as DateTime struct.
+    /// Typed view of the flags byte.
+    pub fn get_flags(&self) -> HeaderFlags {
+        HeaderFlags::from(self.flags)
+    }
+
+    /// Sets the flags byte from the typed flags.
+    pub fn set_flags(&mut self, flags: HeaderFlags) {
+        self.flags = flags.bits();
+    }
+
+    /// Start time as DateTime struct. A second of 60 is a leap second, which chrono
+    /// represents as second 59 with the nanoseconds past 1_000_000_000.
+    ///
+    /// Headers are checked when read, so this only panics if the time fields have been
+    /// set to an invalid time since, see try_start_as_utc.
     pub fn get_start_as_utc(&self) -> DateTime<Utc> {
-        Utc.yo(self.year as i32, self.day_of_year as u32)
-            .and_hms_nano(
-                self.hour as u32,
-                self.minute as u32,
-                self.second as u32,
-                self.nanosecond,
-            )
+        match self.try_start_as_utc() {
+            Ok(start) => start,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /// Start time as DateTime struct, or an error if the time fields are out of range,
+    /// for example a day_of_year of 0, a second of 61 or a second of 60 other than at 23:59,
+    /// when leap seconds occur.
+    pub fn try_start_as_utc(&self) -> Result<DateTime<Utc>, MSeedError> {
+        let bad_time = || {
+            MSeedError::InvalidTime(format!(
+                "{},{:03},{:02}:{:02}:{:02}.{:09}",
+                self.year, self.day_of_year, self.hour, self.minute, self.second, self.nanosecond
+            ))
+        };
+        if self.nanosecond >= 1_000_000_000
+            || (self.second == 60 && (self.hour != 23 || self.minute != 59))
+        {
+            return Err(bad_time());
+        }
+        let (second, leap_nanos) = if self.second == 60 {
+            (59, 1_000_000_000)
+        } else {
+            (self.second as u32, 0)
+        };
+        let time = NaiveDate::from_yo_opt(self.year as i32, self.day_of_year as u32)
+            .and_then(|date| {
+                date.and_hms_nano_opt(
+                    self.hour as u32,
+                    self.minute as u32,
+                    second,
+                    leap_nanos + self.nanosecond,
+                )
+            })
+            .ok_or_else(bad_time)?;
+        Ok(Utc.from_utc_datetime(&time))
     }
 
     pub fn set_start_from_utc(&mut self, start: DateTime<Utc>) {
@@ -156,20 +201,161 @@ impl MSeed3Header {
         start.format("%Y-%m-%dT%H:%M:%S%.9fZ").to_string()
     }
 
+    /// Sample rate in samples per second. The sample_rate_period field holds either a rate,
+    /// if positive, or a period in seconds, if negative. Zero if the record has no rate.
+    pub fn sample_rate_hz(&self) -> f64 {
+        if self.sample_rate_period < 0.0 {
+            -1.0 / self.sample_rate_period
+        } else {
+            self.sample_rate_period
+        }
+    }
+
+    /// Time between samples, rounded to the nanosecond. Zero if the record has no rate.
+    pub fn sample_period(&self) -> Duration {
+        sample_offset(self.sample_rate_period, 1)
+    }
+
+    /// Time of the sample at index i, which may be past the last sample. Offsets from the
+    /// start are limited to i64::MAX nanoseconds, about 292 years, and saturate beyond
+    /// that, see checked_time_of_sample.
+    pub fn time_of_sample(&self, i: usize) -> DateTime<Utc> {
+        self.get_start_as_utc() + sample_offset(self.sample_rate_period, i)
+    }
+
+    /// Time of the sample at index i, or None if its offset from the start is more than
+    /// i64::MAX nanoseconds or the sample rate is not finite.
+    pub fn checked_time_of_sample(&self, i: usize) -> Option<DateTime<Utc>> {
+        self.get_start_as_utc()
+            .checked_add_signed(checked_sample_offset(self.sample_rate_period, i)?)
+    }
+
+    /// Time of the last sample, or the start time if there are no samples. Saturates like
+    /// time_of_sample for records spanning more than about 292 years.
+    pub fn end_time(&self) -> DateTime<Utc> {
+        self.time_of_sample((self.num_samples as usize).saturating_sub(1))
+    }
+
+    /// Index of the sample covering the time, meaning the last sample at or before it, or
+    /// None if the time is before the first sample or at least one sample period after
+    /// the last sample. Always None for records without a sample rate.
+    pub fn sample_index_at(&self, time: DateTime<Utc>) -> Option<usize> {
+        let start = self.get_start_as_utc();
+        if !self.sample_rate_period.is_finite() || self.sample_rate_period == 0.0 || time < start {
+            return None;
+        }
+        // sample offsets are limited to i64::MAX nanoseconds, so no sample covers a later time
+        let diff = (time - start).num_nanoseconds()? as f64;
+        let period = if self.sample_rate_period > 0.0 {
+            1e9 / self.sample_rate_period
+        } else {
+            -self.sample_rate_period * 1e9
+        };
+        let num_samples = self.num_samples as usize;
+        let mut index = ((diff / period) as usize).min(num_samples);
+        // sample times are rounded to the nanosecond, so step to the exact boundary
+        while index > 0 && self.checked_time_of_sample(index).is_none_or(|t| t > time) {
+            index -= 1;
+        }
+        while index < num_samples
+            && self
+                .checked_time_of_sample(index + 1)
+                .is_some_and(|t| t <= time)
+        {
+            index += 1;
+        }
+        if index < num_samples {
+            Some(index)
+        } else {
+            None
+        }
+    }
+
     /// Format CRC as a hex string, like 0x106EAFA5
     pub fn crc_hex_string(&self) -> String {
         //        format!("{:#010X}", self.crc) // I like this style as shows it is a 32 bit number
         format!("{:#0X}", self.crc) // but mseed3-utils from Chad does this
     }
 
+    /// Checks that the data length is consistent with the number of samples for the
+    /// uncompressed primitive encodings. Other encodings cannot be checked without
+    /// decoding the data.
+    pub(crate) fn check_data_length(&self) -> Result<(), MSeedError> {
+        let sample_size: u32 = match self.encoding {
+            DataEncoding::INT16 => 2,
+            DataEncoding::INT32 | DataEncoding::FLOAT32 => 4,
+            DataEncoding::FLOAT64 => 8,
+            _ => return Ok(()),
+        };
+        let expected_data_length =
+            sample_size
+                .checked_mul(self.num_samples)
+                .ok_or(MSeedError::DataLengthOverflow(
+                    self.num_samples,
+                    self.encoding.value(),
+                ))?;
+        if self.data_length != expected_data_length {
+            return Err(MSeedError::DataLength(
+                expected_data_length,
+                self.num_samples,
+                self.encoding.value(),
+                self.data_length,
+            ));
+        }
+        Ok(())
+    }
+
     /// The size of the data record, including the identifier, extra headers and data. Note that
     /// this uses header values set on read, and so if any of these have changed, this value
-    /// will be wrong.
+    /// will be wrong. Saturates at u32::MAX if the lengths in a corrupt header overflow, see
+    /// checked_record_size.
     pub fn get_record_size(&self) -> u32 {
-        FIXED_HEADER_SIZE as u32
+        (FIXED_HEADER_SIZE as u32
+            + self.identifier_length as u32
+            + self.extra_headers_length as u32)
+            .saturating_add(self.data_length)
+    }
+
+    /// The size of the data record, like get_record_size, but errors instead if the lengths
+    /// in a corrupt header sum to more than fits in a u32.
+    pub fn checked_record_size(&self) -> Result<u32, MSeedError> {
+        let headers_size = FIXED_HEADER_SIZE as u32
             + self.identifier_length as u32
-            + self.extra_headers_length as u32
-            + self.data_length
+            + self.extra_headers_length as u32;
+        headers_size
+            .checked_add(self.data_length)
+            .ok_or(MSeedError::RecordSizeOverflow(
+                headers_size,
+                self.data_length,
+            ))
+    }
+}
+
+/// Time from the first sample to the sample at index offset, rounded to the nanosecond.
+/// Positive sample_rate_period is a rate in samples per second, negative is a period
+/// in seconds. Saturates at i64::MAX nanoseconds, about 292 years.
+pub(crate) fn sample_offset(sample_rate_period: f64, offset: usize) -> Duration {
+    let nanos = if sample_rate_period > 0.0 {
+        offset as f64 * 1e9 / sample_rate_period
+    } else {
+        offset as f64 * -sample_rate_period * 1e9
+    };
+    Duration::nanoseconds(nanos.round() as i64)
+}
+
+/// Like sample_offset, but None if the offset is not finite or does not fit in i64
+/// nanoseconds.
+pub(crate) fn checked_sample_offset(sample_rate_period: f64, offset: usize) -> Option<Duration> {
+    let nanos = if sample_rate_period > 0.0 {
+        offset as f64 * 1e9 / sample_rate_period
+    } else {
+        offset as f64 * -sample_rate_period * 1e9
+    };
+    // i64::MAX as f64 rounds up to 2^63, which is already out of range
+    if nanos.is_finite() && nanos.abs() < i64::MAX as f64 {
+        Some(Duration::nanoseconds(nanos.round() as i64))
+    } else {
+        None
     }
 }
 
@@ -184,7 +370,7 @@ impl TryFrom<&[u8]> for MSeed3Header {
                 FIXED_HEADER_SIZE,
             ));
         }
-        let bufslice: &[u8; FIXED_HEADER_SIZE] = &buffer.try_into().unwrap();
+        let bufslice: &[u8; FIXED_HEADER_SIZE] = &buffer[..FIXED_HEADER_SIZE].try_into().unwrap();
         MSeed3Header::try_from(bufslice)
     }
 }
@@ -192,8 +378,21 @@ impl TryFrom<&[u8]> for MSeed3Header {
 impl TryFrom<&[u8; FIXED_HEADER_SIZE]> for MSeed3Header {
     type Error = MSeedError;
 
-    /// Convert byte array to MSeed3Header, error if first bytes are not 'MS3'
+    /// Convert byte array to MSeed3Header, error if first bytes are not 'MS3' or if the
+    /// start time fields are out of range.
     fn try_from(buffer: &[u8; FIXED_HEADER_SIZE]) -> Result<Self, Self::Error> {
+        let header = MSeed3Header::parse_unchecked_time(buffer)?;
+        header.try_start_as_utc()?;
+        Ok(header)
+    }
+}
+
+impl MSeed3Header {
+    /// Parses the fixed header without checking the start time fields, for validation
+    /// that reports each bad field.
+    pub(crate) fn parse_unchecked_time(
+        buffer: &[u8; FIXED_HEADER_SIZE],
+    ) -> Result<MSeed3Header, MSeedError> {
         if buffer[0] != MSeed3Header::REC_IND[0] || buffer[1] != MSeed3Header::REC_IND[1] {
             return Err(MSeedError::BadRecordIndicator(buffer[0], buffer[1]));
         }
@@ -275,6 +474,9 @@ impl fmt::Display for MSeed3Header {
         writeln!(f, "      number of samples: {}", self.num_samples)?;
         writeln!(f, "       sample rate (Hz): {}", self.sample_rate_period)?;
         writeln!(f, "                  flags: [{:#010b}] 8 bits", self.flags)?;
+        for (bit, name) in self.get_flags().names() {
+            writeln!(f, "                         [Bit {}] {}", bit, name)?;
+        }
         writeln!(f, "                    CRC: {}", self.crc_hex_string())?;
         writeln!(
             f,
@@ -334,6 +536,98 @@ mod tests {
         assert!((nanosecond - 1.0_f64).abs() < f64::EPSILON);
     }
 
+    #[test]
+    fn sample_timing() {
+        let start = "2020-01-01T00:00:00Z".parse::<DateTime<Utc>>().unwrap();
+        let mut header = MSeed3Header::new(start, DataEncoding::INT32, 40.0, 100);
+        assert_eq!(header.sample_period(), Duration::milliseconds(25));
+        assert_eq!(header.end_time(), start + Duration::milliseconds(2475));
+        assert_eq!(header.time_of_sample(3), start + Duration::milliseconds(75));
+        assert_eq!(header.sample_index_at(start), Some(0));
+        assert_eq!(
+            header.sample_index_at(start + Duration::milliseconds(74)),
+            Some(2)
+        );
+        assert_eq!(
+            header.sample_index_at(start + Duration::milliseconds(75)),
+            Some(3)
+        );
+        assert_eq!(
+            header.sample_index_at(start + Duration::milliseconds(2499)),
+            Some(99)
+        );
+        assert_eq!(
+            header.sample_index_at(start + Duration::milliseconds(2500)),
+            None
+        );
+        assert_eq!(
+            header.sample_index_at(start - Duration::nanoseconds(1)),
+            None
+        );
+
+        // 3 Hz is not a whole number of nanoseconds
+        header.sample_rate_period = 3.0;
+        assert_eq!(
+            header.time_of_sample(1),
+            start + Duration::nanoseconds(333_333_333)
+        );
+        assert_eq!(
+            header.time_of_sample(2),
+            start + Duration::nanoseconds(666_666_667)
+        );
+        assert_eq!(header.sample_index_at(header.time_of_sample(2)), Some(2));
+
+        // negative is period in seconds
+        header.sample_rate_period = -10.0;
+        assert!((header.sample_rate_hz() - 0.1).abs() < f64::EPSILON);
+        assert_eq!(header.sample_period(), Duration::seconds(10));
+        assert_eq!(header.end_time(), start + Duration::seconds(990));
+
+        // not finite, or so fast every sample rounds to the start time
+        for rate in [f64::NAN, f64::INFINITY, f64::NEG_INFINITY] {
+            header.sample_rate_period = rate;
+            assert_eq!(header.sample_index_at(start), None);
+            assert_eq!(header.sample_index_at(start + Duration::seconds(1)), None);
+        }
+        header.sample_rate_period = 1e300;
+        assert_eq!(header.sample_index_at(start), None);
+        assert_eq!(header.sample_index_at(DateTime::<Utc>::MAX_UTC), None);
+        header.sample_rate_period = -1e9;
+        assert_eq!(
+            header.sample_index_at(start + Duration::days(200 * 365)),
+            Some(6)
+        );
+
+        header.sample_rate_period = 0.0;
+        header.num_samples = 0;
+        assert_eq!(header.end_time(), start);
+        assert_eq!(header.sample_index_at(start), None);
+    }
+
+    #[test]
+    fn sample_offset_limit() {
+        let start = "2020-01-01T00:00:00Z".parse::<DateTime<Utc>>().unwrap();
+        // one sample every 1e6 seconds, so 20000 samples span about 634 years
+        let header = MSeed3Header::new(start, DataEncoding::INT32, -1e6, 20000);
+        let limit = start + Duration::nanoseconds(i64::MAX);
+        assert_eq!(
+            header.checked_time_of_sample(9000),
+            Some(start + Duration::seconds(9_000_000_000))
+        );
+        assert_eq!(header.checked_time_of_sample(10000), None);
+        assert_eq!(header.time_of_sample(10000), limit);
+        assert_eq!(header.end_time(), limit);
+        assert_eq!(
+            header.sample_index_at(start + Duration::seconds(9_000_000_000)),
+            Some(9000)
+        );
+        assert_eq!(
+            header.sample_index_at(limit - Duration::seconds(1)),
+            Some(9223)
+        );
+        assert_eq!(header.sample_index_at(limit + Duration::seconds(1)), None);
+    }
+
     fn get_dummy_header() -> [u8; 64] {
         // hexdump -C -n 64 reference-sinusoid-int16.mseed3
         // 00000000  4d 53 03 04 00 00 00 00  dc 07 01 00 00 00 00 01  |MS..............|
@@ -370,6 +664,8 @@ mod tests {
         assert_eq!(head.record_indicator, MSeed3Header::REC_IND);
         assert_eq!(head.format_version, 3);
         assert_eq!(head.flags, 4);
+        assert!(head.get_flags().clock_locked());
+        assert!(head.to_string().contains("[Bit 2] Clock locked"));
         assert_eq!(head.nanosecond, 0);
         assert_eq!(head.year, 2012);
         assert_eq!(head.day_of_year, 1);
@@ -408,6 +704,36 @@ mod tests {
         assert_eq!(buf[0..2], MSeed3Header::REC_IND);
     }
 
+    #[test]
+    fn invalid_start_time() {
+        let mut buf = get_dummy_header();
+        // day of year 0
+        buf[10] = 0;
+        assert!(matches!(
+            MSeed3Header::try_from(&buf[0..FIXED_HEADER_SIZE]),
+            Err(MSeedError::InvalidTime(_))
+        ));
+        buf[10] = 1;
+        let mut header = MSeed3Header::try_from(&buf[0..FIXED_HEADER_SIZE]).unwrap();
+        header.hour = 24;
+        assert!(header.try_start_as_utc().is_err());
+        header.hour = 23;
+        header.minute = 59;
+        header.second = 60;
+        header.nanosecond = 500_000_000;
+        let start = header.try_start_as_utc().unwrap();
+        assert_eq!(start.format("%H:%M:%S%.3f").to_string(), "23:59:60.500");
+        header.second = 61;
+        assert!(header.try_start_as_utc().is_err());
+        // leap seconds only happen at the end of the day
+        header.second = 60;
+        header.minute = 58;
+        assert!(header.try_start_as_utc().is_err());
+        header.hour = 12;
+        header.minute = 59;
+        assert!(header.try_start_as_utc().is_err());
+    }
+
     #[test]
     fn set_start_leap_second() {
         let buf = get_dummy_header();
@@ -0,0 +1,154 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Typed view of the flags byte in the fixed header. Bits 0 to 2 are defined by the
+/// specification, bits 3 to 7 are reserved and should be zero but are preserved so that
+/// reading and writing a record does not change them.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct HeaderFlags(pub u8);
+
+impl HeaderFlags {
+    /// Bit 0, calibration signals present.
+    pub const CALIBRATION_SIGNALS_PRESENT: u8 = 0x01;
+    /// Bit 1, time tag is questionable.
+    pub const TIME_TAG_QUESTIONABLE: u8 = 0x02;
+    /// Bit 2, clock locked.
+    pub const CLOCK_LOCKED: u8 = 0x04;
+    /// Bits 3 to 7, reserved for future use.
+    pub const RESERVED_MASK: u8 = 0xF8;
+
+    pub fn new() -> HeaderFlags {
+        HeaderFlags(0)
+    }
+
+    /// The raw flags byte.
+    pub fn bits(&self) -> u8 {
+        self.0
+    }
+
+    pub fn calibration_signals_present(&self) -> bool {
+        self.is_set(HeaderFlags::CALIBRATION_SIGNALS_PRESENT)
+    }
+
+    pub fn set_calibration_signals_present(&mut self, value: bool) {
+        self.set(HeaderFlags::CALIBRATION_SIGNALS_PRESENT, value)
+    }
+
+    pub fn time_tag_questionable(&self) -> bool {
+        self.is_set(HeaderFlags::TIME_TAG_QUESTIONABLE)
+    }
+
+    pub fn set_time_tag_questionable(&mut self, value: bool) {
+        self.set(HeaderFlags::TIME_TAG_QUESTIONABLE, value)
+    }
+
+    pub fn clock_locked(&self) -> bool {
+        self.is_set(HeaderFlags::CLOCK_LOCKED)
+    }
+
+    pub fn set_clock_locked(&mut self, value: bool) {
+        self.set(HeaderFlags::CLOCK_LOCKED, value)
+    }
+
+    /// The reserved bits, 3 to 7, that are set, zero if none.
+    pub fn reserved_bits(&self) -> u8 {
+        self.0 & HeaderFlags::RESERVED_MASK
+    }
+
+    pub fn has_reserved_bits(&self) -> bool {
+        self.reserved_bits() != 0
+    }
+
+    /// Clears the reserved bits, leaving the defined flags unchanged.
+    pub fn clear_reserved_bits(&mut self) {
+        self.0 &= !HeaderFlags::RESERVED_MASK
+    }
+
+    /// Names of the flags that are set, in bit order, using the same wording as mseed3-utils.
+    pub fn names(&self) -> Vec<(u8, &'static str)> {
+        (0..8_u8)
+            .filter(|bit| self.0 & (1 << bit) != 0)
+            .map(|bit| (bit, flag_name(bit)))
+            .collect()
+    }
+
+    fn is_set(&self, mask: u8) -> bool {
+        self.0 & mask != 0
+    }
+
+    fn set(&mut self, mask: u8, value: bool) {
+        if value {
+            self.0 |= mask;
+        } else {
+            self.0 &= !mask;
+        }
+    }
+}
+
+fn flag_name(bit: u8) -> &'static str {
+    match bit {
+        0 => "Calibration signals present",
+        1 => "Time tag is questionable",
+        2 => "Clock locked",
+        _ => "Undefined bit set",
+    }
+}
+
+impl From<u8> for HeaderFlags {
+    fn from(v: u8) -> Self {
+        HeaderFlags(v)
+    }
+}
+
+impl From<HeaderFlags> for u8 {
+    fn from(flags: HeaderFlags) -> Self {
+        flags.0
+    }
+}
+
+impl fmt::Display for HeaderFlags {
+    /// Lists the flags that are set, one per line, as `[Bit 2] Clock locked`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let names = self.names();
+        for (i, (bit, name)) in names.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "[Bit {}] {}", bit, name)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn named_bits() {
+        let mut flags = HeaderFlags::new();
+        flags.set_clock_locked(true);
+        assert_eq!(flags.bits(), 4);
+        assert!(flags.clock_locked());
+        assert!(!flags.calibration_signals_present());
+        flags.set_calibration_signals_present(true);
+        flags.set_time_tag_questionable(true);
+        assert_eq!(flags.bits(), 7);
+        flags.set_clock_locked(false);
+        assert_eq!(flags.bits(), 3);
+        assert!(!flags.has_reserved_bits());
+    }
+
+    #[test]
+    fn reserved_bits() {
+        let mut flags = HeaderFlags::from(0x84);
+        assert!(flags.has_reserved_bits());
+        assert_eq!(flags.reserved_bits(), 0x80);
+        assert_eq!(
+            flags.to_string(),
+            "[Bit 2] Clock locked\n[Bit 7] Undefined bit set"
+        );
+        flags.clear_reserved_bits();
+        assert_eq!(u8::from(flags), 0x04);
+    }
+}
@@ -0,0 +1,208 @@
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use std::io::BufRead;
+
+use crate::header::MSeed3Header;
+use crate::mseed_error::MSeedError;
+use crate::record::MSeed3Record;
+
+/// Leap seconds up to the time of the last release, in the IERS leap-seconds.list format.
+pub const BUNDLED_LEAP_SECONDS_LIST: &str = include_str!("leap-seconds.list");
+
+/// Seconds from the NTP epoch, 1900-01-01, to the unix epoch, 1970-01-01.
+const NTP_UNIX_OFFSET: i64 = 2_208_988_800;
+
+/// An entry in the leap second table, the value of TAI-UTC from a time on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LeapSecond {
+    /// Start of the day following the leap second, which is 23:59:60 UTC the day before.
+    pub time: DateTime<Utc>,
+    /// TAI-UTC in seconds from this time on.
+    pub tai_minus_utc: i32,
+}
+
+/// Table of leap seconds, for durations and times that span them. Durations calculated
+/// from DateTime values ignore leap seconds, so are one second short for each positive
+/// leap second in between.
+///
+/// #Example
+///
+/// ```
+/// # use mseed3::MSeedError;
+/// # fn main() -> Result<(), MSeedError> {
+/// use chrono::{DateTime, Duration, Utc};
+/// let table = mseed3::LeapSecondTable::bundled();
+/// let before = "2016-12-31T23:59:59Z".parse::<DateTime<Utc>>()?;
+/// let after = "2017-01-01T00:00:00Z".parse::<DateTime<Utc>>()?;
+/// assert_eq!(after - before, Duration::seconds(1));
+/// assert_eq!(table.duration_between(before, after), Duration::seconds(2));
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LeapSecondTable {
+    /// Sorted by time.
+    pub leap_seconds: Vec<LeapSecond>,
+    /// Time after which the table may be missing a leap second, from the `#@` line.
+    pub expires: Option<DateTime<Utc>>,
+}
+
+impl LeapSecondTable {
+    /// Table of the leap seconds bundled with this crate, see BUNDLED_LEAP_SECONDS_LIST.
+    /// Load a current leap-seconds.list with from_reader to know of newer ones.
+    pub fn bundled() -> LeapSecondTable {
+        LeapSecondTable::parse(BUNDLED_LEAP_SECONDS_LIST)
+            .expect("bundled leap-seconds.list is valid")
+    }
+
+    /// Parses a table in the IERS leap-seconds.list format, where each line is the NTP
+    /// seconds and TAI-UTC, and `#` starts a comment, except `#@` giving the expiry.
+    pub fn parse(text: &str) -> Result<LeapSecondTable, MSeedError> {
+        let mut leap_seconds = Vec::new();
+        let mut expires = None;
+        for line in text.lines() {
+            if let Some(expiry) = line.strip_prefix("#@") {
+                expires = Some(ntp_to_utc(parse_field(expiry.trim(), line)?)?);
+                continue;
+            }
+            let content = line.split('#').next().unwrap_or("").trim();
+            if content.is_empty() {
+                continue;
+            }
+            let mut fields = content.split_whitespace();
+            let (ntp, tai_minus_utc) = match (fields.next(), fields.next()) {
+                (Some(ntp), Some(tai_minus_utc)) => (ntp, tai_minus_utc),
+                _ => return Err(bad_line(line)),
+            };
+            leap_seconds.push(LeapSecond {
+                time: ntp_to_utc(parse_field(ntp, line)?)?,
+                tai_minus_utc: parse_field(tai_minus_utc, line)?,
+            });
+        }
+        leap_seconds.sort_by_key(|leap| leap.time);
+        Ok(LeapSecondTable {
+            leap_seconds,
+            expires,
+        })
+    }
+
+    /// Reads a leap-seconds.list file, see parse.
+    pub fn from_reader<R: BufRead>(reader: &mut R) -> Result<LeapSecondTable, MSeedError> {
+        let mut text = String::new();
+        reader.read_to_string(&mut text)?;
+        LeapSecondTable::parse(&text)
+    }
+
+    /// TAI-UTC in seconds at the time, or None before the first entry in 1972.
+    pub fn tai_minus_utc(&self, time: DateTime<Utc>) -> Option<i32> {
+        self.leap_seconds
+            .iter()
+            .rev()
+            .find(|leap| leap.time <= time)
+            .map(|leap| leap.tai_minus_utc)
+    }
+
+    /// Net number of leap seconds inserted after start and up to end, negative for
+    /// removed leap seconds or if end is before start.
+    pub fn leap_seconds_between(&self, start: DateTime<Utc>, end: DateTime<Utc>) -> i64 {
+        let at = |t| self.tai_minus_utc(t).unwrap_or(0) as i64;
+        at(end) - at(start)
+    }
+
+    /// Elapsed time from start to end, including any leap seconds in between.
+    pub fn duration_between(&self, start: DateTime<Utc>, end: DateTime<Utc>) -> Duration {
+        end - start + Duration::seconds(self.leap_seconds_between(start, end))
+    }
+
+    /// Time of the last sample of the record with the header, allowing for leap seconds
+    /// in the table between the start and end. Samples are evenly spaced in elapsed time,
+    /// so the last sample is labelled one second earlier for each leap second inserted. A
+    /// last sample falling on the leap second itself is given as the second after it.
+    pub fn end_time(&self, header: &MSeed3Header) -> Result<DateTime<Utc>, MSeedError> {
+        let start = header.try_start_as_utc()?;
+        let end = header.end_time();
+        let leaps = self.leap_seconds_between(start, end - Duration::seconds(1));
+        Ok(end - Duration::seconds(leaps))
+    }
+}
+
+fn bad_line(line: &str) -> MSeedError {
+    MSeedError::InvalidTime(format!("bad line in leap seconds list: {}", line))
+}
+
+fn parse_field<T: std::str::FromStr>(field: &str, line: &str) -> Result<T, MSeedError> {
+    field.parse::<T>().map_err(|_| bad_line(line))
+}
+
+fn ntp_to_utc(ntp_seconds: i64) -> Result<DateTime<Utc>, MSeedError> {
+    Utc.timestamp_opt(ntp_seconds - NTP_UNIX_OFFSET, 0)
+        .single()
+        .ok_or_else(|| MSeedError::InvalidTime(format!("NTP seconds {}", ntp_seconds)))
+}
+
+impl MSeed3Record {
+    /// Time of the last sample, honoring `FDSN.Time.LeapSecond`. When the record contains a
+    /// leap second the samples after it are labelled one second earlier for an inserted
+    /// leap second, or later for a removed one, than header.end_time() gives.
+    pub fn end_time(&self) -> Result<DateTime<Utc>, MSeedError> {
+        self.header.try_start_as_utc()?;
+        let leap_second = self
+            .get_fdsn_time()?
+            .and_then(|time| time.leap_second)
+            .unwrap_or(0);
+        Ok(self.header.end_time() - Duration::seconds(leap_second))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fdsn_headers::FdsnTime;
+
+    fn time(s: &str) -> DateTime<Utc> {
+        s.parse::<DateTime<Utc>>().unwrap()
+    }
+
+    #[test]
+    fn bundled_table() {
+        let table = LeapSecondTable::bundled();
+        assert_eq!(table.leap_seconds.len(), 28);
+        assert_eq!(table.expires, Some(time("2026-06-28T00:00:00Z")));
+        assert_eq!(table.tai_minus_utc(time("1971-12-31T00:00:00Z")), None);
+        assert_eq!(table.tai_minus_utc(time("1972-01-01T00:00:00Z")), Some(10));
+        assert_eq!(table.tai_minus_utc(time("2016-12-31T23:59:59Z")), Some(36));
+        assert_eq!(table.tai_minus_utc(time("2020-01-01T00:00:00Z")), Some(37));
+        assert_eq!(
+            table.leap_seconds_between(time("2012-01-01T00:00:00Z"), time("2017-06-01T00:00:00Z")),
+            3
+        );
+    }
+
+    #[test]
+    fn parse_list() -> Result<(), MSeedError> {
+        let text = "# comment\n#@\t3707596800\n3644697600\t36\t# 1 Jul 2015\n3692217600\t37\n";
+        let table = LeapSecondTable::from_reader(&mut text.as_bytes())?;
+        assert_eq!(table.leap_seconds.len(), 2);
+        assert_eq!(table.leap_seconds[1].time, time("2017-01-01T00:00:00Z"));
+        assert_eq!(table.expires, Some(time("2017-06-28T00:00:00Z")));
+        assert!(LeapSecondTable::parse("3692217600\n").is_err());
+        assert!(LeapSecondTable::parse("abc 37\n").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn end_time_across_leap() -> Result<(), MSeedError> {
+        let start = time("2016-12-31T23:59:58Z");
+        let mut rec = MSeed3Record::from_ints(start, 1.0, vec![0, 1, 2, 3, 4]);
+        // samples at 58, 59, 60, 00, 01
+        assert_eq!(rec.header.end_time(), time("2017-01-01T00:00:02Z"));
+        let table = LeapSecondTable::bundled();
+        assert_eq!(table.end_time(&rec.header)?, time("2017-01-01T00:00:01Z"));
+        assert_eq!(rec.end_time()?, time("2017-01-01T00:00:02Z"));
+        rec.set_fdsn_time(&FdsnTime {
+            leap_second: Some(1),
+            ..Default::default()
+        })?;
+        assert_eq!(rec.end_time()?, time("2017-01-01T00:00:01Z"));
+        Ok(())
+    }
+}
@@ -0,0 +1,370 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io::BufRead;
+
+use crate::header::{MSeed3Header, FIXED_HEADER_SIZE};
+use crate::mseed_error::MSeedError;
+use crate::record::MSeed3Record;
+use crate::record_ref::MSeed3RecordRef;
+
+/// Default largest record the LenientMSeed3Reader will accept, 16 MiB.
+pub const DEFAULT_MAX_RECORD_SIZE: usize = 16 * 1024 * 1024;
+
+/// Record indicator and format version that start every miniseed3 record.
+const RECORD_START: [u8; 3] = [b'M', b'S', 3];
+
+/// A region of the input that was skipped as it did not contain a valid record.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct BadRegion {
+    /// Byte offset from the start of the input.
+    pub offset: u64,
+    pub length: u64,
+    /// Why the first record candidate in the region was rejected.
+    pub reason: String,
+}
+
+impl fmt::Display for BadRegion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "skipped {} bytes at offset {}: {}",
+            self.length, self.offset, self.reason
+        )
+    }
+}
+
+/// Iterator over the records in a BufRead that skips over damaged records instead of
+/// stopping. When the bytes at the current position are not a valid record, the reader
+/// scans forward for the next `MS` record indicator with format version 3, and only
+/// continues from it once the fixed header is plausible and the CRC matches. The skipped
+/// bytes are reported as a BadRegion, see bad_regions.
+///
+/// Only errors reading from the underlying input are returned from the iterator, after
+/// which iteration stops.
+pub struct LenientMSeed3Reader<R: BufRead> {
+    buf_reader: R,
+    /// Largest record size accepted, larger sizes in a header are taken as corruption.
+    pub max_record_size: usize,
+    buffer: Vec<u8>,
+    /// Offset of the start of buffer in the input.
+    buffer_offset: u64,
+    record_offset: u64,
+    eof: bool,
+    done: bool,
+    current_bad: Option<BadRegion>,
+    bad_regions: Vec<BadRegion>,
+}
+
+impl<R: BufRead> LenientMSeed3Reader<R> {
+    pub fn new(buf_reader: R) -> LenientMSeed3Reader<R> {
+        LenientMSeed3Reader {
+            buf_reader,
+            max_record_size: DEFAULT_MAX_RECORD_SIZE,
+            buffer: Vec::new(),
+            buffer_offset: 0,
+            record_offset: 0,
+            eof: false,
+            done: false,
+            current_bad: None,
+            bad_regions: Vec::new(),
+        }
+    }
+
+    /// Byte offset from the start of the input of the most recently returned record.
+    pub fn record_offset(&self) -> u64 {
+        self.record_offset
+    }
+
+    /// Bad regions found so far. A region is only added once the next good record, or the
+    /// end of input, is found.
+    pub fn bad_regions(&self) -> &[BadRegion] {
+        &self.bad_regions
+    }
+
+    /// Removes and returns the bad regions found so far.
+    pub fn take_bad_regions(&mut self) -> Vec<BadRegion> {
+        std::mem::take(&mut self.bad_regions)
+    }
+
+    /// Reads until the buffer holds at least len bytes or the input is exhausted.
+    fn fill_to(&mut self, len: usize) -> Result<(), MSeedError> {
+        while self.buffer.len() < len && !self.eof {
+            let available = self.buf_reader.fill_buf()?;
+            if available.is_empty() {
+                self.eof = true;
+            } else {
+                let n = available.len();
+                self.buffer.extend_from_slice(available);
+                self.buf_reader.consume(n);
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks for a valid record at the start of the buffer, returning its size.
+    fn check_candidate(&mut self) -> Result<Result<usize, String>, MSeedError> {
+        self.fill_to(FIXED_HEADER_SIZE)?;
+        if self.buffer.len() < FIXED_HEADER_SIZE {
+            return Ok(Err(format!(
+                "{} bytes at end of input, too short for a record",
+                self.buffer.len()
+            )));
+        }
+        let header = match MSeed3Header::try_from(&self.buffer[..]) {
+            Ok(header) => header,
+            Err(e) => return Ok(Err(e.to_string())),
+        };
+        let record_size = match header.checked_record_size() {
+            Ok(record_size) => record_size as usize,
+            Err(e) => return Ok(Err(e.to_string())),
+        };
+        if record_size > self.max_record_size {
+            return Ok(Err(format!(
+                "record size {} larger than maximum {}",
+                record_size, self.max_record_size
+            )));
+        }
+        self.fill_to(record_size)?;
+        let record = match MSeed3RecordRef::from_bytes(&self.buffer) {
+            Ok(record) => record,
+            Err(e) => return Ok(Err(e.to_string())),
+        };
+        if let Err(e) = record.verify_crc() {
+            return Ok(Err(e.to_string()));
+        }
+        Ok(Ok(record_size))
+    }
+
+    /// Drops n bytes from the front of the buffer.
+    fn skip(&mut self, n: usize) {
+        self.buffer.drain(..n);
+        self.buffer_offset += n as u64;
+    }
+
+    /// Adds n bytes at the front of the buffer to the current bad region and drops them.
+    fn skip_bad(&mut self, n: usize, reason: &str) {
+        match &mut self.current_bad {
+            Some(bad) => bad.length += n as u64,
+            None => {
+                self.current_bad = Some(BadRegion {
+                    offset: self.buffer_offset,
+                    length: n as u64,
+                    reason: String::from(reason),
+                })
+            }
+        }
+        self.skip(n);
+    }
+
+    fn close_bad_region(&mut self) {
+        if let Some(bad) = self.current_bad.take() {
+            self.bad_regions.push(bad);
+        }
+    }
+
+    /// Skips past the bad bytes at the start of the buffer to the next possible record.
+    fn resync(&mut self, reason: &str) -> Result<(), MSeedError> {
+        // the candidate at the start of the buffer has already failed
+        let mut from = 1;
+        loop {
+            let found = self.buffer[from..]
+                .windows(RECORD_START.len())
+                .position(|w| w == RECORD_START);
+            if let Some(i) = found {
+                self.skip_bad(from + i, reason);
+                return Ok(());
+            }
+            if self.eof {
+                let n = self.buffer.len();
+                self.skip_bad(n, reason);
+                return Ok(());
+            }
+            // keep the tail in case it is the start of a split record indicator
+            let keep = (RECORD_START.len() - 1).min(self.buffer.len() - from);
+            let n = self.buffer.len() - keep;
+            self.skip_bad(n, reason);
+            from = 0;
+            let target = self.buffer.len() + 4096;
+            self.fill_to(target)?;
+        }
+    }
+
+    fn next_record(&mut self) -> Result<Option<MSeed3Record>, MSeedError> {
+        loop {
+            self.fill_to(1)?;
+            if self.buffer.is_empty() {
+                self.close_bad_region();
+                return Ok(None);
+            }
+            let reason = match self.check_candidate()? {
+                Ok(record_size) => {
+                    let parsed = MSeed3RecordRef::from_bytes(&self.buffer[..record_size])
+                        .and_then(|r| r.to_record());
+                    match parsed {
+                        Ok(record) => {
+                            self.close_bad_region();
+                            self.record_offset = self.buffer_offset;
+                            self.skip(record_size);
+                            return Ok(Some(record));
+                        }
+                        Err(e) => {
+                            // CRC is good so the record length can be trusted
+                            let reason = e.to_string();
+                            self.skip_bad(record_size, &reason);
+                            continue;
+                        }
+                    }
+                }
+                Err(reason) => reason,
+            };
+            self.resync(&reason)?;
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for LenientMSeed3Reader<R> {
+    type Item = Result<MSeed3Record, MSeedError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        match self.next_record() {
+            Ok(Some(record)) => Some(Ok(record)),
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_encoding::DataEncoding;
+    use chrono::{DateTime, Utc};
+    use std::io::{BufReader, BufWriter, Write};
+
+    fn write_records(records: &[MSeed3Record]) -> Vec<u8> {
+        let mut out = Vec::new();
+        {
+            let mut buf_writer = BufWriter::new(&mut out);
+            for rec in records {
+                rec.write_to(&mut buf_writer).unwrap();
+            }
+            buf_writer.flush().unwrap();
+        }
+        out
+    }
+
+    fn sample_records() -> Vec<MSeed3Record> {
+        let start = "2014-11-28T12:00:09Z".parse::<DateTime<Utc>>().unwrap();
+        (0..3)
+            .map(|i| MSeed3Record::from_ints(start, 10.0, vec![i, i + 1, i + 2]))
+            .collect()
+    }
+
+    #[test]
+    fn bad_crc_and_garbage() -> Result<(), MSeedError> {
+        let records = sample_records();
+        let one = write_records(&records[..1]);
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&one);
+        let garbage_offset = bytes.len();
+        bytes.extend_from_slice(b"garbage MS\x03 more garbage");
+        let mut bad_crc = write_records(&records[1..2]);
+        let last = bad_crc.len() - 1;
+        bad_crc[last] ^= 0xff;
+        bytes.extend_from_slice(&bad_crc);
+        let good_offset = bytes.len();
+        bytes.extend_from_slice(&write_records(&records[2..]));
+        bytes.extend_from_slice(b"tail");
+
+        // small buffer so the scan crosses BufRead refills
+        let mut reader = LenientMSeed3Reader::new(BufReader::with_capacity(7, &bytes[..]));
+        let first = reader.next().unwrap()?;
+        assert_eq!(first.header.num_samples, 3);
+        let second = reader.next().unwrap()?;
+        assert_eq!(reader.record_offset(), good_offset as u64);
+        assert_eq!(
+            second.decode_samples()?.to_string(),
+            records[2].decode_samples()?.to_string()
+        );
+        assert!(reader.next().is_none());
+        // garbage and the bad CRC record are adjacent, so form one region
+        let bad = reader.bad_regions();
+        assert_eq!(bad.len(), 2);
+        assert_eq!(bad[0].offset, garbage_offset as u64);
+        assert_eq!(bad[0].length, (good_offset - garbage_offset) as u64);
+        assert!(bad[0].reason.contains("must start with MS"), "{}", bad[0]);
+        assert_eq!(bad[1].offset, (bytes.len() - 4) as u64);
+        assert_eq!(bad[1].length, 4);
+        Ok(())
+    }
+
+    #[test]
+    fn bad_crc_only() -> Result<(), MSeedError> {
+        let records = sample_records();
+        let mut bytes = write_records(&records);
+        let second_offset = bytes.len() / 3;
+        // corrupt a data byte of the second record
+        bytes[2 * second_offset - 1] ^= 0x01;
+        let mut reader = LenientMSeed3Reader::new(BufReader::new(&bytes[..]));
+        let read: Vec<MSeed3Record> = (&mut reader).collect::<Result<_, _>>()?;
+        assert_eq!(read.len(), 2);
+        assert_eq!(
+            reader.bad_regions(),
+            &[BadRegion {
+                offset: second_offset as u64,
+                length: second_offset as u64,
+                reason: reader.bad_regions()[0].reason.clone(),
+            }]
+        );
+        assert!(reader.bad_regions()[0].reason.contains("CRC"));
+        Ok(())
+    }
+
+    #[test]
+    fn overflowing_lengths() -> Result<(), MSeedError> {
+        let records = sample_records();
+        let good = write_records(&records[..1]);
+        // 4 bytes per sample overflows u32
+        let mut num_samples = write_records(&records[1..2]);
+        num_samples[24..28].copy_from_slice(&0x4000_0001_u32.to_le_bytes());
+        // compressed, so the record size overflows u32 instead
+        let mut data_length = write_records(&records[2..]);
+        data_length[15] = DataEncoding::STEIM2.value();
+        data_length[36..40].copy_from_slice(&0xFFFF_FFF0_u32.to_le_bytes());
+        let mut bytes = Vec::new();
+        for part in [&good, &num_samples, &good, &data_length, &good] {
+            bytes.extend_from_slice(part);
+        }
+        let mut reader = LenientMSeed3Reader::new(BufReader::new(&bytes[..]));
+        let read: Vec<MSeed3Record> = (&mut reader).collect::<Result<_, _>>()?;
+        assert_eq!(read.len(), 3);
+        let bad = reader.bad_regions();
+        assert_eq!(bad.len(), 2);
+        assert_eq!(bad[0].offset, good.len() as u64);
+        assert_eq!(bad[0].length, num_samples.len() as u64);
+        assert!(bad[0].reason.contains("1073741825 samples"), "{}", bad[0]);
+        assert_eq!(bad[1].offset, (2 * good.len() + num_samples.len()) as u64);
+        assert_eq!(bad[1].length, data_length.len() as u64);
+        Ok(())
+    }
+
+    #[test]
+    fn all_good() -> Result<(), MSeedError> {
+        let records = sample_records();
+        let bytes = write_records(&records);
+        let mut reader = LenientMSeed3Reader::new(BufReader::new(&bytes[..]));
+        let read: Vec<MSeed3Record> = (&mut reader).collect::<Result<_, _>>()?;
+        assert_eq!(read.len(), 3);
+        assert!(reader.take_bad_regions().is_empty());
+        Ok(())
+    }
+}
@@ -130,29 +130,81 @@
 //!
 //!
 
+mod band_code;
 mod data_encoding;
+mod decoded_timeseries;
+mod detection;
 mod encoded_timeseries;
+mod extra_header_schema;
+mod fdsn_headers;
 mod fdsn_source_identifier;
+mod gaps;
 mod header;
+mod header_flags;
+mod leap_seconds;
+mod lenient_reader;
+mod mseed2;
 mod mseed_error;
+mod read_options;
+mod reader;
 mod record;
-mod steim1;
+mod record_packer;
+mod record_ref;
+mod sid_pattern;
+pub mod steim1;
+pub mod steim2;
 mod steim_frame_block;
+mod time_correction;
+mod time_window;
+mod trace;
+mod validator;
 
 use std::io::BufRead;
 
+pub use self::band_code::{check_band_code, suggest_band_code, InstrumentType, BAND_CORNER_PERIOD};
 pub use self::data_encoding::DataEncoding;
+pub use self::decoded_timeseries::DecodedTimeseries;
+pub use self::detection::{
+    list_detections, DetectionQuery, RecordDetection, GENERIC_DETECTION, MURDOCK_DETECTION,
+};
 pub use self::encoded_timeseries::EncodedTimeseries;
+pub use self::extra_header_schema::{
+    ExtraHeaderValidator, SchemaViolation, FDSN_EXTRA_HEADERS_SCHEMA,
+    FDSN_EXTRA_HEADERS_SCHEMA_VERSION,
+};
+pub use self::fdsn_headers::{
+    FdsnCalibration, FdsnCalibrationSequence, FdsnClock, FdsnDetection, FdsnEvent, FdsnFlags,
+    FdsnHeaders, FdsnProvenance, FdsnRecenter, FdsnRecenterSequence, FdsnTime, FdsnTimeException,
+};
 pub use self::fdsn_source_identifier::{FdsnSourceIdentifier, SourceIdentifier};
+pub use self::gaps::{GapDetector, GapOverlap, GapReport, DEFAULT_GAP_TOLERANCE};
 pub use self::header::{MSeed3Header, FIXED_HEADER_SIZE};
+pub use self::header_flags::HeaderFlags;
+pub use self::leap_seconds::{LeapSecond, LeapSecondTable, BUNDLED_LEAP_SECONDS_LIST};
+pub use self::lenient_reader::{BadRegion, LenientMSeed3Reader, DEFAULT_MAX_RECORD_SIZE};
+pub use self::mseed2::{
+    write_mseed2, BTime, Blockette100, Blockette1000, Blockette1001, Blockette500, MSeed2Header,
+    MSeed2Reader, MSeed2Record, MSEED2_FIXED_HEADER_SIZE, MSEED2_RECORD_LENGTH_EXPONENTS,
+};
 pub use self::mseed_error::MSeedError;
+pub use self::read_options::ReadOptions;
+pub use self::reader::{
+    MSeed3Reader, MSeed3RecordSummary, MSeed3SummaryReader, UnparsedMSeed3Reader,
+};
 pub use self::record::{
     pack_headers, MSeed3Record, UnparsedMSeed3Record, CASTAGNOLI, FDSN_EXTRA_HEADERS,
 };
+pub use self::record_packer::{RecordPacker, StreamingPacker};
+pub use self::record_ref::{MSeed3RecordRef, MSeed3RecordRefIter};
+pub use self::sid_pattern::{SelectionList, SidPattern};
 pub use self::steim1::{decode, encode};
 pub use self::steim_frame_block::{SteimFrame, SteimFrameBlock};
+pub use self::time_window::{SourceIdentifierMatcher, TimeWindowReader};
+pub use self::trace::{Trace, TraceList, DEFAULT_SAMPLE_RATE_TOLERANCE};
+pub use self::validator::{RecordValidator, Severity, ValidationIssue, ValidationReport};
 
-/// Read miniseed3 records from a BufReader.
+/// Read miniseed3 records from a BufReader. All records are loaded into memory, see
+/// MSeed3Reader to iterate over records one at a time.
 ///
 /// #Example
 ///
@@ -167,15 +219,5 @@ pub use self::steim_frame_block::{SteimFrame, SteimFrameBlock};
 /// ```
 ///
 pub fn read_mseed3<R: BufRead>(buf_reader: &mut R) -> Result<Vec<MSeed3Record>, MSeedError> {
-    let mut records: Vec<MSeed3Record> = Vec::new();
-    while !buf_reader.fill_buf()?.is_empty() {
-        let result = MSeed3Record::from_reader(&mut buf_reader.by_ref());
-        match result {
-            Ok(rec) => {
-                records.push(rec);
-            }
-            Err(e) => return Err(e),
-        }
-    }
-    Ok(records)
+    MSeed3Reader::new(buf_reader).collect()
 }
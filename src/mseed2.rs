@@ -0,0 +1,1436 @@
+use chrono::prelude::*;
+use chrono::{Duration, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::map::Map;
+use serde_json::{json, Value};
+use std::convert::{TryFrom, TryInto};
+use std::fmt;
+use std::io::{BufWriter, Read, Write};
+
+use crate::data_encoding::DataEncoding;
+use crate::decoded_timeseries::DecodedTimeseries;
+use crate::encoded_timeseries::EncodedTimeseries;
+use crate::fdsn_source_identifier::{FdsnSourceIdentifier, SourceIdentifier};
+use crate::header::{sample_offset, MSeed3Header};
+use crate::header_flags::HeaderFlags;
+use crate::mseed_error::MSeedError;
+use crate::record::{MSeed3Record, FDSN_EXTRA_HEADERS};
+use crate::{steim1, steim2};
+
+/// Size in bytes of the miniSEED 2 fixed section of data header.
+pub const MSEED2_FIXED_HEADER_SIZE: usize = 48;
+
+/// Blockette 1000 record length exponents accepted when reading, records of 128 to 65536
+/// bytes.
+pub const MSEED2_RECORD_LENGTH_EXPONENTS: std::ops::RangeInclusive<u8> = 7..=16;
+
+/// Offset of the data in written records, after blockettes 1000 and 1001.
+const MSEED2_DATA_OFFSET: usize = 64;
+
+/// SEED 2 BTIME, the time structure used in the fixed header and blockettes. The fraction
+/// is in units of 0.0001 seconds.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct BTime {
+    pub year: u16,
+    pub day_of_year: u16,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+    pub fract: u16,
+}
+
+impl BTime {
+    fn parse(bytes: &[u8], big_endian: bool) -> BTime {
+        BTime {
+            year: read_u16(&bytes[0..2], big_endian),
+            day_of_year: read_u16(&bytes[2..4], big_endian),
+            hour: bytes[4],
+            minute: bytes[5],
+            second: bytes[6],
+            fract: read_u16(&bytes[8..10], big_endian),
+        }
+    }
+
+    /// Converts to a DateTime, adding the given microseconds. A second of 60 is
+    /// treated as a leap second.
+    pub fn to_utc(&self, microsecond: i64) -> Result<DateTime<Utc>, MSeedError> {
+        let bad_time = || MSeedError::MSeed2Parse(format!("invalid BTIME {:?}", self));
+        let date = NaiveDate::from_yo_opt(self.year as i32, self.day_of_year as u32)
+            .ok_or_else(bad_time)?;
+        let (second, leap_nanos) = if self.second == 60 {
+            (59, 1_000_000_000)
+        } else {
+            (self.second as u32, 0)
+        };
+        let time = date
+            .and_hms_nano_opt(
+                self.hour as u32,
+                self.minute as u32,
+                second,
+                leap_nanos + self.fract as u32 * 100_000,
+            )
+            .ok_or_else(bad_time)?;
+        Ok(Utc.from_utc_datetime(&time) + Duration::microseconds(microsecond))
+    }
+}
+
+/// The fixed section of data header of a miniSEED 2 record.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MSeed2Header {
+    pub sequence_number: String,
+    pub quality_indicator: u8,
+    pub station: String,
+    pub location: String,
+    pub channel: String,
+    pub network: String,
+    pub start: BTime,
+    pub num_samples: u16,
+    pub sample_rate_factor: i16,
+    pub sample_rate_multiplier: i16,
+    pub activity_flags: u8,
+    pub io_clock_flags: u8,
+    pub data_quality_flags: u8,
+    pub num_blockettes: u8,
+    pub time_correction: i32,
+    pub data_offset: u16,
+    pub first_blockette: u16,
+    pub big_endian: bool,
+}
+
+impl MSeed2Header {
+    /// Parses the 48 byte fixed header, detecting byte order from the year and day.
+    pub fn parse(bytes: &[u8]) -> Result<MSeed2Header, MSeedError> {
+        if bytes.len() < MSEED2_FIXED_HEADER_SIZE {
+            return Err(MSeedError::InsufficientBytes(
+                bytes.len(),
+                MSEED2_FIXED_HEADER_SIZE,
+            ));
+        }
+        let quality_indicator = bytes[6];
+        if !matches!(quality_indicator, b'D' | b'R' | b'Q' | b'M') {
+            return Err(MSeedError::MSeed2Parse(format!(
+                "data quality indicator must be one of D, R, Q or M but was {}",
+                quality_indicator
+            )));
+        }
+        let big_endian = if is_valid_year_day(&bytes[20..24], true) {
+            true
+        } else if is_valid_year_day(&bytes[20..24], false) {
+            false
+        } else {
+            return Err(MSeedError::MSeed2Parse(String::from(
+                "cannot determine byte order, start year and day invalid",
+            )));
+        };
+        Ok(MSeed2Header {
+            sequence_number: ascii_field(&bytes[0..6])?,
+            quality_indicator,
+            station: ascii_field(&bytes[8..13])?,
+            location: ascii_field(&bytes[13..15])?,
+            channel: ascii_field(&bytes[15..18])?,
+            network: ascii_field(&bytes[18..20])?,
+            start: BTime::parse(&bytes[20..30], big_endian),
+            num_samples: read_u16(&bytes[30..32], big_endian),
+            sample_rate_factor: read_u16(&bytes[32..34], big_endian) as i16,
+            sample_rate_multiplier: read_u16(&bytes[34..36], big_endian) as i16,
+            activity_flags: bytes[36],
+            io_clock_flags: bytes[37],
+            data_quality_flags: bytes[38],
+            num_blockettes: bytes[39],
+            time_correction: read_u32(&bytes[40..44], big_endian) as i32,
+            data_offset: read_u16(&bytes[44..46], big_endian),
+            first_blockette: read_u16(&bytes[46..48], big_endian),
+            big_endian,
+        })
+    }
+
+    /// Nominal sample rate in samples per second calculated from the factor and multiplier.
+    pub fn nominal_sample_rate(&self) -> f64 {
+        let factor = self.sample_rate_factor as f64;
+        let multiplier = self.sample_rate_multiplier as f64;
+        if factor > 0.0 && multiplier > 0.0 {
+            factor * multiplier
+        } else if factor > 0.0 && multiplier < 0.0 {
+            -factor / multiplier
+        } else if factor < 0.0 && multiplier > 0.0 {
+            -multiplier / factor
+        } else if factor < 0.0 && multiplier < 0.0 {
+            1.0 / (factor * multiplier)
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Blockette 100, sample rate.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Blockette100 {
+    pub sample_rate: f32,
+    pub flags: u8,
+}
+
+/// Blockette 1000, data only SEED.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Blockette1000 {
+    pub encoding: u8,
+    pub word_order: u8,
+    pub record_length_exponent: u8,
+}
+
+impl Blockette1000 {
+    pub fn record_length(&self) -> usize {
+        1_usize << self.record_length_exponent
+    }
+}
+
+/// Blockette 1001, data extension.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Blockette1001 {
+    pub timing_quality: u8,
+    pub microsecond: i8,
+    pub frame_count: u8,
+}
+
+/// Blockette 500, timing.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Blockette500 {
+    pub vco_correction: f32,
+    pub exception_time: BTime,
+    pub microsecond: i8,
+    pub reception_quality: u8,
+    pub exception_count: u32,
+    pub exception_type: String,
+    pub clock_model: String,
+    pub clock_status: String,
+}
+
+/// A miniSEED 2 record with the fixed header, the blockettes this crate understands and the
+/// raw data bytes, in the byte order of the record. Other blockettes are skipped.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MSeed2Record {
+    pub header: MSeed2Header,
+    pub b100: Option<Blockette100>,
+    pub b1000: Blockette1000,
+    pub b1001: Option<Blockette1001>,
+    pub b500: Vec<Blockette500>,
+    pub data: Vec<u8>,
+}
+
+impl MSeed2Record {
+    /// Read a single miniSEED 2 record. Blockette 1000 is required to know the record length.
+    pub fn from_reader<R: Read>(reader: &mut R) -> Result<MSeed2Record, MSeedError> {
+        let mut buffer = vec![0; MSEED2_FIXED_HEADER_SIZE];
+        reader.read_exact(&mut buffer)?;
+        let header = MSeed2Header::parse(&buffer)?;
+        let be = header.big_endian;
+        let mut b100 = None;
+        let mut b1000 = None;
+        let mut b1001 = None;
+        let mut b500 = Vec::new();
+        let mut offset = header.first_blockette as usize;
+        let mut num_read = 0;
+        while offset != 0 && num_read < header.num_blockettes {
+            if offset < MSEED2_FIXED_HEADER_SIZE {
+                return Err(MSeedError::MSeed2Parse(format!(
+                    "blockette offset {} inside fixed header",
+                    offset
+                )));
+            }
+            fill_to(reader, &mut buffer, offset + 4, &b1000)?;
+            let blockette_type = read_u16(&buffer[offset..offset + 2], be);
+            let next = read_u16(&buffer[offset + 2..offset + 4], be) as usize;
+            let size = match blockette_type {
+                100 => 12,
+                500 => 200,
+                1000 | 1001 => 8,
+                _ => 4,
+            };
+            fill_to(reader, &mut buffer, offset + size, &b1000)?;
+            let b = &buffer[offset..offset + size];
+            match blockette_type {
+                100 => {
+                    b100 = Some(Blockette100 {
+                        sample_rate: f32::from_bits(read_u32(&b[4..8], be)),
+                        flags: b[8],
+                    })
+                }
+                1000 => {
+                    if !MSEED2_RECORD_LENGTH_EXPONENTS.contains(&b[6]) {
+                        return Err(MSeedError::MSeed2Parse(format!(
+                            "blockette 1000 record length exponent {} not in {:?}",
+                            b[6], MSEED2_RECORD_LENGTH_EXPONENTS
+                        )));
+                    }
+                    b1000 = Some(Blockette1000 {
+                        encoding: b[4],
+                        word_order: b[5],
+                        record_length_exponent: b[6],
+                    })
+                }
+                1001 => {
+                    b1001 = Some(Blockette1001 {
+                        timing_quality: b[4],
+                        microsecond: b[5] as i8,
+                        frame_count: b[7],
+                    })
+                }
+                500 => b500.push(Blockette500 {
+                    vco_correction: f32::from_bits(read_u32(&b[4..8], be)),
+                    exception_time: BTime::parse(&b[8..18], be),
+                    microsecond: b[18] as i8,
+                    reception_quality: b[19],
+                    exception_count: read_u32(&b[20..24], be),
+                    exception_type: ascii_field(&b[24..40])?,
+                    clock_model: ascii_field(&b[40..72])?,
+                    clock_status: ascii_field(&b[72..200])?,
+                }),
+                _ => (),
+            }
+            num_read += 1;
+            if next != 0 && next <= offset {
+                return Err(MSeedError::MSeed2Parse(format!(
+                    "next blockette offset {} not after current {}",
+                    next, offset
+                )));
+            }
+            offset = next;
+        }
+        let b1000 = b1000.ok_or_else(|| {
+            MSeedError::MSeed2Parse(String::from(
+                "blockette 1000 required to determine record length",
+            ))
+        })?;
+        let record_length = b1000.record_length();
+        let data_offset = header.data_offset as usize;
+        if record_length < buffer.len() || data_offset > record_length {
+            return Err(MSeedError::MSeed2Parse(format!(
+                "record length {} inconsistent with blockettes or data offset {}",
+                record_length, data_offset
+            )));
+        }
+        fill_to(reader, &mut buffer, record_length, &Some(b1000.clone()))?;
+        let data = if data_offset >= MSEED2_FIXED_HEADER_SIZE {
+            buffer[data_offset..record_length].to_vec()
+        } else {
+            Vec::new()
+        };
+        Ok(MSeed2Record {
+            header,
+            b100,
+            b1000,
+            b1001,
+            b500,
+            data,
+        })
+    }
+
+    pub fn record_length(&self) -> usize {
+        self.b1000.record_length()
+    }
+
+    /// Data encoding from blockette 1000. The miniSEED 2 codes for the encodings in
+    /// DataEncoding have the same values.
+    pub fn encoding(&self) -> DataEncoding {
+        DataEncoding::from_int(self.b1000.encoding)
+    }
+
+    /// True if data words are big endian, from blockette 1000.
+    pub fn is_data_big_endian(&self) -> bool {
+        self.b1000.word_order == 1
+    }
+
+    /// Start time including the microseconds from blockette 1001. The header time
+    /// correction is applied if the activity flags show it has not been already.
+    pub fn start_time(&self) -> Result<DateTime<Utc>, MSeedError> {
+        let microsecond = match &self.b1001 {
+            Some(b1001) => b1001.microsecond as i64,
+            None => 0,
+        };
+        let mut start = self.header.start.to_utc(microsecond)?;
+        if self.header.time_correction != 0 && self.header.activity_flags & 0x02 == 0 {
+            start += Duration::microseconds(self.header.time_correction as i64 * 100);
+        }
+        Ok(start)
+    }
+
+    /// Sample rate in samples per second, from blockette 100 if present, otherwise from the
+    /// header factor and multiplier.
+    pub fn sample_rate(&self) -> f64 {
+        match &self.b100 {
+            Some(b100) => b100.sample_rate as f64,
+            None => self.header.nominal_sample_rate(),
+        }
+    }
+
+    /// Converts to a miniSEED 3 record following the FDSN miniSEED 2 to 3 mapping.
+    /// Flags without a place in the miniSEED 3 header are stored in the FDSN extra headers.
+    pub fn to_mseed3(&self) -> Result<MSeed3Record, MSeedError> {
+        let encoding = self.encoding();
+        let sample_rate = self.sample_rate();
+        let mut header = MSeed3Header::new(
+            self.start_time()?,
+            encoding.clone(),
+            sample_rate_to_rate_period(sample_rate),
+            self.header.num_samples as usize,
+        );
+        header.publication_version = match self.header.quality_indicator {
+            b'R' => 1,
+            b'D' => 2,
+            b'Q' => 3,
+            b'M' => 4,
+            _ => 0,
+        };
+        let act = self.header.activity_flags;
+        let io = self.header.io_clock_flags;
+        let dq = self.header.data_quality_flags;
+        let mut flags = HeaderFlags::new();
+        flags.set_calibration_signals_present(act & 0x01 != 0);
+        flags.set_time_tag_questionable(dq & 0x80 != 0);
+        flags.set_clock_locked(io & 0x20 != 0);
+        header.set_flags(flags);
+
+        let identifier = SourceIdentifier::Fdsn(FdsnSourceIdentifier::from_nslc(
+            &self.header.network,
+            &self.header.station,
+            &self.header.location,
+            &self.header.channel,
+        )?);
+        let encoded_data = EncodedTimeseries::Raw(self.data_to_mseed3(&encoding)?);
+        let extra_headers = self.fdsn_extra_headers()?;
+        let extra_headers = if extra_headers.is_empty() {
+            None
+        } else {
+            let mut eh = Map::new();
+            eh.insert(
+                String::from(FDSN_EXTRA_HEADERS),
+                Value::Object(extra_headers),
+            );
+            Some(eh)
+        };
+        Ok(MSeed3Record::new(
+            header,
+            identifier,
+            extra_headers,
+            encoded_data,
+        ))
+    }
+
+    /// Converts a miniSEED 3 record into one or more miniSEED 2 records of the given record
+    /// length, usually 512 or 4096, each with blockettes 1000 and 1001. Data is written big
+    /// endian, re-encoded and split as needed. Errors if the identifier, sample rate, start
+    /// time or encoding cannot be represented in miniSEED 2. Start times of records after the
+    /// first are rounded to the microsecond.
+    pub fn from_mseed3(
+        rec: &MSeed3Record,
+        record_length: usize,
+    ) -> Result<Vec<MSeed2Record>, MSeedError> {
+        if !record_length.is_power_of_two() || !(256..=65536).contains(&record_length) {
+            return Err(MSeedError::MSeed2Unrepresentable(format!(
+                "record length {} must be a power of two between 256 and 65536",
+                record_length
+            )));
+        }
+        let (network, station, location, channel) = sid_to_nslc(&rec.identifier)?;
+        let (sample_rate_factor, sample_rate_multiplier) =
+            rate_factor_multiplier(rec.header.sample_rate_period)?;
+        if !rec.header.nanosecond.is_multiple_of(1000) {
+            return Err(MSeedError::MSeed2Unrepresentable(format!(
+                "start time nanoseconds {} not a whole number of microseconds",
+                rec.header.nanosecond
+            )));
+        }
+        let empty = Map::new();
+        let fdsn = rec.get_fdsn_headers().unwrap_or(&empty);
+        let is_set = |group: &str, key: &str| {
+            fdsn.get(group).and_then(|g| g.get(key)) == Some(&Value::Bool(true))
+        };
+        let time_correction = match fdsn.get("Time").and_then(|t| t.get("Correction")) {
+            Some(v) => (v.as_f64().unwrap_or(0.0) * 10000.0).round() as i32,
+            None => 0,
+        };
+        let timing_quality = fdsn
+            .get("Time")
+            .and_then(|t| t.get("Quality"))
+            .and_then(|v| v.as_u64())
+            .unwrap_or(0)
+            .min(100) as u8;
+
+        let flags = rec.header.get_flags();
+        let mut activity_flags = flags.calibration_signals_present() as u8;
+        if time_correction != 0 {
+            activity_flags |= 0x02; // correction already applied to start
+        }
+        match fdsn
+            .get("Time")
+            .and_then(|t| t.get("LeapSecond"))
+            .and_then(|v| v.as_i64())
+        {
+            Some(leap) if leap > 0 => activity_flags |= 0x10,
+            Some(leap) if leap < 0 => activity_flags |= 0x20,
+            _ => (),
+        }
+        if is_set("Event", "InProgress") {
+            activity_flags |= 0x40;
+        }
+        let mut io_clock_flags = 0_u8;
+        for (bit, key) in [
+            (0x01, "StationVolumeParityError"),
+            (0x02, "LongRecordRead"),
+            (0x04, "ShortRecordRead"),
+        ] {
+            if is_set("Flags", key) {
+                io_clock_flags |= bit;
+            }
+        }
+        if flags.clock_locked() {
+            io_clock_flags |= 0x20;
+        }
+        let mut data_quality_flags = 0_u8;
+        for (bit, key) in [
+            (0x01, "AmplifierSaturation"),
+            (0x02, "DigitizerClipping"),
+            (0x04, "Spikes"),
+            (0x08, "Glitches"),
+            (0x10, "MissingData"),
+            (0x20, "TelemetrySyncError"),
+            (0x40, "FilterCharging"),
+        ] {
+            if is_set("Flags", key) {
+                data_quality_flags |= bit;
+            }
+        }
+        if flags.time_tag_questionable() {
+            data_quality_flags |= 0x80;
+        }
+        let quality_indicator = match rec.header.publication_version {
+            1 => b'R',
+            3 => b'Q',
+            4 => b'M',
+            _ => b'D',
+        };
+        let first_sequence = fdsn.get("Sequence").and_then(|v| v.as_u64()).unwrap_or(1);
+
+        let chunks = split_data(rec, record_length - MSEED2_DATA_OFFSET)?;
+        let mut records = Vec::with_capacity(chunks.len());
+        for (idx, (offset, num_samples, data)) in chunks.iter().enumerate() {
+            let (start, microsecond) = btime_from_utc(
+                rec.header.try_start_as_utc()?
+                    + sample_offset(rec.header.sample_rate_period, *offset),
+            );
+            let mut act = activity_flags;
+            let mut io = io_clock_flags;
+            if idx == 0 {
+                if is_set("Event", "Begin") {
+                    act |= 0x04;
+                }
+                if is_set("Flags", "StartOfTimeSeries") {
+                    io |= 0x08;
+                }
+            }
+            if idx == chunks.len() - 1 {
+                if is_set("Event", "End") {
+                    act |= 0x08;
+                }
+                if is_set("Flags", "EndOfTimeSeries") {
+                    io |= 0x10;
+                }
+            }
+            let frame_count = match rec.header.encoding {
+                DataEncoding::STEIM1 | DataEncoding::STEIM2 => u8::try_from(data.len() / 64)
+                    .map_err(|_| {
+                        MSeedError::MSeed2Unrepresentable(format!(
+                            "{} steim frames do not fit in blockette 1001",
+                            data.len() / 64
+                        ))
+                    })?,
+                _ => 0,
+            };
+            records.push(MSeed2Record {
+                header: MSeed2Header {
+                    sequence_number: format!("{:06}", (first_sequence + idx as u64) % 1_000_000),
+                    quality_indicator,
+                    station: station.clone(),
+                    location: location.clone(),
+                    channel: channel.clone(),
+                    network: network.clone(),
+                    start,
+                    num_samples: *num_samples as u16,
+                    sample_rate_factor,
+                    sample_rate_multiplier,
+                    activity_flags: act,
+                    io_clock_flags: io,
+                    data_quality_flags,
+                    num_blockettes: 2,
+                    time_correction,
+                    data_offset: MSEED2_DATA_OFFSET as u16,
+                    first_blockette: MSEED2_FIXED_HEADER_SIZE as u16,
+                    big_endian: true,
+                },
+                b100: None,
+                b1000: Blockette1000 {
+                    encoding: rec.header.encoding.value(),
+                    word_order: 1,
+                    record_length_exponent: record_length.trailing_zeros() as u8,
+                },
+                b1001: Some(Blockette1001 {
+                    timing_quality,
+                    microsecond,
+                    frame_count,
+                }),
+                b500: Vec::new(),
+                data: data.clone(),
+            });
+        }
+        Ok(records)
+    }
+
+    /// Writes the record, padded to the record length. Blockettes are written in the order
+    /// 1000, 1001, 100 then any 500, followed by the data.
+    pub fn write_to<W>(&self, buf: &mut BufWriter<W>) -> Result<(), MSeedError>
+    where
+        W: std::io::Write,
+    {
+        buf.write_all(&self.to_bytes()?)?;
+        Ok(())
+    }
+
+    /// The bytes of the record, padded to the record length.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, MSeedError> {
+        let be = self.header.big_endian;
+        let h = &self.header;
+        let mut blockettes: Vec<Vec<u8>> = Vec::new();
+        let mut b = Vec::with_capacity(8);
+        b.extend_from_slice(&write_u16(1000, be));
+        b.extend_from_slice(&[0, 0]);
+        b.extend_from_slice(&[
+            self.b1000.encoding,
+            self.b1000.word_order,
+            self.b1000.record_length_exponent,
+            0,
+        ]);
+        blockettes.push(b);
+        if let Some(b1001) = &self.b1001 {
+            let mut b = Vec::with_capacity(8);
+            b.extend_from_slice(&write_u16(1001, be));
+            b.extend_from_slice(&[0, 0]);
+            b.extend_from_slice(&[
+                b1001.timing_quality,
+                b1001.microsecond as u8,
+                0,
+                b1001.frame_count,
+            ]);
+            blockettes.push(b);
+        }
+        if let Some(b100) = &self.b100 {
+            let mut b = Vec::with_capacity(12);
+            b.extend_from_slice(&write_u16(100, be));
+            b.extend_from_slice(&[0, 0]);
+            b.extend_from_slice(&write_u32(b100.sample_rate.to_bits(), be));
+            b.extend_from_slice(&[b100.flags, 0, 0, 0]);
+            blockettes.push(b);
+        }
+        for b500 in &self.b500 {
+            let mut b = Vec::with_capacity(200);
+            b.extend_from_slice(&write_u16(500, be));
+            b.extend_from_slice(&[0, 0]);
+            b.extend_from_slice(&write_u32(b500.vco_correction.to_bits(), be));
+            b.extend_from_slice(&write_btime(&b500.exception_time, be));
+            b.extend_from_slice(&[b500.microsecond as u8, b500.reception_quality]);
+            b.extend_from_slice(&write_u32(b500.exception_count, be));
+            b.extend_from_slice(&pad_field(&b500.exception_type, 16, "exception type")?);
+            b.extend_from_slice(&pad_field(&b500.clock_model, 32, "clock model")?);
+            b.extend_from_slice(&pad_field(&b500.clock_status, 128, "clock status")?);
+            blockettes.push(b);
+        }
+        let mut offset = MSEED2_FIXED_HEADER_SIZE;
+        let num_blockettes = blockettes.len();
+        for (idx, b) in blockettes.iter_mut().enumerate() {
+            offset += b.len();
+            let next = if idx + 1 < num_blockettes { offset } else { 0 };
+            b[2..4].copy_from_slice(&write_u16(next as u16, be));
+        }
+        let blockette_end = offset;
+        let data_offset = if h.data_offset as usize >= blockette_end {
+            h.data_offset as usize
+        } else {
+            blockette_end.div_ceil(64) * 64
+        };
+        let record_length = self.record_length();
+        if data_offset + self.data.len() > record_length {
+            return Err(MSeedError::MSeed2Unrepresentable(format!(
+                "{} bytes of headers and data do not fit in record length {}",
+                data_offset + self.data.len(),
+                record_length
+            )));
+        }
+
+        let mut out = Vec::with_capacity(record_length);
+        let sequence_number = format!("{:0>6}", h.sequence_number);
+        out.extend_from_slice(&pad_field(&sequence_number, 6, "sequence number")?);
+        out.extend_from_slice(&[h.quality_indicator, b' ']);
+        out.extend_from_slice(&pad_field(&h.station, 5, "station")?);
+        out.extend_from_slice(&pad_field(&h.location, 2, "location")?);
+        out.extend_from_slice(&pad_field(&h.channel, 3, "channel")?);
+        out.extend_from_slice(&pad_field(&h.network, 2, "network")?);
+        out.extend_from_slice(&write_btime(&h.start, be));
+        out.extend_from_slice(&write_u16(h.num_samples, be));
+        out.extend_from_slice(&write_u16(h.sample_rate_factor as u16, be));
+        out.extend_from_slice(&write_u16(h.sample_rate_multiplier as u16, be));
+        out.extend_from_slice(&[
+            h.activity_flags,
+            h.io_clock_flags,
+            h.data_quality_flags,
+            num_blockettes as u8,
+        ]);
+        out.extend_from_slice(&write_u32(h.time_correction as u32, be));
+        let data_offset_field = if self.data.is_empty() { 0 } else { data_offset };
+        out.extend_from_slice(&write_u16(data_offset_field as u16, be));
+        out.extend_from_slice(&write_u16(MSEED2_FIXED_HEADER_SIZE as u16, be));
+        for b in blockettes {
+            out.extend_from_slice(&b);
+        }
+        out.resize(data_offset, 0);
+        out.extend_from_slice(&self.data);
+        out.resize(record_length, 0);
+        Ok(out)
+    }
+
+    /// Data bytes converted to miniSEED 3 byte order, little endian for primitive types and
+    /// big endian for Steim, and trimmed to the number of samples for primitive types.
+    fn data_to_mseed3(&self, encoding: &DataEncoding) -> Result<Vec<u8>, MSeedError> {
+        let big_endian = self.is_data_big_endian();
+        let (word_size, little_endian_in_mseed3) = match encoding {
+            DataEncoding::INT16 => (2, true),
+            DataEncoding::INT32 | DataEncoding::FLOAT32 => (4, true),
+            DataEncoding::FLOAT64 => (8, true),
+            DataEncoding::STEIM1 | DataEncoding::STEIM2 | DataEncoding::STEIM3 => (4, false),
+            _ => return Ok(self.data.clone()),
+        };
+        let mut data = if little_endian_in_mseed3 {
+            let length = word_size * self.header.num_samples as usize;
+            if length > self.data.len() {
+                return Err(MSeedError::MSeed2Parse(format!(
+                    "{} samples need {} bytes but only {} in record",
+                    self.header.num_samples,
+                    length,
+                    self.data.len()
+                )));
+            }
+            self.data[..length].to_vec()
+        } else {
+            // whole steim frames
+            self.data[..self.data.len() / 64 * 64].to_vec()
+        };
+        if big_endian == little_endian_in_mseed3 {
+            for word in data.chunks_exact_mut(word_size) {
+                word.reverse();
+            }
+        }
+        Ok(data)
+    }
+
+    fn fdsn_extra_headers(&self) -> Result<Map<String, Value>, MSeedError> {
+        let act = self.header.activity_flags;
+        let io = self.header.io_clock_flags;
+        let dq = self.header.data_quality_flags;
+        let mut fdsn = Map::new();
+        let mut time = Map::new();
+        if let Some(b1001) = &self.b1001 {
+            time.insert(String::from("Quality"), json!(b1001.timing_quality));
+        }
+        if self.header.time_correction != 0 {
+            time.insert(
+                String::from("Correction"),
+                json!(self.header.time_correction as f64 * 0.0001),
+            );
+        }
+        if act & 0x10 != 0 {
+            time.insert(String::from("LeapSecond"), json!(1));
+        } else if act & 0x20 != 0 {
+            time.insert(String::from("LeapSecond"), json!(-1));
+        }
+        if !self.b500.is_empty() {
+            let mut exceptions = Vec::new();
+            for b in &self.b500 {
+                let exception_time = b.exception_time.to_utc(b.microsecond as i64)?;
+                exceptions.push(json!({
+                    "VCOCorrection": b.vco_correction,
+                    "Time": exception_time.format("%Y-%m-%dT%H:%M:%S%.6fZ").to_string(),
+                    "ReceptionQuality": b.reception_quality,
+                    "Count": b.exception_count,
+                    "Type": b.exception_type,
+                    "ClockStatus": b.clock_status,
+                }));
+            }
+            time.insert(String::from("Exception"), Value::Array(exceptions));
+            let clock_model = &self.b500[self.b500.len() - 1].clock_model;
+            if !clock_model.is_empty() {
+                fdsn.insert(String::from("Clock"), json!({ "Model": clock_model }));
+            }
+        }
+        if !time.is_empty() {
+            fdsn.insert(String::from("Time"), Value::Object(time));
+        }
+
+        let mut event = Map::new();
+        insert_flag(&mut event, "Begin", act & 0x04);
+        insert_flag(&mut event, "End", act & 0x08);
+        insert_flag(&mut event, "InProgress", act & 0x40);
+        if !event.is_empty() {
+            fdsn.insert(String::from("Event"), Value::Object(event));
+        }
+
+        let mut flags = Map::new();
+        insert_flag(&mut flags, "StationVolumeParityError", io & 0x01);
+        insert_flag(&mut flags, "LongRecordRead", io & 0x02);
+        insert_flag(&mut flags, "ShortRecordRead", io & 0x04);
+        insert_flag(&mut flags, "StartOfTimeSeries", io & 0x08);
+        insert_flag(&mut flags, "EndOfTimeSeries", io & 0x10);
+        insert_flag(&mut flags, "AmplifierSaturation", dq & 0x01);
+        insert_flag(&mut flags, "DigitizerClipping", dq & 0x02);
+        insert_flag(&mut flags, "Spikes", dq & 0x04);
+        insert_flag(&mut flags, "Glitches", dq & 0x08);
+        insert_flag(&mut flags, "MissingData", dq & 0x10);
+        insert_flag(&mut flags, "TelemetrySyncError", dq & 0x20);
+        insert_flag(&mut flags, "FilterCharging", dq & 0x40);
+        if !flags.is_empty() {
+            fdsn.insert(String::from("Flags"), Value::Object(flags));
+        }
+
+        if let Ok(sequence) = self.header.sequence_number.parse::<u64>() {
+            if sequence != 0 {
+                fdsn.insert(String::from("Sequence"), json!(sequence));
+            }
+        }
+        Ok(fdsn)
+    }
+}
+
+impl fmt::Display for MSeed2Record {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "  {}_{}_{}_{}, {}, {} bytes, {} samples, {} sps",
+            self.header.network,
+            self.header.station,
+            self.header.location,
+            self.header.channel,
+            self.header.quality_indicator as char,
+            self.record_length(),
+            self.header.num_samples,
+            self.sample_rate()
+        )
+    }
+}
+
+/// Iterator over the miniSEED 2 records in a Read. Iteration stops after the first error.
+pub struct MSeed2Reader<R: Read> {
+    reader: R,
+    done: bool,
+}
+
+impl<R: Read> MSeed2Reader<R> {
+    pub fn new(reader: R) -> MSeed2Reader<R> {
+        MSeed2Reader {
+            reader,
+            done: false,
+        }
+    }
+}
+
+impl<R: Read> Iterator for MSeed2Reader<R> {
+    type Item = Result<MSeed2Record, MSeedError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        // check for end of input before a record starts
+        let mut first = [0_u8; 1];
+        loop {
+            match self.reader.read(&mut first) {
+                Ok(0) => {
+                    self.done = true;
+                    return None;
+                }
+                Ok(_) => break,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(MSeedError::from(e)));
+                }
+            }
+        }
+        let result = MSeed2Record::from_reader(&mut (&first[..]).chain(&mut self.reader));
+        if result.is_err() {
+            self.done = true;
+        }
+        Some(result)
+    }
+}
+
+/// Converts a sample rate to the miniSEED 3 sample_rate_period. Rates below 1 sample per
+/// second that are a whole number of seconds per sample are stored as a negative period.
+pub(crate) fn sample_rate_to_rate_period(sample_rate: f64) -> f64 {
+    if sample_rate > 0.0 && sample_rate < 1.0 {
+        let period = 1.0 / sample_rate;
+        if (period - period.round()).abs() < 1e-6 {
+            return -period.round();
+        }
+    }
+    sample_rate
+}
+
+/// Write a miniSEED 3 record as one or more miniSEED 2 records, returning the number of
+/// records written. See MSeed2Record::from_mseed3.
+pub fn write_mseed2<W>(
+    rec: &MSeed3Record,
+    record_length: usize,
+    buf: &mut BufWriter<W>,
+) -> Result<usize, MSeedError>
+where
+    W: std::io::Write,
+{
+    let records = MSeed2Record::from_mseed3(rec, record_length)?;
+    for r in &records {
+        r.write_to(buf)?;
+    }
+    Ok(records.len())
+}
+
+/// Splits the data of the record into (sample offset, number of samples, bytes) for each
+/// miniSEED 2 record, with at most max_data_length bytes each.
+fn split_data(
+    rec: &MSeed3Record,
+    max_data_length: usize,
+) -> Result<Vec<(usize, usize, Vec<u8>)>, MSeedError> {
+    let mut chunks = Vec::new();
+    let max_samples = |sample_size: usize| (max_data_length / sample_size).min(u16::MAX as usize);
+    match (&rec.header.encoding, rec.decode_samples()?) {
+        (DataEncoding::STEIM1, DecodedTimeseries::Int32(samples))
+        | (DataEncoding::STEIM2, DecodedTimeseries::Int32(samples)) => {
+            let frames = max_data_length / 64;
+            let mut offset = 0;
+            while offset < samples.len() {
+                let bias = if offset == 0 {
+                    samples[0]
+                } else {
+                    samples[offset - 1]
+                };
+                let end = samples.len().min(offset + u16::MAX as usize);
+                let frame_block = if matches!(rec.header.encoding, DataEncoding::STEIM1) {
+                    steim1::encode_with_bias(&samples[offset..end], frames, bias)?
+                } else {
+                    steim2::encode_with_bias(&samples[offset..end], frames, bias)?
+                };
+                chunks.push((
+                    offset,
+                    frame_block.num_samples,
+                    frame_block.get_encoded_data()?,
+                ));
+                offset += frame_block.num_samples;
+            }
+        }
+        (DataEncoding::INT16, DecodedTimeseries::Int16(samples)) => {
+            for (i, c) in samples.chunks(max_samples(2)).enumerate() {
+                let data = c.iter().flat_map(|v| v.to_be_bytes()).collect();
+                chunks.push((i * max_samples(2), c.len(), data));
+            }
+        }
+        (DataEncoding::INT32, DecodedTimeseries::Int32(samples)) => {
+            for (i, c) in samples.chunks(max_samples(4)).enumerate() {
+                let data = c.iter().flat_map(|v| v.to_be_bytes()).collect();
+                chunks.push((i * max_samples(4), c.len(), data));
+            }
+        }
+        (DataEncoding::FLOAT32, DecodedTimeseries::Float32(samples)) => {
+            for (i, c) in samples.chunks(max_samples(4)).enumerate() {
+                let data = c.iter().flat_map(|v| v.to_be_bytes()).collect();
+                chunks.push((i * max_samples(4), c.len(), data));
+            }
+        }
+        (DataEncoding::FLOAT64, DecodedTimeseries::Float64(samples)) => {
+            for (i, c) in samples.chunks(max_samples(8)).enumerate() {
+                let data = c.iter().flat_map(|v| v.to_be_bytes()).collect();
+                chunks.push((i * max_samples(8), c.len(), data));
+            }
+        }
+        (DataEncoding::TEXT, DecodedTimeseries::Text(text)) => {
+            for (i, c) in text.as_bytes().chunks(max_samples(1)).enumerate() {
+                chunks.push((i * max_samples(1), c.len(), c.to_vec()));
+            }
+        }
+        (encoding, _) => {
+            return Err(MSeedError::MSeed2Unrepresentable(format!(
+                "data encoding {}",
+                encoding
+            )))
+        }
+    }
+    if chunks.is_empty() {
+        chunks.push((0, 0, Vec::new()));
+    }
+    Ok(chunks)
+}
+
+/// Splits an FDSN source identifier into SEED network, station, location and channel codes.
+fn sid_to_nslc(
+    identifier: &SourceIdentifier,
+) -> Result<(String, String, String, String), MSeedError> {
+    let sid = match identifier {
+        SourceIdentifier::Fdsn(sid) => sid,
+        SourceIdentifier::Raw(s) => {
+            return Err(MSeedError::MSeed2Unrepresentable(format!(
+                "identifier {} is not an FDSN source identifier",
+                s
+            )))
+        }
+    };
+    let unrepresentable = || {
+        MSeedError::MSeed2Unrepresentable(format!(
+            "identifier {} does not fit SEED network, station, location and channel codes",
+            sid
+        ))
+    };
+    let (network, station, location, channel) = sid.to_nslc().map_err(|_| unrepresentable())?;
+    if network.len() > 2 || station.len() > 5 || location.len() > 2 {
+        return Err(unrepresentable());
+    }
+    Ok((network, station, location, channel))
+}
+
+/// Finds a miniSEED 2 sample rate factor and multiplier for the miniSEED 3 sample_rate_period.
+fn rate_factor_multiplier(sample_rate_period: f64) -> Result<(i16, i16), MSeedError> {
+    let max = i16::MAX as f64;
+    if sample_rate_period == 0.0 {
+        return Ok((0, 0));
+    }
+    let rate = if sample_rate_period < 0.0 {
+        let period = -sample_rate_period;
+        if period.fract() == 0.0 && period <= max {
+            return Ok((-(period as i16), 1));
+        }
+        1.0 / period
+    } else {
+        sample_rate_period
+    };
+    if rate.fract() == 0.0 && rate <= max {
+        return Ok((rate as i16, 1));
+    }
+    for multiplier in 2..=i16::MAX {
+        let factor = rate * multiplier as f64;
+        if factor > max {
+            break;
+        }
+        if factor >= 1.0 && ((factor.round() / multiplier as f64) - rate).abs() < rate * 1e-9 {
+            return Ok((factor.round() as i16, -multiplier));
+        }
+    }
+    Err(MSeedError::MSeed2Unrepresentable(format!(
+        "sample rate {} as factor and multiplier",
+        sample_rate_period
+    )))
+}
+
+/// BTIME and microseconds for a time, rounded to the microsecond. The BTIME is rounded to
+/// the nearest 100 microseconds so the blockette 1001 offset is in the range -50..=49.
+fn btime_from_utc(time: DateTime<Utc>) -> (BTime, i8) {
+    let time = time + Duration::nanoseconds(500);
+    let micros = (time.nanosecond() / 1000 % 100) as i8;
+    let microsecond = if micros >= 50 { micros - 100 } else { micros };
+    let time = time - Duration::microseconds(microsecond as i64);
+    let nanos = time.nanosecond();
+    // chrono represents a leap second as nanoseconds past 1e9
+    let (second, nanos) = if nanos >= 1_000_000_000 {
+        (60, nanos - 1_000_000_000)
+    } else {
+        (time.second() as u8, nanos)
+    };
+    let btime = BTime {
+        year: time.year() as u16,
+        day_of_year: time.ordinal() as u16,
+        hour: time.hour() as u8,
+        minute: time.minute() as u8,
+        second,
+        fract: (nanos / 100_000) as u16,
+    };
+    (btime, microsecond)
+}
+
+fn insert_flag(map: &mut Map<String, Value>, key: &str, bit: u8) {
+    if bit != 0 {
+        map.insert(String::from(key), Value::Bool(true));
+    }
+}
+
+/// Reads more bytes into the buffer until it has at least len bytes. Fails if past the
+/// record length, when known.
+fn fill_to<R: Read>(
+    reader: &mut R,
+    buffer: &mut Vec<u8>,
+    len: usize,
+    b1000: &Option<Blockette1000>,
+) -> Result<(), MSeedError> {
+    if let Some(b1000) = b1000 {
+        if len > b1000.record_length() {
+            return Err(MSeedError::MSeed2Parse(format!(
+                "blockette past end of record, {} > {}",
+                len,
+                b1000.record_length()
+            )));
+        }
+    }
+    if buffer.len() < len {
+        let start = buffer.len();
+        buffer.resize(len, 0);
+        reader.read_exact(&mut buffer[start..])?;
+    }
+    Ok(())
+}
+
+fn is_valid_year_day(bytes: &[u8], big_endian: bool) -> bool {
+    let year = read_u16(&bytes[0..2], big_endian);
+    let day = read_u16(&bytes[2..4], big_endian);
+    (1900..=2100).contains(&year) && (1..=366).contains(&day)
+}
+
+/// Space padded ascii field, trimmed.
+fn ascii_field(bytes: &[u8]) -> Result<String, MSeedError> {
+    let s = std::str::from_utf8(bytes)?;
+    Ok(s.trim_matches(|c: char| c == ' ' || c == '\0').to_string())
+}
+
+fn read_u16(bytes: &[u8], big_endian: bool) -> u16 {
+    let b: [u8; 2] = bytes.try_into().unwrap();
+    if big_endian {
+        u16::from_be_bytes(b)
+    } else {
+        u16::from_le_bytes(b)
+    }
+}
+
+fn read_u32(bytes: &[u8], big_endian: bool) -> u32 {
+    let b: [u8; 4] = bytes.try_into().unwrap();
+    if big_endian {
+        u32::from_be_bytes(b)
+    } else {
+        u32::from_le_bytes(b)
+    }
+}
+
+/// Space padded ascii field of exactly the given length.
+fn pad_field(value: &str, len: usize, name: &str) -> Result<Vec<u8>, MSeedError> {
+    if value.len() > len || !value.is_ascii() {
+        return Err(MSeedError::MSeed2Unrepresentable(format!(
+            "{} '{}' longer than {} ascii characters",
+            name, value, len
+        )));
+    }
+    let mut bytes = value.as_bytes().to_vec();
+    bytes.resize(len, b' ');
+    Ok(bytes)
+}
+
+fn write_btime(time: &BTime, big_endian: bool) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(10);
+    bytes.extend_from_slice(&write_u16(time.year, big_endian));
+    bytes.extend_from_slice(&write_u16(time.day_of_year, big_endian));
+    bytes.extend_from_slice(&[time.hour, time.minute, time.second, 0]);
+    bytes.extend_from_slice(&write_u16(time.fract, big_endian));
+    bytes
+}
+
+fn write_u16(v: u16, big_endian: bool) -> [u8; 2] {
+    if big_endian {
+        v.to_be_bytes()
+    } else {
+        v.to_le_bytes()
+    }
+}
+
+fn write_u32(v: u32, big_endian: bool) -> [u8; 4] {
+    if big_endian {
+        v.to_be_bytes()
+    } else {
+        v.to_le_bytes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::record_packer::RecordPacker;
+
+    /// Builds a 512 byte big endian record, with blockettes 1000 and 1001.
+    fn build_mseed2(encoding: u8, data: &[u8], num_samples: u16) -> Vec<u8> {
+        let mut rec = Vec::new();
+        rec.extend_from_slice(b"000042D ");
+        rec.extend_from_slice(b"ANMO ");
+        rec.extend_from_slice(b"00");
+        rec.extend_from_slice(b"BHZ");
+        rec.extend_from_slice(b"IU");
+        rec.extend_from_slice(&2010_u16.to_be_bytes());
+        rec.extend_from_slice(&58_u16.to_be_bytes());
+        rec.extend_from_slice(&[6, 16, 11, 0]);
+        rec.extend_from_slice(&1234_u16.to_be_bytes()); // 0.1234 sec
+        rec.extend_from_slice(&num_samples.to_be_bytes());
+        rec.extend_from_slice(&20_i16.to_be_bytes());
+        rec.extend_from_slice(&1_i16.to_be_bytes());
+        rec.extend_from_slice(&[0x05, 0x20, 0x80, 2]); // act, io, dq, num blockettes
+        rec.extend_from_slice(&0_i32.to_be_bytes());
+        rec.extend_from_slice(&64_u16.to_be_bytes()); // data offset
+        rec.extend_from_slice(&48_u16.to_be_bytes()); // first blockette
+        assert_eq!(rec.len(), 48);
+        // b1000 at 48
+        rec.extend_from_slice(&1000_u16.to_be_bytes());
+        rec.extend_from_slice(&56_u16.to_be_bytes());
+        rec.extend_from_slice(&[encoding, 1, 9, 0]);
+        // b1001 at 56
+        rec.extend_from_slice(&1001_u16.to_be_bytes());
+        rec.extend_from_slice(&0_u16.to_be_bytes());
+        rec.extend_from_slice(&[90, 56_u8, 0, 1]);
+        assert_eq!(rec.len(), 64);
+        rec.extend_from_slice(data);
+        rec.resize(512, 0);
+        rec
+    }
+
+    #[test]
+    fn parse_header_and_blockettes() -> Result<(), MSeedError> {
+        let data: Vec<u8> = [1_i32, -2, 3]
+            .iter()
+            .flat_map(|v| v.to_be_bytes())
+            .collect();
+        let bytes = build_mseed2(3, &data, 3);
+        let rec = MSeed2Record::from_reader(&mut &bytes[..])?;
+        assert!(rec.header.big_endian);
+        assert_eq!(rec.header.network, "IU");
+        assert_eq!(rec.header.station, "ANMO");
+        assert_eq!(rec.header.location, "00");
+        assert_eq!(rec.header.channel, "BHZ");
+        assert_eq!(rec.record_length(), 512);
+        assert_eq!(rec.b1001.as_ref().unwrap().timing_quality, 90);
+        assert_eq!(rec.data.len(), 512 - 64);
+        assert!((rec.sample_rate() - 20.0).abs() < f64::EPSILON);
+        Ok(())
+    }
+
+    #[test]
+    fn bad_record_length_exponent() {
+        for exponent in [6_u8, 17, 40, 70] {
+            let mut bytes = build_mseed2(3, &[], 0);
+            bytes[54] = exponent;
+            assert!(
+                matches!(
+                    MSeed2Record::from_reader(&mut &bytes[..]),
+                    Err(MSeedError::MSeed2Parse(_))
+                ),
+                "exponent {}",
+                exponent
+            );
+        }
+    }
+
+    #[test]
+    fn convert_int32() -> Result<(), MSeedError> {
+        let data: Vec<u8> = [1_i32, -2, 3]
+            .iter()
+            .flat_map(|v| v.to_be_bytes())
+            .collect();
+        let bytes = build_mseed2(3, &data, 3);
+        let rec = MSeed2Record::from_reader(&mut &bytes[..])?.to_mseed3()?;
+        assert_eq!(rec.identifier.to_string(), "FDSN:IU_ANMO_00_B_H_Z");
+        assert_eq!(rec.header.publication_version, 2);
+        assert_eq!(rec.header.flags, 0x07);
+        assert_eq!(rec.header.year, 2010);
+        assert_eq!(rec.header.day_of_year, 58);
+        assert_eq!(rec.header.second, 11);
+        assert_eq!(rec.header.nanosecond, 123_456_000);
+        assert_eq!(
+            rec.decode_samples()?,
+            DecodedTimeseries::Int32(vec![1, -2, 3])
+        );
+        let fdsn = rec.get_fdsn_headers().unwrap();
+        assert_eq!(fdsn["Time"]["Quality"], json!(90));
+        assert_eq!(fdsn["Event"]["Begin"], json!(true));
+        assert_eq!(fdsn["Sequence"], json!(42));
+        Ok(())
+    }
+
+    #[test]
+    fn convert_steim2() -> Result<(), MSeedError> {
+        let samples: Vec<i32> = (0..200).map(|i| (i * 7) % 93 - 40).collect();
+        let frame_block = steim2::encode(&samples, 7)?;
+        let data = frame_block.get_encoded_data()?;
+        let bytes = build_mseed2(11, &data, frame_block.num_samples as u16);
+        let rec = MSeed2Record::from_reader(&mut &bytes[..])?.to_mseed3()?;
+        assert_eq!(rec.encoded_data.byte_len(), 7 * 64);
+        match rec.decode_samples()? {
+            DecodedTimeseries::Int32(v) => assert_eq!(v, samples[..frame_block.num_samples]),
+            _ => panic!("expected ints"),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn reader_multiple() -> Result<(), MSeedError> {
+        let data: Vec<u8> = [1_i32, -2, 3]
+            .iter()
+            .flat_map(|v| v.to_be_bytes())
+            .collect();
+        let mut bytes = build_mseed2(3, &data, 3);
+        bytes.extend(build_mseed2(3, &data, 2));
+        let records: Vec<MSeed2Record> =
+            MSeed2Reader::new(&bytes[..]).collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[1].header.num_samples, 2);
+        Ok(())
+    }
+
+    #[test]
+    fn rate_period() {
+        assert!((sample_rate_to_rate_period(0.1) + 10.0).abs() < f64::EPSILON);
+        assert!((sample_rate_to_rate_period(40.0) - 40.0).abs() < f64::EPSILON);
+        assert!((sample_rate_to_rate_period(0.3) - 0.3).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn write_split_int32() -> Result<(), MSeedError> {
+        let start = "2014-11-28T12:00:09.123456Z".parse::<DateTime<Utc>>()?;
+        let samples: Vec<i32> = (0..300).map(|i| i * 3 - 100).collect();
+        let mut rec = MSeed3Record::from_ints(start, 20.0, samples.clone());
+        rec.header.publication_version = 3;
+        rec.mut_fdsn_headers()
+            .insert(String::from("Time"), json!({"Quality": 80}));
+        let mut bytes = Vec::new();
+        {
+            let mut buf_writer = BufWriter::new(&mut bytes);
+            assert_eq!(write_mseed2(&rec, 512, &mut buf_writer)?, 3);
+            buf_writer.flush()?;
+        }
+        assert_eq!(bytes.len(), 3 * 512);
+        let records = MSeed2Reader::new(&bytes[..]).collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(records[0].header.channel, "BHZ");
+        assert_eq!(records[0].header.quality_indicator, b'Q');
+        assert_eq!(records[0].b1001.as_ref().unwrap().timing_quality, 80);
+        assert_eq!(records[0].start_time()?, start);
+        assert_eq!(
+            records[1].start_time()?,
+            start + Duration::milliseconds(112 * 50)
+        );
+        let mut round_trip = Vec::new();
+        for r in &records {
+            match r.to_mseed3()?.decode_samples()? {
+                DecodedTimeseries::Int32(v) => round_trip.extend(v),
+                _ => panic!("expected ints"),
+            }
+        }
+        assert_eq!(round_trip, samples);
+        Ok(())
+    }
+
+    #[test]
+    fn write_steim2() -> Result<(), MSeedError> {
+        let start = "2014-11-28T12:00:09Z".parse::<DateTime<Utc>>()?;
+        let samples: Vec<i32> = (0..2000).map(|i| (i * 37) % 1001 - 500).collect();
+        let packer = RecordPacker::new(
+            SourceIdentifier::from("FDSN:XX_STA_00_B_H_Z"),
+            40.0,
+            DataEncoding::STEIM2,
+        );
+        let mut round_trip = Vec::new();
+        for rec in packer.pack_ints(start, &samples)? {
+            for r in MSeed2Record::from_mseed3(&rec, 512)? {
+                let bytes = r.to_bytes()?;
+                assert_eq!(bytes.len(), 512);
+                let r = MSeed2Record::from_reader(&mut &bytes[..])?;
+                match r.to_mseed3()?.decode_samples()? {
+                    DecodedTimeseries::Int32(v) => round_trip.extend(v),
+                    _ => panic!("expected ints"),
+                }
+            }
+        }
+        assert_eq!(round_trip, samples);
+        Ok(())
+    }
+
+    #[test]
+    fn unrepresentable() -> Result<(), MSeedError> {
+        let start = "2014-11-28T12:00:09.123456789Z".parse::<DateTime<Utc>>()?;
+        let rec = MSeed3Record::from_ints(start, 20.0, vec![1, 2, 3]);
+        assert!(matches!(
+            MSeed2Record::from_mseed3(&rec, 512),
+            Err(MSeedError::MSeed2Unrepresentable(_))
+        ));
+        let start = "2014-11-28T12:00:09Z".parse::<DateTime<Utc>>()?;
+        let mut rec = MSeed3Record::from_ints(start, 20.0, vec![1, 2, 3]);
+        rec.identifier = SourceIdentifier::from("FDSN:XX_LONGSTA_00_B_H_Z");
+        assert!(matches!(
+            MSeed2Record::from_mseed3(&rec, 512),
+            Err(MSeedError::MSeed2Unrepresentable(_))
+        ));
+        let mut rec = MSeed3Record::from_ints(start, std::f64::consts::PI, vec![1, 2, 3]);
+        assert!(MSeed2Record::from_mseed3(&rec, 512).is_err());
+        rec.header.sample_rate_period = 20.0;
+        assert!(MSeed2Record::from_mseed3(&rec, 500).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn microsecond_rounding() -> Result<(), MSeedError> {
+        let start = "2014-11-28T12:00:09.123450Z".parse::<DateTime<Utc>>()?;
+        let (btime, microsecond) = btime_from_utc(start);
+        assert_eq!((btime.second, btime.fract, microsecond), (9, 1235, -50));
+        let start = "2014-12-31T23:59:59.999950Z".parse::<DateTime<Utc>>()?;
+        let (btime, microsecond) = btime_from_utc(start);
+        assert_eq!(
+            (
+                btime.year,
+                btime.day_of_year,
+                btime.hour,
+                btime.second,
+                btime.fract
+            ),
+            (2015, 1, 0, 0, 0)
+        );
+        assert_eq!(microsecond, -50);
+        let (btime, microsecond) = btime_from_utc(start - Duration::microseconds(1));
+        assert_eq!((btime.second, btime.fract, microsecond), (59, 9999, 49));
+
+        let start = "2014-11-28T12:00:09.123450Z".parse::<DateTime<Utc>>()?;
+        let rec = MSeed3Record::from_ints(start, 20.0, vec![1, 2, 3]);
+        let bytes = MSeed2Record::from_mseed3(&rec, 512)?[0].to_bytes()?;
+        let r = MSeed2Record::from_reader(&mut &bytes[..])?;
+        assert_eq!(r.b1001.as_ref().unwrap().microsecond, -50);
+        assert_eq!(r.start_time()?, start);
+        Ok(())
+    }
+
+    #[test]
+    fn too_many_frames() -> Result<(), MSeedError> {
+        let start = "2014-11-28T12:00:09Z".parse::<DateTime<Utc>>()?;
+        let samples: Vec<i32> = (0..20000).map(|i| (i * 7919) % 200_001 - 100_000).collect();
+        let mut packer = RecordPacker::new(
+            SourceIdentifier::from("FDSN:XX_STA_00_B_H_Z"),
+            40.0,
+            DataEncoding::STEIM2,
+        );
+        packer.max_record_length = 65536;
+        let rec = packer.pack_ints(start, &samples)?.remove(0);
+        assert!(matches!(
+            MSeed2Record::from_mseed3(&rec, 32768),
+            Err(MSeedError::MSeed2Unrepresentable(_))
+        ));
+        assert!(MSeed2Record::from_mseed3(&rec, 4096).is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn factor_multiplier() -> Result<(), MSeedError> {
+        assert_eq!(rate_factor_multiplier(40.0)?, (40, 1));
+        assert_eq!(rate_factor_multiplier(-10.0)?, (-10, 1));
+        assert_eq!(rate_factor_multiplier(0.3)?, (3, -10));
+        assert_eq!(rate_factor_multiplier(0.0)?, (0, 0));
+        Ok(())
+    }
+}
@@ -1,4 +1,6 @@
+use crate::extra_header_schema::SchemaViolation;
 use chrono::ParseError;
+use std::str::Utf8Error;
 use std::string::FromUtf8Error;
 use thiserror::Error;
 
@@ -8,10 +10,14 @@ pub enum MSeedError {
     IOError(#[from] std::io::Error),
     #[error("Insufficient bytes, {0} < fixed header size {1}")]
     InsufficientBytes(usize, usize),
+    #[error("Insufficient bytes, {0} < record size {1}")]
+    InsufficientRecordBytes(usize, usize),
     #[error("CRC invalid for record: calc:{0:#X} header:{1:#X}")]
     CrcInvalid(u32, u32),
     #[error("Text not UTF8")]
     FromUtf8Error(#[from] FromUtf8Error),
+    #[error("Text not UTF8")]
+    Utf8Error(#[from] Utf8Error),
     #[error("cannot parse extra headers")]
     JsonError(#[from] serde_json::Error),
     #[error("MSeed3 header must start with MS, (77, 83)  but was `{0}{1}`")]
@@ -24,12 +30,34 @@ pub enum MSeedError {
     ExtraHeaderNotObject(serde_json::Value),
     #[error("MSeed3 extra header parse: `{0}`")]
     ExtraHeaderParse(String),
+    #[error("MSeed3 extra headers do not match schema: {}", .0.iter().map(|v| v.to_string()).collect::<Vec<_>>().join(", "))]
+    ExtraHeaderSchema(Vec<SchemaViolation>),
     #[error("Unknown data encoding: `{0}`")]
     UnknownEncoding(u8),
+    #[error("Steim-3 decompression is not supported")]
+    Steim3Unsupported,
+    #[error("Opaque data cannot be decoded into samples")]
+    OpaqueData,
+    #[error("Cannot pack samples as encoding: `{0}`")]
+    PackEncoding(u8),
+    #[error("Max record length {0} too small for {1} bytes of headers and data")]
+    RecordLength(usize, usize),
     #[error("Expected {0} bytes for {1} samples as encoding type {2} but header has data_length={3} bytes.",)]
     DataLength(u32, u32, u8, u32),
+    #[error("Data length overflows u32 for {0} samples as encoding type {1}")]
+    DataLengthOverflow(u32, u8),
+    #[error("Record size overflows u32 with {0} bytes of headers and {1} bytes of data")]
+    RecordSizeOverflow(u32, u32),
+    #[error("Selection error: {0}")]
+    Selection(String),
+    #[error("Invalid time: {0}")]
+    InvalidTime(String),
     #[error("Date parsing error: `{0}`")]
     ParseError(#[from] ParseError),
+    #[error("miniSEED 2 parse error: `{0}`")]
+    MSeed2Parse(String),
+    #[error("Cannot represent in miniSEED 2: {0}")]
+    MSeed2Unrepresentable(String),
     #[error("MSeed3 compression/decompression error: `{0}`")]
     Compression(String),
     #[error("MSeed3 error: `{0}`")]
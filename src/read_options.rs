@@ -0,0 +1,42 @@
+use serde::{Deserialize, Serialize};
+
+/// Controls how much work is done when reading records. The default does a full read,
+/// verifying the CRC, parsing the extra headers and reading the payload. For scanning the
+/// headers of large archives, see ReadOptions::header_only and MSeed3SummaryReader.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReadOptions {
+    /// Calculate the CRC and compare with the header. This requires reading every byte of
+    /// the record, so the payload cannot be seeked past when true.
+    pub verify_crc: bool,
+    /// Read the extra headers. When false they are skipped without reading them as a
+    /// string, and records have empty extra headers.
+    pub parse_extra_headers: bool,
+    /// Read the payload. When false it is skipped and records have empty data, so they
+    /// cannot be decoded or written back out. A MSeed3SummaryReader never keeps the payload.
+    pub read_payload: bool,
+}
+
+impl ReadOptions {
+    pub fn new() -> ReadOptions {
+        ReadOptions::default()
+    }
+
+    /// Options for the fastest scan, no CRC check, extra headers or payload.
+    pub fn header_only() -> ReadOptions {
+        ReadOptions {
+            verify_crc: false,
+            parse_extra_headers: false,
+            read_payload: false,
+        }
+    }
+}
+
+impl Default for ReadOptions {
+    fn default() -> Self {
+        ReadOptions {
+            verify_crc: true,
+            parse_extra_headers: true,
+            read_payload: true,
+        }
+    }
+}
@@ -0,0 +1,571 @@
+use serde::{Deserialize, Serialize};
+use serde_json::map::Map;
+use serde_json::Value;
+use std::convert::TryFrom;
+use std::io::{BufRead, ErrorKind, Seek, SeekFrom};
+
+use crate::fdsn_source_identifier::SourceIdentifier;
+use crate::header::{MSeed3Header, FIXED_HEADER_SIZE};
+use crate::mseed_error::MSeedError;
+use crate::read_options::ReadOptions;
+use crate::record::{
+    check_digest, crc_digest, parse_headers, skip_bytes, update_digest, MSeed3Record,
+    UnparsedMSeed3Record,
+};
+
+/// Iterator over the records in a BufRead, reading one record at a time so memory use does not
+/// grow with the size of the input. Extra headers are left as a string, see MSeed3Reader
+/// for records with the extra headers parsed.
+///
+/// Iteration stops after the first error, as the position of the next record cannot be
+/// known once a record is bad.
+pub struct UnparsedMSeed3Reader<R: BufRead> {
+    buf_reader: R,
+    position: u64,
+    record_offset: u64,
+    done: bool,
+    options: ReadOptions,
+}
+
+impl<R: BufRead> UnparsedMSeed3Reader<R> {
+    pub fn new(buf_reader: R) -> UnparsedMSeed3Reader<R> {
+        UnparsedMSeed3Reader::with_options(buf_reader, ReadOptions::default())
+    }
+
+    /// Create a reader using the options, for example to skip the CRC check.
+    pub fn with_options(buf_reader: R, options: ReadOptions) -> UnparsedMSeed3Reader<R> {
+        UnparsedMSeed3Reader {
+            buf_reader,
+            position: 0,
+            record_offset: 0,
+            done: false,
+            options,
+        }
+    }
+
+    /// Byte offset from the start of the input of the most recently returned record.
+    pub fn record_offset(&self) -> u64 {
+        self.record_offset
+    }
+
+    /// Number of bytes read so far, which is the offset of the next record.
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    /// Unwraps this reader, returning the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.buf_reader
+    }
+}
+
+impl<R: BufRead> Iterator for UnparsedMSeed3Reader<R> {
+    type Item = Result<UnparsedMSeed3Record, MSeedError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        match self.buf_reader.fill_buf() {
+            Ok([]) => {
+                self.done = true;
+                return None;
+            }
+            Ok(_) => (),
+            Err(e) => {
+                self.done = true;
+                return Some(Err(MSeedError::from(e)));
+            }
+        }
+        let result =
+            UnparsedMSeed3Record::from_reader_with_options(&mut self.buf_reader, &self.options)
+                .and_then(|rec| Ok((rec.header.checked_record_size()?, rec)));
+        self.record_offset = self.position;
+        match result {
+            Ok((record_size, rec)) => {
+                self.position += record_size as u64;
+                Some(Ok(rec))
+            }
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+/// Iterator over the records in a BufRead, reading one record at a time so memory use does not
+/// grow with the size of the input.
+///
+/// Iteration stops after an error reading a record, as the position of the next record cannot
+/// be known once a record is bad. An error parsing the extra headers is returned but iteration
+/// continues, as the record length is still known.
+///
+/// #Example
+///
+/// ```
+/// # use mseed3::MSeedError;
+/// # fn main() -> Result<(), MSeedError> {
+/// # use chrono::{DateTime, Utc};
+/// # use mseed3::MSeed3Record;
+/// # let start = "2014-11-28T12:00:09Z".parse::<DateTime<Utc>>()?;
+/// # let mut bytes = Vec::new();
+/// # {
+/// #     let mut buf_writer = std::io::BufWriter::new(&mut bytes);
+/// #     MSeed3Record::from_ints(start, 10.0, vec![1, 2, 3]).write_to(&mut buf_writer)?;
+/// #     MSeed3Record::from_ints(start, 10.0, vec![4, 5]).write_to(&mut buf_writer)?;
+/// # }
+/// let mut reader = mseed3::MSeed3Reader::new(std::io::BufReader::new(&bytes[..]));
+/// while let Some(result) = reader.next() {
+///     let record = result?;
+///     println!("offset {}: {}", reader.record_offset(), record.identifier);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct MSeed3Reader<R: BufRead> {
+    unparsed_reader: UnparsedMSeed3Reader<R>,
+}
+
+impl<R: BufRead> MSeed3Reader<R> {
+    pub fn new(buf_reader: R) -> MSeed3Reader<R> {
+        MSeed3Reader {
+            unparsed_reader: UnparsedMSeed3Reader::new(buf_reader),
+        }
+    }
+
+    /// Create a reader using the options, for example to skip the CRC check.
+    pub fn with_options(buf_reader: R, options: ReadOptions) -> MSeed3Reader<R> {
+        MSeed3Reader {
+            unparsed_reader: UnparsedMSeed3Reader::with_options(buf_reader, options),
+        }
+    }
+
+    /// Byte offset from the start of the input of the most recently returned record.
+    pub fn record_offset(&self) -> u64 {
+        self.unparsed_reader.record_offset()
+    }
+
+    /// Number of bytes read so far, which is the offset of the next record.
+    pub fn position(&self) -> u64 {
+        self.unparsed_reader.position()
+    }
+
+    /// Unwraps this reader, returning the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.unparsed_reader.into_inner()
+    }
+}
+
+impl<R: BufRead> Iterator for MSeed3Reader<R> {
+    type Item = Result<MSeed3Record, MSeedError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.unparsed_reader
+            .next()
+            .map(|result| result.and_then(parse_headers))
+    }
+}
+
+/// The fixed header and identifier of a record, and optionally the extra headers, without
+/// the payload.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MSeed3RecordSummary {
+    /// Byte offset of the record from the start of the input.
+    pub offset: u64,
+    pub header: MSeed3Header,
+    pub identifier: SourceIdentifier,
+    /// None unless ReadOptions::parse_extra_headers is set.
+    pub extra_headers: Option<Map<String, Value>>,
+}
+
+/// Iterator over summaries of the records in a BufRead, for listing the contents of large
+/// files without holding the payload in memory. With ReadOptions::header_only the
+/// extra headers and payload are skipped unread, and created with new_seeking they are
+/// seeked past, so only the fixed header and identifier of each record are read.
+///
+/// Iteration stops after the first error, as the position of the next record cannot be
+/// known once a record is bad.
+pub struct MSeed3SummaryReader<R: BufRead> {
+    buf_reader: R,
+    options: ReadOptions,
+    skip: fn(&mut R, u64) -> std::io::Result<()>,
+    position: u64,
+    done: bool,
+}
+
+impl<R: BufRead> MSeed3SummaryReader<R> {
+    /// Create a summary reader that skips bytes by consuming them from the BufRead.
+    pub fn new(buf_reader: R, options: ReadOptions) -> MSeed3SummaryReader<R> {
+        MSeed3SummaryReader {
+            buf_reader,
+            options,
+            skip: consume_bytes,
+            position: 0,
+            done: false,
+        }
+    }
+
+    /// Number of bytes read or skipped so far, which is the offset of the next record.
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    /// Unwraps this reader, returning the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.buf_reader
+    }
+
+    /// Skips len bytes, passing them through the digest when the CRC is verified.
+    fn skip_bytes(
+        &mut self,
+        len: u64,
+        digest: &mut Option<crc::Digest<'static, u32>>,
+    ) -> Result<(), MSeedError> {
+        if digest.is_none() {
+            return Ok((self.skip)(&mut self.buf_reader, len)?);
+        }
+        skip_bytes(&mut self.buf_reader, len, digest)
+    }
+
+    fn read_bytes(
+        &mut self,
+        len: usize,
+        digest: &mut Option<crc::Digest<'static, u32>>,
+    ) -> Result<Vec<u8>, MSeedError> {
+        let mut buffer = vec![0; len];
+        self.buf_reader.read_exact(&mut buffer)?;
+        update_digest(digest, &buffer);
+        Ok(buffer)
+    }
+
+    fn read_summary(&mut self) -> Result<MSeed3RecordSummary, MSeedError> {
+        let mut buffer = [0; FIXED_HEADER_SIZE];
+        self.buf_reader.read_exact(&mut buffer)?;
+        let header = MSeed3Header::try_from(&buffer)?;
+        header.check_data_length()?;
+        let record_size = header.checked_record_size()?;
+        let mut digest = crc_digest(&mut buffer, &self.options);
+        let id_bytes = self.read_bytes(header.raw_identifier_length() as usize, &mut digest)?;
+        let identifier = SourceIdentifier::try_from(id_bytes)?;
+        let eh_length = header.raw_extra_headers_length();
+        let extra_headers = if self.options.parse_extra_headers {
+            let eh_bytes = self.read_bytes(eh_length as usize, &mut digest)?;
+            if eh_length > 2 {
+                match serde_json::from_slice(&eh_bytes)? {
+                    Value::Object(map) => Some(map),
+                    v => return Err(MSeedError::ExtraHeaderNotObject(v)),
+                }
+            } else {
+                Some(Map::new())
+            }
+        } else {
+            self.skip_bytes(eh_length as u64, &mut digest)?;
+            None
+        };
+        self.skip_bytes(header.raw_data_length() as u64, &mut digest)?;
+        check_digest(digest, &header)?;
+        let summary = MSeed3RecordSummary {
+            offset: self.position,
+            header,
+            identifier,
+            extra_headers,
+        };
+        self.position += record_size as u64;
+        Ok(summary)
+    }
+}
+
+impl<R: BufRead + Seek> MSeed3SummaryReader<R> {
+    /// Create a summary reader that seeks past skipped bytes. Unless the CRC is verified,
+    /// the payload is never read, but a record truncated by the end of the input is
+    /// not detected.
+    pub fn new_seeking(buf_reader: R, options: ReadOptions) -> MSeed3SummaryReader<R> {
+        MSeed3SummaryReader {
+            skip: seek_bytes,
+            ..MSeed3SummaryReader::new(buf_reader, options)
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for MSeed3SummaryReader<R> {
+    type Item = Result<MSeed3RecordSummary, MSeedError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        match self.buf_reader.fill_buf() {
+            Ok([]) => {
+                self.done = true;
+                return None;
+            }
+            Ok(_) => (),
+            Err(e) => {
+                self.done = true;
+                return Some(Err(MSeedError::from(e)));
+            }
+        }
+        let result = self.read_summary();
+        if result.is_err() {
+            self.done = true;
+        }
+        Some(result)
+    }
+}
+
+fn consume_bytes<R: BufRead>(buf_reader: &mut R, len: u64) -> std::io::Result<()> {
+    let mut remaining = len;
+    while remaining > 0 {
+        let available = buf_reader.fill_buf()?.len();
+        if available == 0 {
+            return Err(ErrorKind::UnexpectedEof.into());
+        }
+        let n = available.min(remaining as usize);
+        buf_reader.consume(n);
+        remaining -= n as u64;
+    }
+    Ok(())
+}
+
+fn seek_bytes<R: Seek>(reader: &mut R, len: u64) -> std::io::Result<()> {
+    reader.seek(SeekFrom::Current(len as i64))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_encoding::DataEncoding;
+    use crate::encoded_timeseries::EncodedTimeseries;
+    use chrono::{DateTime, Utc};
+    use std::io::{BufReader, BufWriter, Cursor, Write};
+
+    fn write_records(records: &[MSeed3Record]) -> Result<Vec<u8>, MSeedError> {
+        let mut out = Vec::new();
+        {
+            let mut buf_writer = BufWriter::new(&mut out);
+            for rec in records {
+                rec.write_to(&mut buf_writer)?;
+            }
+            buf_writer.flush()?;
+        }
+        Ok(out)
+    }
+
+    #[test]
+    fn read_with_offsets() -> Result<(), MSeedError> {
+        let start = "2014-11-28T12:00:09Z".parse::<DateTime<Utc>>()?;
+        let records = vec![
+            MSeed3Record::from_ints(start, 10.0, vec![1, 2, 3]),
+            MSeed3Record::from_floats(start, 10.0, vec![1.0, 2.0]),
+            MSeed3Record::from_ints(start, 10.0, vec![4, 5, 6, 7]),
+        ];
+        let bytes = write_records(&records)?;
+        let mut reader = MSeed3Reader::new(BufReader::new(&bytes[..]));
+        let mut offsets = Vec::new();
+        let mut num_samples = Vec::new();
+        while let Some(rec) = reader.next() {
+            let rec = rec?;
+            offsets.push(reader.record_offset());
+            num_samples.push(rec.header.num_samples);
+        }
+        assert_eq!(num_samples, vec![3, 2, 4]);
+        assert_eq!(offsets[0], 0);
+        assert_eq!(offsets[1], records[0].get_record_size() as u64);
+        assert_eq!(
+            offsets[2],
+            (records[0].get_record_size() + records[1].get_record_size()) as u64
+        );
+        assert_eq!(reader.position(), bytes.len() as u64);
+        Ok(())
+    }
+
+    #[test]
+    fn stop_after_error() -> Result<(), MSeedError> {
+        let start = "2014-11-28T12:00:09Z".parse::<DateTime<Utc>>()?;
+        let records = vec![
+            MSeed3Record::from_ints(start, 10.0, vec![1, 2, 3]),
+            MSeed3Record::from_ints(start, 10.0, vec![4, 5, 6, 7]),
+        ];
+        let mut bytes = write_records(&records)?;
+        let second = records[0].get_record_size() as usize;
+        bytes[second] = b'X';
+        let mut reader = UnparsedMSeed3Reader::new(BufReader::new(&bytes[..]));
+        assert!(reader.next().unwrap().is_ok());
+        assert!(matches!(
+            reader.next(),
+            Some(Err(MSeedError::BadRecordIndicator(b'X', b'S')))
+        ));
+        assert_eq!(reader.record_offset(), second as u64);
+        assert!(reader.next().is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn summaries() -> Result<(), MSeedError> {
+        let start = "2014-11-28T12:00:09Z".parse::<DateTime<Utc>>()?;
+        let mut second = MSeed3Record::from_ints(start, 10.0, vec![4, 5, 6, 7]);
+        second.mut_fdsn_headers().insert(
+            String::from("Sequence"),
+            Value::Number(serde_json::Number::from(7)),
+        );
+        let records = vec![MSeed3Record::from_ints(start, 10.0, vec![1, 2, 3]), second];
+        let mut bytes = write_records(&records)?;
+        let second_offset = records[0].get_record_size() as u64;
+
+        let summaries: Vec<MSeed3RecordSummary> =
+            MSeed3SummaryReader::new(BufReader::new(&bytes[..]), ReadOptions::default())
+                .collect::<Result<_, _>>()?;
+        assert_eq!(summaries.len(), 2);
+        assert_eq!(summaries[1].offset, second_offset);
+        assert_eq!(summaries[1].header.num_samples, 4);
+        assert_eq!(
+            summaries[1].extra_headers.as_ref().unwrap()["FDSN"]["Sequence"],
+            7
+        );
+        assert!(summaries[0].extra_headers.as_ref().unwrap().is_empty());
+
+        // corrupt last data byte, only noticed when verifying the crc
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xff;
+        let mut reader = MSeed3SummaryReader::new(BufReader::new(&bytes[..]), ReadOptions::new());
+        assert!(reader.next().unwrap().is_ok());
+        assert!(matches!(
+            reader.next(),
+            Some(Err(MSeedError::CrcInvalid(_, _)))
+        ));
+        let mut reader =
+            MSeed3SummaryReader::new_seeking(Cursor::new(&bytes[..]), ReadOptions::header_only());
+        let first = reader.next().unwrap()?;
+        assert_eq!(
+            first.identifier.to_string(),
+            records[0].identifier.to_string()
+        );
+        let second = reader.next().unwrap()?;
+        assert_eq!(second.offset, second_offset);
+        assert!(second.extra_headers.is_none());
+        assert!(reader.next().is_none());
+        assert_eq!(reader.position(), bytes.len() as u64);
+
+        let unchecked = MSeed3Reader::with_options(
+            BufReader::new(&bytes[..]),
+            ReadOptions {
+                verify_crc: false,
+                ..Default::default()
+            },
+        );
+        assert_eq!(unchecked.count(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn records_without_extra_headers_or_payload() -> Result<(), MSeedError> {
+        let start = "2014-11-28T12:00:09Z".parse::<DateTime<Utc>>()?;
+        let mut first = MSeed3Record::from_ints(start, 10.0, vec![1, 2, 3]);
+        first.mut_fdsn_headers().insert(
+            String::from("Sequence"),
+            Value::Number(serde_json::Number::from(7)),
+        );
+        let records = vec![first, MSeed3Record::from_ints(start, 10.0, vec![4, 5])];
+        let mut bytes = write_records(&records)?;
+        // skipped bytes still go through the CRC
+        let options = ReadOptions {
+            parse_extra_headers: false,
+            read_payload: false,
+            ..Default::default()
+        };
+        let read: Vec<MSeed3Record> =
+            MSeed3Reader::with_options(BufReader::new(&bytes[..]), options)
+                .collect::<Result<_, _>>()?;
+        assert_eq!(read.len(), 2);
+        assert!(read[0].extra_headers.is_empty());
+        assert_eq!(read[0].header.num_samples, 3);
+        assert_eq!(read[0].header.raw_data_length(), 12);
+        assert!(matches!(&read[0].encoded_data, EncodedTimeseries::Raw(v) if v.is_empty()));
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xFF;
+        let mut reader = UnparsedMSeed3Reader::with_options(BufReader::new(&bytes[..]), options);
+        assert_eq!(reader.next().unwrap()?.extra_headers, "{}");
+        assert!(matches!(
+            reader.next(),
+            Some(Err(MSeedError::CrcInvalid(_, _)))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn summary_overflowing_lengths() -> Result<(), MSeedError> {
+        let start = "2014-11-28T12:00:09Z".parse::<DateTime<Utc>>()?;
+        let bytes = write_records(&[MSeed3Record::from_ints(start, 10.0, vec![1, 2, 3])])?;
+        // 4 bytes per sample overflows u32
+        let mut num_samples = bytes.clone();
+        num_samples[24..28].copy_from_slice(&0x4000_0001_u32.to_le_bytes());
+        // compressed, so the record size overflows u32 instead
+        let mut data_length = bytes.clone();
+        data_length[15] = DataEncoding::STEIM2.value();
+        data_length[36..40].copy_from_slice(&0xFFFF_FFF0_u32.to_le_bytes());
+        for options in [ReadOptions::default(), ReadOptions::header_only()] {
+            let mut reader = MSeed3SummaryReader::new(BufReader::new(&num_samples[..]), options);
+            assert!(matches!(
+                reader.next(),
+                Some(Err(MSeedError::DataLengthOverflow(0x4000_0001, 3)))
+            ));
+            assert!(reader.next().is_none());
+            let mut reader =
+                MSeed3SummaryReader::new_seeking(Cursor::new(&data_length[..]), options);
+            assert!(matches!(
+                reader.next(),
+                Some(Err(MSeedError::RecordSizeOverflow(..)))
+            ));
+            assert!(reader.next().is_none());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn continue_after_extra_header_error() -> Result<(), MSeedError> {
+        let start = "2014-11-28T12:00:09Z".parse::<DateTime<Utc>>()?;
+        let mut first = MSeed3Record::from_ints(start, 10.0, vec![1, 2, 3]);
+        first.mut_fdsn_headers().insert(
+            String::from("Sequence"),
+            Value::Number(serde_json::Number::from(7)),
+        );
+        let records = vec![first, MSeed3Record::from_ints(start, 10.0, vec![4, 5])];
+        let mut bytes = write_records(&records)?;
+        // break the opening brace of the extra headers json
+        let eh_offset = FIXED_HEADER_SIZE + records[0].identifier.calc_len() as usize;
+        assert_eq!(bytes[eh_offset], b'{');
+        bytes[eh_offset] = b'X';
+        let options = ReadOptions {
+            verify_crc: false,
+            ..Default::default()
+        };
+        let mut reader = MSeed3Reader::with_options(BufReader::new(&bytes[..]), options);
+        assert!(reader.next().unwrap().is_err());
+        assert_eq!(reader.next().unwrap()?.header.num_samples, 2);
+        assert!(reader.next().is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn truncated_record() -> Result<(), MSeedError> {
+        let start = "2014-11-28T12:00:09Z".parse::<DateTime<Utc>>()?;
+        let bytes = write_records(&[MSeed3Record::from_ints(start, 10.0, vec![1, 2, 3])])?;
+        let mut reader = MSeed3Reader::new(BufReader::new(&bytes[0..20]));
+        assert!(matches!(reader.next(), Some(Err(MSeedError::IOError(_)))));
+        assert!(reader.next().is_none());
+        // short in the identifier, extra headers or payload, even without the CRC check
+        let options = ReadOptions::header_only();
+        for len in [FIXED_HEADER_SIZE + 2, bytes.len() - 14, bytes.len() - 1] {
+            let mut reader = MSeed3Reader::with_options(BufReader::new(&bytes[..len]), options);
+            assert!(
+                matches!(reader.next(), Some(Err(MSeedError::IOError(_)))),
+                "{}",
+                len
+            );
+            assert!(MSeed3Record::from_reader(&mut BufReader::new(&bytes[..len])).is_err());
+        }
+        Ok(())
+    }
+}
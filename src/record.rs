@@ -1,7 +1,7 @@
 use byteorder::{LittleEndian, WriteBytesExt};
 use chrono::prelude::*;
 use chrono::Utc;
-use crc::{Crc, CRC_32_ISCSI};
+use crc::{Crc, Digest, CRC_32_ISCSI};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use serde_json::map::Map;
@@ -11,10 +11,12 @@ use std::io::prelude::*;
 use std::io::BufWriter;
 
 use crate::data_encoding::DataEncoding;
+use crate::decoded_timeseries::DecodedTimeseries;
 use crate::encoded_timeseries::EncodedTimeseries;
 use crate::fdsn_source_identifier::{FdsnSourceIdentifier, SourceIdentifier};
 use crate::header::{MSeed3Header, CRC_OFFSET, FIXED_HEADER_SIZE};
 use crate::mseed_error::MSeedError;
+use crate::read_options::ReadOptions;
 use std::convert::TryFrom;
 
 pub const CASTAGNOLI: Crc<u32> = Crc::<u32>::new(&CRC_32_ISCSI);
@@ -39,65 +41,56 @@ pub struct UnparsedMSeed3Record {
 impl UnparsedMSeed3Record {
     /// Read a single record record from the BufRead
     pub fn from_reader<R: BufRead>(buf_reader: &mut R) -> Result<UnparsedMSeed3Record, MSeedError> {
+        UnparsedMSeed3Record::from_reader_with_options(buf_reader, &ReadOptions::default())
+    }
+
+    /// Read a single record record from the BufRead, only calculating the CRC if
+    /// options.verify_crc is true. Unless options.parse_extra_headers is true the extra headers
+    /// are skipped and left as an empty object, and unless options.read_payload is true the
+    /// payload is skipped and left empty, so the record cannot be decoded or written.
+    pub fn from_reader_with_options<R: BufRead>(
+        buf_reader: &mut R,
+        options: &ReadOptions,
+    ) -> Result<UnparsedMSeed3Record, MSeedError> {
         let mut buffer = [0; FIXED_HEADER_SIZE];
-        let _ = buf_reader
-            .by_ref()
-            .take(FIXED_HEADER_SIZE as u64)
-            .read(&mut buffer)?;
+        buf_reader.read_exact(&mut buffer)?;
         let mut header = MSeed3Header::try_from(&buffer)?;
-        // set crc field to zero for crc calculation, header has already read value
-        buffer[CRC_OFFSET] = 0;
-        buffer[CRC_OFFSET + 1] = 0;
-        buffer[CRC_OFFSET + 2] = 0;
-        buffer[CRC_OFFSET + 3] = 0;
-        let mut digest = CASTAGNOLI.digest();
-        digest.update(&buffer);
-
-        let mut buffer = Vec::new();
-        let _ = buf_reader
-            .by_ref()
-            .take(header.raw_identifier_length() as u64)
-            .read_to_end(&mut buffer)?;
-        digest.update(&buffer);
+        let mut digest = crc_digest(&mut buffer, options);
+
+        let mut buffer = vec![0; header.raw_identifier_length() as usize];
+        buf_reader.read_exact(&mut buffer)?;
+        update_digest(&mut digest, &buffer);
         let identifier = SourceIdentifier::try_from(buffer)?;
         let extra_headers: String;
-        let mut json_reader = buf_reader
-            .by_ref()
-            .take(header.raw_extra_headers_length() as u64);
-        let mut buffer = Vec::new();
-        let _ = json_reader.read_to_end(&mut buffer)?;
-        digest.update(&buffer);
-        if header.raw_extra_headers_length() > 2 {
-            extra_headers = String::from_utf8(buffer)?;
+        if options.parse_extra_headers {
+            let mut buffer = vec![0; header.raw_extra_headers_length() as usize];
+            buf_reader.read_exact(&mut buffer)?;
+            update_digest(&mut digest, &buffer);
+            if header.raw_extra_headers_length() > 2 {
+                extra_headers = String::from_utf8(buffer)?;
+            } else {
+                extra_headers = String::from("{}");
+            }
         } else {
+            skip_bytes(
+                buf_reader,
+                header.raw_extra_headers_length() as u64,
+                &mut digest,
+            )?;
             extra_headers = String::from("{}");
         }
-        let expected_data_length = match header.encoding {
-            DataEncoding::INT16 => 2 * header.num_samples,
-            DataEncoding::INT32 => 4 * header.num_samples,
-            DataEncoding::FLOAT32 => 4 * header.num_samples,
-            DataEncoding::FLOAT64 => 8 * header.num_samples,
-            _ => header.raw_data_length(),
-        };
-        if header.raw_data_length() != expected_data_length {
-            return Err(MSeedError::DataLength(
-                expected_data_length,
-                header.num_samples,
-                header.encoding.value(),
-                header.raw_data_length(),
-            ));
-        }
+        header.check_data_length()?;
+        header.checked_record_size()?;
 
         let mut encoded_data = Vec::new();
-        let _ = buf_reader
-            .by_ref()
-            .take(header.raw_data_length() as u64)
-            .read_to_end(&mut encoded_data)?;
-        digest.update(&encoded_data);
-        let crc_calc = digest.finalize();
-        if crc_calc != header.crc {
-            return Err(MSeedError::CrcInvalid(crc_calc, header.crc));
+        if options.read_payload {
+            encoded_data = vec![0; header.raw_data_length() as usize];
+            buf_reader.read_exact(&mut encoded_data)?;
+            update_digest(&mut digest, &encoded_data);
+        } else {
+            skip_bytes(buf_reader, header.raw_data_length() as u64, &mut digest)?;
         }
+        check_digest(digest, &header)?;
         let encoded_data = EncodedTimeseries::Raw(encoded_data);
         header.num_samples = encoded_data.reconcile_num_samples(header.num_samples);
         Ok(UnparsedMSeed3Record {
@@ -172,6 +165,63 @@ impl UnparsedMSeed3Record {
     }
 }
 
+/// Starts a CRC digest of the fixed header, with the CRC field set to zero, or None if
+/// the CRC is not to be verified.
+pub(crate) fn crc_digest(
+    buffer: &mut [u8; FIXED_HEADER_SIZE],
+    options: &ReadOptions,
+) -> Option<Digest<'static, u32>> {
+    if !options.verify_crc {
+        return None;
+    }
+    // set crc field to zero for crc calculation, header has already read value
+    buffer[CRC_OFFSET..CRC_OFFSET + 4].fill(0);
+    let mut digest = CASTAGNOLI.digest();
+    digest.update(buffer);
+    Some(digest)
+}
+
+pub(crate) fn update_digest(digest: &mut Option<Digest<'static, u32>>, bytes: &[u8]) {
+    if let Some(digest) = digest {
+        digest.update(bytes);
+    }
+}
+
+/// Consumes len bytes from the BufRead without keeping them, passing them through the
+/// digest when the CRC is verified. Errors if the input ends first.
+pub(crate) fn skip_bytes<R: BufRead>(
+    buf_reader: &mut R,
+    len: u64,
+    digest: &mut Option<Digest<'static, u32>>,
+) -> Result<(), MSeedError> {
+    let mut remaining = len;
+    while remaining > 0 {
+        let available = buf_reader.fill_buf()?;
+        if available.is_empty() {
+            return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+        }
+        let n = available.len().min(remaining as usize);
+        update_digest(digest, &available[..n]);
+        buf_reader.consume(n);
+        remaining -= n as u64;
+    }
+    Ok(())
+}
+
+/// Compares the calculated CRC with the header, if it was calculated.
+pub(crate) fn check_digest(
+    digest: Option<Digest<'static, u32>>,
+    header: &MSeed3Header,
+) -> Result<(), MSeedError> {
+    if let Some(digest) = digest {
+        let crc_calc = digest.finalize();
+        if crc_calc != header.crc {
+            return Err(MSeedError::CrcInvalid(crc_calc, header.crc));
+        }
+    }
+    Ok(())
+}
+
 pub fn parse_headers(raw_rec: UnparsedMSeed3Record) -> Result<MSeed3Record, MSeedError> {
     let v: Value = serde_json::from_str(&raw_rec.extra_headers)?;
     let eh_json = match v {
@@ -385,9 +435,33 @@ impl MSeed3Record {
         self.header.get_record_size()
     }
 
+    /// Decodes the timeseries into primitive values based on the header's encoding and
+    /// number of samples. Steim compressed data is uncompressed to Int32.
+    ///
+    /// #Example
+    ///
+    /// ```
+    /// # use mseed3::MSeedError;
+    /// # fn main() -> Result<(), MSeedError> {
+    /// use chrono::{DateTime, Utc};
+    /// use mseed3::{DecodedTimeseries, MSeed3Record};
+    /// let start = "2014-11-28T12:00:09Z".parse::<DateTime<Utc>>()?;
+    /// let record = MSeed3Record::from_ints(start, 10.0, vec![3, 1, 4, 1, 5]);
+    /// match record.decode_samples()? {
+    ///     DecodedTimeseries::Int32(v) => assert_eq!(v, vec![3, 1, 4, 1, 5]),
+    ///     _ => panic!("expected ints"),
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn decode_samples(&self) -> Result<DecodedTimeseries, MSeedError> {
+        self.encoded_data
+            .decode(&self.header.encoding, self.header.num_samples)
+    }
+
     pub fn get_fdsn_headers(&self) -> Option<&Map<String, Value>> {
-        match &self.extra_headers[FDSN_EXTRA_HEADERS] {
-            Value::Object(map) => Some(map),
+        match self.extra_headers.get(FDSN_EXTRA_HEADERS) {
+            Some(Value::Object(map)) => Some(map),
             _ => None,
         }
     }
@@ -401,9 +475,12 @@ impl MSeed3Record {
         }
     }
     pub fn create_fdsn_headers(&mut self) {
-        match &self.extra_headers[FDSN_EXTRA_HEADERS] {
-            Value::Object(_map) => (),
-            _ => self.extra_headers[FDSN_EXTRA_HEADERS] = json!({}),
+        match self.extra_headers.get(FDSN_EXTRA_HEADERS) {
+            Some(Value::Object(_map)) => (),
+            _ => {
+                self.extra_headers
+                    .insert(String::from(FDSN_EXTRA_HEADERS), json!({}));
+            }
         }
     }
 }
@@ -0,0 +1,742 @@
+use chrono::{DateTime, Duration, Utc};
+use serde_json::map::Map;
+use serde_json::Value;
+
+use crate::data_encoding::DataEncoding;
+use crate::encoded_timeseries::EncodedTimeseries;
+use crate::fdsn_source_identifier::SourceIdentifier;
+use crate::header::{sample_offset, MSeed3Header, FIXED_HEADER_SIZE};
+use crate::mseed_error::MSeedError;
+use crate::record::MSeed3Record;
+use crate::steim_frame_block::SteimFrameBlock;
+use crate::{steim1, steim2};
+
+/// Size in bytes of a single Steim frame.
+const STEIM_FRAME_SIZE: usize = 64;
+
+/// Packs a long timeseries into a sequence of records, each no larger than
+/// max_record_length bytes. The start time of each record is advanced by the
+/// number of samples in the records before it.
+///
+/// Integers can be packed as INT32, STEIM1 or STEIM2 and floats as FLOAT32. For Steim
+/// encodings, the first difference of each record is calculated from the last sample
+/// of the previous record.
+///
+/// #Example
+///
+/// ```
+/// # use mseed3::MSeedError;
+/// # fn main() -> Result<(), MSeedError> {
+/// use chrono::{DateTime, Utc};
+/// use mseed3::{DataEncoding, RecordPacker, SourceIdentifier};
+/// let start = "2014-11-28T12:00:09Z".parse::<DateTime<Utc>>()?;
+/// let timeseries: Vec<i32> = (0..10000).map(|i| i % 100).collect();
+/// let identifier = SourceIdentifier::from("FDSN:XX_BIRD_00_H_H_Z");
+/// let mut packer = RecordPacker::new(identifier, 100.0, DataEncoding::STEIM2);
+/// packer.max_record_length = 512;
+/// let records = packer.pack_ints(start, &timeseries)?;
+/// assert!(records.len() > 1);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct RecordPacker {
+    pub identifier: SourceIdentifier,
+    pub sample_rate_period: f64,
+    pub encoding: DataEncoding,
+    pub max_record_length: usize,
+    pub publication_version: u8,
+    pub extra_headers: Option<Map<String, Value>>,
+}
+
+impl RecordPacker {
+    /// Default maximum record length, in bytes.
+    pub const DEFAULT_RECORD_LENGTH: usize = 4096;
+
+    /// Create a packer with the default max record length and no extra headers.
+    pub fn new(
+        identifier: SourceIdentifier,
+        sample_rate_period: f64,
+        encoding: DataEncoding,
+    ) -> RecordPacker {
+        RecordPacker {
+            identifier,
+            sample_rate_period,
+            encoding,
+            max_record_length: RecordPacker::DEFAULT_RECORD_LENGTH,
+            publication_version: 0,
+            extra_headers: None,
+        }
+    }
+
+    /// Packs integer samples as INT32, STEIM1 or STEIM2, depending on the encoding.
+    pub fn pack_ints(
+        &self,
+        start: DateTime<Utc>,
+        data: &[i32],
+    ) -> Result<Vec<MSeed3Record>, MSeedError> {
+        let data_bytes = self.max_data_length()?;
+        let mut records = Vec::new();
+        let mut offset = 0;
+        match self.encoding {
+            DataEncoding::INT32 => {
+                for chunk in data.chunks(self.samples_per_record(data_bytes, 4)?) {
+                    records.push(self.create_record(
+                        start,
+                        offset,
+                        chunk.len(),
+                        EncodedTimeseries::Int32(chunk.to_vec()),
+                    ));
+                    offset += chunk.len();
+                }
+            }
+            DataEncoding::STEIM1 | DataEncoding::STEIM2 => {
+                let frames = data_bytes / STEIM_FRAME_SIZE;
+                if frames == 0 {
+                    return Err(MSeedError::RecordLength(
+                        self.max_record_length,
+                        self.header_length() + STEIM_FRAME_SIZE,
+                    ));
+                }
+                while offset < data.len() {
+                    let bias = if offset == 0 {
+                        data[0]
+                    } else {
+                        data[offset - 1]
+                    };
+                    let frame_block = self.steim_encode(&data[offset..], frames, bias)?;
+                    let encoded = frame_block.get_encoded_data()?;
+                    let encoded_data = match self.encoding {
+                        DataEncoding::STEIM1 => EncodedTimeseries::Steim1(encoded),
+                        _ => EncodedTimeseries::Steim2(encoded),
+                    };
+                    records.push(self.create_record(
+                        start,
+                        offset,
+                        frame_block.num_samples,
+                        encoded_data,
+                    ));
+                    offset += frame_block.num_samples;
+                }
+            }
+            _ => return Err(MSeedError::PackEncoding(self.encoding.value())),
+        }
+        Ok(records)
+    }
+
+    /// Packs float samples as FLOAT32.
+    pub fn pack_floats(
+        &self,
+        start: DateTime<Utc>,
+        data: &[f32],
+    ) -> Result<Vec<MSeed3Record>, MSeedError> {
+        let data_bytes = self.max_data_length()?;
+        let mut records = Vec::new();
+        let mut offset = 0;
+        match self.encoding {
+            DataEncoding::FLOAT32 => {
+                for chunk in data.chunks(self.samples_per_record(data_bytes, 4)?) {
+                    records.push(self.create_record(
+                        start,
+                        offset,
+                        chunk.len(),
+                        EncodedTimeseries::Float32(chunk.to_vec()),
+                    ));
+                    offset += chunk.len();
+                }
+            }
+            _ => return Err(MSeedError::PackEncoding(self.encoding.value())),
+        }
+        Ok(records)
+    }
+
+    /// Size in bytes of the fixed header, identifier and extra headers of each record.
+    pub fn header_length(&self) -> usize {
+        let extra_headers_length = match &self.extra_headers {
+            Some(eh) => {
+                let len = Value::Object(eh.clone()).to_string().len();
+                if len > 2 {
+                    len
+                } else {
+                    0
+                }
+            }
+            None => 0,
+        };
+        FIXED_HEADER_SIZE + self.identifier.calc_len() as usize + extra_headers_length
+    }
+
+    /// Number of bytes available for data in each record.
+    fn max_data_length(&self) -> Result<usize, MSeedError> {
+        let header_length = self.header_length();
+        if self.max_record_length <= header_length {
+            return Err(MSeedError::RecordLength(
+                self.max_record_length,
+                header_length,
+            ));
+        }
+        Ok((self.max_record_length - header_length).min(u32::MAX as usize))
+    }
+
+    fn samples_per_record(
+        &self,
+        data_bytes: usize,
+        sample_size: usize,
+    ) -> Result<usize, MSeedError> {
+        let num = data_bytes / sample_size;
+        if num == 0 {
+            return Err(MSeedError::RecordLength(
+                self.max_record_length,
+                self.header_length() + sample_size,
+            ));
+        }
+        Ok(num)
+    }
+
+    fn steim_encode(
+        &self,
+        data: &[i32],
+        frames: usize,
+        bias: i32,
+    ) -> Result<SteimFrameBlock, MSeedError> {
+        match self.encoding {
+            DataEncoding::STEIM1 => steim1::encode_with_bias(data, frames, bias),
+            _ => steim2::encode_with_bias(data, frames, bias),
+        }
+    }
+
+    /// Creates a record starting offset samples after start.
+    fn create_record(
+        &self,
+        start: DateTime<Utc>,
+        offset: usize,
+        num_samples: usize,
+        encoded_data: EncodedTimeseries,
+    ) -> MSeed3Record {
+        let rec_start = start + sample_offset(self.sample_rate_period, offset);
+        let mut header = MSeed3Header::new(
+            rec_start,
+            self.encoding.clone(),
+            self.sample_rate_period,
+            num_samples,
+        );
+        header.publication_version = self.publication_version;
+        MSeed3Record::new(
+            header,
+            self.identifier.clone(),
+            self.extra_headers.clone(),
+            encoded_data,
+        )
+    }
+}
+
+/// Packs samples that arrive a few at a time, as from a digitizer, into records. A record is
+/// returned once enough samples have been pushed to fill max_record_length, or once the
+/// buffered samples span more than the latency, if set. For Steim encodings the first
+/// difference of each record continues from the last sample of the previous record.
+///
+/// Samples that do not start at the expected time of the next sample, within half a
+/// sample period, begin a new segment, flushing any buffered samples as a partial record.
+///
+/// #Example
+///
+/// ```
+/// # use mseed3::MSeedError;
+/// # fn main() -> Result<(), MSeedError> {
+/// use chrono::{DateTime, Duration, Utc};
+/// use mseed3::{DataEncoding, RecordPacker, SourceIdentifier, StreamingPacker};
+/// let mut time = "2014-11-28T12:00:09Z".parse::<DateTime<Utc>>()?;
+/// let identifier = SourceIdentifier::from("FDSN:XX_BIRD_00_H_H_Z");
+/// let packer = RecordPacker::new(identifier, 10.0, DataEncoding::STEIM2);
+/// let mut streaming = StreamingPacker::new(packer)?;
+/// streaming.latency = Some(Duration::seconds(30));
+/// let mut records = Vec::new();
+/// for _ in 0..100 {
+///     records.extend(streaming.push_samples(&[1, 2, 3, 4, 5], time)?);
+///     time = time + Duration::milliseconds(500);
+/// }
+/// records.extend(streaming.close()?);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct StreamingPacker {
+    pub packer: RecordPacker,
+    pub latency: Option<Duration>,
+    buffer: Vec<i32>,
+    buffer_start: Option<DateTime<Utc>>,
+    bias: Option<i32>,
+    /// Buffered samples needed before the Steim frames could be full.
+    next_encode_len: usize,
+}
+
+impl StreamingPacker {
+    /// Create a streaming packer using the packer's identifier, sample rate, encoding and
+    /// max record length. Only INT32, STEIM1 and STEIM2 are supported. There is no latency
+    /// timeout by default.
+    pub fn new(packer: RecordPacker) -> Result<StreamingPacker, MSeedError> {
+        match packer.encoding {
+            DataEncoding::INT32 | DataEncoding::STEIM1 | DataEncoding::STEIM2 => (),
+            _ => return Err(MSeedError::PackEncoding(packer.encoding.value())),
+        }
+        let streaming = StreamingPacker {
+            packer,
+            latency: None,
+            buffer: Vec::new(),
+            buffer_start: None,
+            bias: None,
+            next_encode_len: 0,
+        };
+        // check the record length can hold data
+        streaming.max_samples_or_frames()?;
+        Ok(streaming)
+    }
+
+    /// Adds samples, the first of which is at time, returning any records that are complete.
+    /// If the samples cannot be encoded, for example a difference too large for Steim2, an
+    /// error is returned and none of them are added, so later samples start a new segment.
+    pub fn push_samples(
+        &mut self,
+        samples: &[i32],
+        time: DateTime<Utc>,
+    ) -> Result<Vec<MSeed3Record>, MSeedError> {
+        let mut records = Vec::new();
+        if samples.is_empty() {
+            return Ok(records);
+        }
+        let new_segment = match self.next_sample_time() {
+            Some(expected) => {
+                let half_period = sample_offset(self.packer.sample_rate_period, 1) / 2;
+                let diff = time - expected;
+                diff > half_period || -diff > half_period
+            }
+            None => false,
+        };
+        if !matches!(self.packer.encoding, DataEncoding::INT32) {
+            // check the new samples encode before changing anything, so a bad one is never
+            // buffered and an error does not lose flushed samples
+            let bias = if new_segment {
+                None
+            } else {
+                self.buffer.last().copied().or(self.bias)
+            };
+            self.packer
+                .steim_encode(samples, 0, bias.unwrap_or(samples[0]))?;
+        }
+        if new_segment {
+            // gap or overlap, so finish current record and start a new segment
+            records.extend(self.flush()?);
+            self.bias = None;
+            self.buffer_start = None;
+        }
+        if self.buffer_start.is_none() {
+            self.buffer_start = Some(time);
+        }
+        self.buffer.extend_from_slice(samples);
+        self.pack_full_records(&mut records)?;
+        if let Some(last) = self.next_sample_time() {
+            let last_sample = last - sample_offset(self.packer.sample_rate_period, 1);
+            records.extend(self.poll(last_sample)?);
+        }
+        Ok(records)
+    }
+
+    /// Flushes the buffered samples as a partial record if the first of them is older than
+    /// the latency, compared to now.
+    pub fn poll(&mut self, now: DateTime<Utc>) -> Result<Option<MSeed3Record>, MSeedError> {
+        match (self.latency, self.buffer_start) {
+            (Some(latency), Some(buffer_start))
+                if !self.buffer.is_empty() && now - buffer_start >= latency =>
+            {
+                self.flush()
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Packs all buffered samples into a possibly partial record. The next samples are
+    /// expected to continue after the buffered samples.
+    pub fn flush(&mut self) -> Result<Option<MSeed3Record>, MSeedError> {
+        if self.buffer.is_empty() {
+            return Ok(None);
+        }
+        let num_samples = self.buffer.len();
+        let encoded_data = match self.packer.encoding {
+            DataEncoding::INT32 => EncodedTimeseries::Int32(self.buffer.clone()),
+            _ => {
+                let frame_block = self.steim_encode(0)?;
+                self.steim_timeseries(frame_block.get_encoded_data()?)
+            }
+        };
+        Ok(Some(self.take_record(num_samples, encoded_data)))
+    }
+
+    /// Flushes any remaining samples as a final partial record.
+    pub fn close(mut self) -> Result<Option<MSeed3Record>, MSeedError> {
+        self.flush()
+    }
+
+    /// Number of samples waiting to be packed.
+    pub fn buffered_samples(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// Time the next pushed sample is expected, if samples have been pushed.
+    pub fn next_sample_time(&self) -> Option<DateTime<Utc>> {
+        self.buffer_start
+            .map(|start| start + sample_offset(self.packer.sample_rate_period, self.buffer.len()))
+    }
+
+    /// Max samples per record for INT32, or max frames per record for Steim.
+    fn max_samples_or_frames(&self) -> Result<usize, MSeedError> {
+        let data_bytes = self.packer.max_data_length()?;
+        match self.packer.encoding {
+            DataEncoding::INT32 => self.packer.samples_per_record(data_bytes, 4),
+            _ => self.packer.samples_per_record(data_bytes, STEIM_FRAME_SIZE),
+        }
+    }
+
+    fn pack_full_records(&mut self, records: &mut Vec<MSeed3Record>) -> Result<(), MSeedError> {
+        let max = self.max_samples_or_frames()?;
+        loop {
+            match self.packer.encoding {
+                DataEncoding::INT32 => {
+                    if self.buffer.len() < max {
+                        return Ok(());
+                    }
+                    let encoded_data = EncodedTimeseries::Int32(self.buffer[..max].to_vec());
+                    records.push(self.take_record(max, encoded_data));
+                }
+                _ => {
+                    // each word holds at least one sample, so cannot be full until more
+                    // samples than words
+                    if self.buffer.len() <= max * 15 - 2 || self.buffer.len() < self.next_encode_len
+                    {
+                        return Ok(());
+                    }
+                    let frame_block = self.steim_encode(max)?;
+                    if frame_block.num_samples == self.buffer.len() {
+                        // each new sample takes at most one more word, so the frames cannot
+                        // be full until there are as many new samples as free words
+                        let free_words = (max * 15).saturating_sub(frame_block.num_words());
+                        self.next_encode_len = self.buffer.len() + free_words.max(1);
+                        return Ok(());
+                    }
+                    let encoded_data = self.steim_timeseries(frame_block.get_encoded_data()?);
+                    records.push(self.take_record(frame_block.num_samples, encoded_data));
+                }
+            }
+        }
+    }
+
+    fn steim_encode(&self, frames: usize) -> Result<SteimFrameBlock, MSeedError> {
+        let bias = self.bias.unwrap_or(self.buffer[0]);
+        self.packer.steim_encode(&self.buffer, frames, bias)
+    }
+
+    fn steim_timeseries(&self, encoded: Vec<u8>) -> EncodedTimeseries {
+        match self.packer.encoding {
+            DataEncoding::STEIM1 => EncodedTimeseries::Steim1(encoded),
+            _ => EncodedTimeseries::Steim2(encoded),
+        }
+    }
+
+    /// Creates a record from the first num_samples buffered samples and removes them.
+    fn take_record(&mut self, num_samples: usize, encoded_data: EncodedTimeseries) -> MSeed3Record {
+        let start = self
+            .buffer_start
+            .expect("buffer start set when samples pushed");
+        let record = self
+            .packer
+            .create_record(start, 0, num_samples, encoded_data);
+        self.bias = Some(self.buffer[num_samples - 1]);
+        self.buffer.drain(..num_samples);
+        self.next_encode_len = 0;
+        self.buffer_start =
+            Some(start + sample_offset(self.packer.sample_rate_period, num_samples));
+        record
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoded_timeseries::DecodedTimeseries;
+    use crate::reader::MSeed3Reader;
+    use std::io::{BufReader, BufWriter, Write};
+
+    fn sine_data(n: usize) -> Vec<i32> {
+        (0..n)
+            .map(|i| ((i as f64 / 15.0).sin() * 5000.0) as i32)
+            .collect()
+    }
+
+    fn check_packed(encoding: DataEncoding) -> Result<(), MSeedError> {
+        let start = "2014-11-28T12:00:09Z".parse::<DateTime<Utc>>()?;
+        let data = sine_data(5000);
+        let identifier = SourceIdentifier::from("FDSN:XX_BIRD_00_H_H_Z");
+        let mut packer = RecordPacker::new(identifier, 20.0, encoding);
+        packer.max_record_length = 512;
+        let records = packer.pack_ints(start, &data)?;
+        assert!(records.len() > 1);
+
+        let mut out = Vec::new();
+        {
+            let mut buf_writer = BufWriter::new(&mut out);
+            for rec in &records {
+                let (bytes_written, _) = rec.write_to(&mut buf_writer)?;
+                assert!(bytes_written <= 512);
+            }
+            buf_writer.flush()?;
+        }
+        let mut all = Vec::new();
+        let mut expected_start = start;
+        for rec in MSeed3Reader::new(BufReader::new(&out[..])) {
+            let rec = rec?;
+            assert_eq!(rec.header.get_start_as_utc(), expected_start);
+            expected_start += Duration::milliseconds(50 * rec.header.num_samples as i64);
+            match rec.decode_samples()? {
+                DecodedTimeseries::Int32(v) => all.extend(v),
+                _ => panic!("expected ints"),
+            }
+        }
+        assert_eq!(all, data);
+        Ok(())
+    }
+
+    #[test]
+    fn pack_int32() -> Result<(), MSeedError> {
+        check_packed(DataEncoding::INT32)
+    }
+
+    #[test]
+    fn pack_steim1() -> Result<(), MSeedError> {
+        check_packed(DataEncoding::STEIM1)
+    }
+
+    #[test]
+    fn pack_steim2() -> Result<(), MSeedError> {
+        check_packed(DataEncoding::STEIM2)
+    }
+
+    #[test]
+    fn pack_floats() -> Result<(), MSeedError> {
+        let start = "2014-11-28T12:00:09Z".parse::<DateTime<Utc>>()?;
+        let data: Vec<f32> = (0..1000).map(|i| i as f32 / 10.0).collect();
+        let identifier = SourceIdentifier::from("FDSN:XX_BIRD_00_H_H_Z");
+        let mut packer = RecordPacker::new(identifier, -10.0, DataEncoding::FLOAT32);
+        packer.max_record_length = 1024;
+        let records = packer.pack_floats(start, &data)?;
+        let per_record = (1024 - packer.header_length()) / 4;
+        assert_eq!(records.len(), data.len().div_ceil(per_record));
+        assert_eq!(records[0].header.num_samples as usize, per_record);
+        // period of 10 seconds
+        assert_eq!(
+            records[1].header.get_start_as_utc(),
+            start + Duration::seconds(10 * per_record as i64)
+        );
+        Ok(())
+    }
+
+    fn stream_samples(encoding: DataEncoding) -> Result<(), MSeedError> {
+        let start = "2014-11-28T12:00:09Z".parse::<DateTime<Utc>>()?;
+        let data = sine_data(3000);
+        let identifier = SourceIdentifier::from("FDSN:XX_BIRD_00_H_H_Z");
+        let mut packer = RecordPacker::new(identifier, 20.0, encoding);
+        packer.max_record_length = 512;
+        let mut streaming = StreamingPacker::new(packer)?;
+        let mut records = Vec::new();
+        for (i, chunk) in data.chunks(7).enumerate() {
+            let time = start + Duration::milliseconds(50 * 7 * i as i64);
+            records.extend(streaming.push_samples(chunk, time)?);
+        }
+        assert!(streaming.buffered_samples() > 0);
+        records.extend(streaming.close()?);
+        assert!(records.len() > 1);
+        let mut all = Vec::new();
+        let mut expected_start = start;
+        for rec in &records {
+            assert!(rec.get_record_size() <= 512);
+            assert_eq!(rec.header.get_start_as_utc(), expected_start);
+            expected_start += Duration::milliseconds(50 * rec.header.num_samples as i64);
+            match rec.decode_samples()? {
+                DecodedTimeseries::Int32(v) => all.extend(v),
+                _ => panic!("expected ints"),
+            }
+        }
+        assert_eq!(all, data);
+        Ok(())
+    }
+
+    #[test]
+    fn stream_int32() -> Result<(), MSeedError> {
+        stream_samples(DataEncoding::INT32)
+    }
+
+    #[test]
+    fn stream_steim1() -> Result<(), MSeedError> {
+        stream_samples(DataEncoding::STEIM1)
+    }
+
+    #[test]
+    fn stream_steim2() -> Result<(), MSeedError> {
+        stream_samples(DataEncoding::STEIM2)
+    }
+
+    #[test]
+    fn stream_one_sample_at_a_time() -> Result<(), MSeedError> {
+        let start = "2014-11-28T12:00:09Z".parse::<DateTime<Utc>>()?;
+        let data = sine_data(3000);
+        let identifier = SourceIdentifier::from("FDSN:XX_BIRD_00_H_H_Z");
+        let mut packer = RecordPacker::new(identifier, 20.0, DataEncoding::STEIM2);
+        packer.max_record_length = 512;
+        let packed = packer.pack_ints(start, &data)?;
+        let mut streaming = StreamingPacker::new(packer)?;
+        let mut records = Vec::new();
+        for (i, sample) in data.iter().enumerate() {
+            let time = start + Duration::milliseconds(50 * i as i64);
+            records.extend(streaming.push_samples(&[*sample], time)?);
+        }
+        records.extend(streaming.close()?);
+        // same full records as packing all at once
+        let num_samples = |recs: &[MSeed3Record]| -> Vec<u32> {
+            recs.iter().map(|rec| rec.header.num_samples).collect()
+        };
+        assert_eq!(num_samples(&records), num_samples(&packed));
+        let mut all = Vec::new();
+        for rec in &records {
+            assert!(rec.get_record_size() <= 512);
+            match rec.decode_samples()? {
+                DecodedTimeseries::Int32(v) => all.extend(v),
+                _ => panic!("expected ints"),
+            }
+        }
+        assert_eq!(all, data);
+        Ok(())
+    }
+
+    #[test]
+    fn stream_recovers_after_encode_error() -> Result<(), MSeedError> {
+        let start = "2014-11-28T12:00:09Z".parse::<DateTime<Utc>>()?;
+        let identifier = SourceIdentifier::from("FDSN:XX_BIRD_00_H_H_Z");
+        let packer = RecordPacker::new(identifier, 10.0, DataEncoding::STEIM2);
+        let mut streaming = StreamingPacker::new(packer)?;
+        assert!(streaming.push_samples(&[1, 2, 3], start)?.is_empty());
+        // difference too large for steim2 is rejected, keeping the buffered samples
+        let bad_time = start + Duration::milliseconds(300);
+        assert!(matches!(
+            streaming.push_samples(&[1 << 30], bad_time),
+            Err(MSeedError::Compression(_))
+        ));
+        assert_eq!(streaming.buffered_samples(), 3);
+        assert_eq!(streaming.next_sample_time(), Some(bad_time));
+        // the rejected sample leaves a gap before the next
+        let next = start + Duration::milliseconds(400);
+        let records = streaming.push_samples(&[4, 5], next)?;
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].header.num_samples, 3);
+        let last = streaming.close()?.unwrap();
+        assert_eq!(last.header.get_start_as_utc(), next);
+        match last.decode_samples()? {
+            DecodedTimeseries::Int32(v) => assert_eq!(v, vec![4, 5]),
+            _ => panic!("expected ints"),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn stream_gap_with_encode_error_keeps_buffer() -> Result<(), MSeedError> {
+        let start = "2014-11-28T12:00:09Z".parse::<DateTime<Utc>>()?;
+        let identifier = SourceIdentifier::from("FDSN:XX_BIRD_00_H_H_Z");
+        let packer = RecordPacker::new(identifier, 10.0, DataEncoding::STEIM2);
+        let mut streaming = StreamingPacker::new(packer)?;
+        assert!(streaming.push_samples(&[1, 2, 3], start)?.is_empty());
+        // after a gap, but the new samples cannot be encoded, so nothing is flushed
+        let gap_start = start + Duration::seconds(60);
+        assert!(matches!(
+            streaming.push_samples(&[0, 1 << 30], gap_start),
+            Err(MSeedError::Compression(_))
+        ));
+        assert_eq!(streaming.buffered_samples(), 3);
+        let last = streaming.close()?.unwrap();
+        assert_eq!(last.header.get_start_as_utc(), start);
+        match last.decode_samples()? {
+            DecodedTimeseries::Int32(v) => assert_eq!(v, vec![1, 2, 3]),
+            _ => panic!("expected ints"),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn stream_steim2_continues_differences() -> Result<(), MSeedError> {
+        let start = "2014-11-28T12:00:09Z".parse::<DateTime<Utc>>()?;
+        let data: Vec<i32> = (0..2000).map(|i| 1000 + i).collect();
+        let identifier = SourceIdentifier::from("FDSN:XX_BIRD_00_H_H_Z");
+        let mut packer = RecordPacker::new(identifier, 20.0, DataEncoding::STEIM2);
+        packer.max_record_length = 256;
+        let mut streaming = StreamingPacker::new(packer)?;
+        let mut records = streaming.push_samples(&data, start)?;
+        records.extend(streaming.close()?);
+        assert!(records.len() > 1);
+        for rec in &records {
+            // d(0) relative to previous record is 1, same as all other differences,
+            // so first data word is 7 x 4 bit differences of 1
+            let bytes = match &rec.encoded_data {
+                EncodedTimeseries::Steim2(v) => v,
+                _ => panic!("expected steim2"),
+            };
+            if rec.header.get_start_as_utc() != start {
+                assert_eq!(bytes[12..16], [0x81, 0x11, 0x11, 0x11]);
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn stream_latency_and_gap() -> Result<(), MSeedError> {
+        let start = "2014-11-28T12:00:09Z".parse::<DateTime<Utc>>()?;
+        let identifier = SourceIdentifier::from("FDSN:XX_BIRD_00_H_H_Z");
+        let packer = RecordPacker::new(identifier, 10.0, DataEncoding::STEIM2);
+        let mut streaming = StreamingPacker::new(packer)?;
+        streaming.latency = Some(Duration::seconds(2));
+        // 1 second of data, not yet at latency
+        assert!(streaming.push_samples(&[1; 10], start)?.is_empty());
+        assert!(streaming
+            .poll(start + Duration::milliseconds(1500))?
+            .is_none());
+        let rec = streaming.poll(start + Duration::seconds(2))?.unwrap();
+        assert_eq!(rec.header.num_samples, 10);
+        assert_eq!(streaming.buffered_samples(), 0);
+
+        // continues at expected time, then a gap flushes
+        let next = start + Duration::seconds(1);
+        assert_eq!(streaming.next_sample_time(), Some(next));
+        assert!(streaming.push_samples(&[2; 5], next)?.is_empty());
+        let gap_start = next + Duration::seconds(60);
+        let records = streaming.push_samples(&[3; 5], gap_start)?;
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].header.get_start_as_utc(), next);
+        assert_eq!(records[0].header.num_samples, 5);
+        let last = streaming.close()?.unwrap();
+        assert_eq!(last.header.get_start_as_utc(), gap_start);
+        Ok(())
+    }
+
+    #[test]
+    fn pack_errors() -> Result<(), MSeedError> {
+        let start = "2014-11-28T12:00:09Z".parse::<DateTime<Utc>>()?;
+        let identifier = SourceIdentifier::from("FDSN:XX_BIRD_00_H_H_Z");
+        let mut packer = RecordPacker::new(identifier, 20.0, DataEncoding::FLOAT64);
+        assert!(matches!(
+            packer.pack_ints(start, &[1, 2, 3]),
+            Err(MSeedError::PackEncoding(5))
+        ));
+        packer.encoding = DataEncoding::STEIM2;
+        packer.max_record_length = 100;
+        assert!(matches!(
+            packer.pack_ints(start, &[1, 2, 3]),
+            Err(MSeedError::RecordLength(100, _))
+        ));
+        Ok(())
+    }
+}
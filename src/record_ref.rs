@@ -0,0 +1,283 @@
+use serde_json::map::Map;
+use serde_json::Value;
+use std::convert::TryFrom;
+use std::fmt;
+
+use crate::encoded_timeseries::EncodedTimeseries;
+use crate::fdsn_source_identifier::SourceIdentifier;
+use crate::header::{MSeed3Header, CRC_OFFSET, FIXED_HEADER_SIZE};
+use crate::mseed_error::MSeedError;
+use crate::record::{parse_headers, MSeed3Record, UnparsedMSeed3Record, CASTAGNOLI};
+
+/// Miniseed3 record borrowed from a byte slice, for example a memory mapped file, without
+/// copying the identifier, extra headers or data. Only the fixed header is parsed when the
+/// record is created. The CRC is not checked until verify_crc is called.
+#[derive(Debug, Clone)]
+pub struct MSeed3RecordRef<'a> {
+    pub header: MSeed3Header,
+    pub identifier: &'a str,
+    pub extra_headers: &'a str,
+    pub payload: &'a [u8],
+    record_bytes: &'a [u8],
+}
+
+impl<'a> MSeed3RecordRef<'a> {
+    /// Parses the record at the start of the byte slice, which may contain further records
+    /// after this one.
+    pub fn from_bytes(bytes: &'a [u8]) -> Result<MSeed3RecordRef<'a>, MSeedError> {
+        let header = MSeed3Header::try_from(bytes)?;
+        MSeed3RecordRef::with_header(bytes, header)
+    }
+
+    /// Like from_bytes, but with the fixed header already parsed from the start of bytes.
+    pub(crate) fn with_header(
+        bytes: &'a [u8],
+        header: MSeed3Header,
+    ) -> Result<MSeed3RecordRef<'a>, MSeedError> {
+        header.check_data_length()?;
+        let record_size = header.checked_record_size()? as usize;
+        if bytes.len() < record_size {
+            return Err(MSeedError::InsufficientRecordBytes(
+                bytes.len(),
+                record_size,
+            ));
+        }
+        let id_end = FIXED_HEADER_SIZE + header.raw_identifier_length() as usize;
+        let eh_end = id_end + header.raw_extra_headers_length() as usize;
+        let identifier = std::str::from_utf8(&bytes[FIXED_HEADER_SIZE..id_end])?;
+        let extra_headers = std::str::from_utf8(&bytes[id_end..eh_end])?;
+        Ok(MSeed3RecordRef {
+            header,
+            identifier,
+            extra_headers,
+            payload: &bytes[eh_end..record_size],
+            record_bytes: &bytes[..record_size],
+        })
+    }
+
+    /// Iterate over all records in the byte slice.
+    pub fn iter(bytes: &'a [u8]) -> MSeed3RecordRefIter<'a> {
+        MSeed3RecordRefIter::new(bytes)
+    }
+
+    /// All bytes of this record, including the header.
+    pub fn record_bytes(&self) -> &'a [u8] {
+        self.record_bytes
+    }
+
+    /// Calculates the CRC of the record bytes, using zero for the header CRC field.
+    pub fn calc_crc(&self) -> u32 {
+        let mut digest = CASTAGNOLI.digest();
+        digest.update(&self.record_bytes[..CRC_OFFSET]);
+        digest.update(&[0_u8; 4]);
+        digest.update(&self.record_bytes[CRC_OFFSET + 4..]);
+        digest.finalize()
+    }
+
+    /// Checks the calculated CRC against the value in the header.
+    pub fn verify_crc(&self) -> Result<(), MSeedError> {
+        let crc_calc = self.calc_crc();
+        if crc_calc != self.header.crc {
+            return Err(MSeedError::CrcInvalid(crc_calc, self.header.crc));
+        }
+        Ok(())
+    }
+
+    /// Parses the identifier, see the identifier field for the unparsed string.
+    pub fn source_identifier(&self) -> SourceIdentifier {
+        SourceIdentifier::from(self.identifier)
+    }
+
+    /// Parses the extra headers json, an empty map if there are none.
+    pub fn parse_extra_headers(&self) -> Result<Map<String, Value>, MSeedError> {
+        if self.extra_headers.len() <= 2 {
+            return Ok(Map::new());
+        }
+        let v: Value = serde_json::from_str(self.extra_headers)?;
+        match v {
+            Value::Object(map) => Ok(map),
+            _ => Err(MSeedError::ExtraHeaderNotObject(v)),
+        }
+    }
+
+    /// Copies into an owned record, with the extra headers left as a string.
+    pub fn to_unparsed(&self) -> UnparsedMSeed3Record {
+        let extra_headers = if self.extra_headers.len() > 2 {
+            String::from(self.extra_headers)
+        } else {
+            String::from("{}")
+        };
+        let mut header = self.header.clone();
+        let encoded_data = EncodedTimeseries::Raw(self.payload.to_vec());
+        header.num_samples = encoded_data.reconcile_num_samples(header.num_samples);
+        UnparsedMSeed3Record {
+            header,
+            identifier: self.source_identifier(),
+            extra_headers,
+            encoded_data,
+        }
+    }
+
+    /// Copies into an owned record, parsing the extra headers.
+    pub fn to_record(&self) -> Result<MSeed3Record, MSeedError> {
+        parse_headers(self.to_unparsed())
+    }
+}
+
+impl<'a> fmt::Display for MSeed3RecordRef<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "  {}, {}", self.identifier, self.header)
+    }
+}
+
+/// Iterator over the records in a byte slice, returning borrowed MSeed3RecordRef.
+/// Iteration stops after the first error.
+pub struct MSeed3RecordRefIter<'a> {
+    bytes: &'a [u8],
+    position: usize,
+    record_offset: usize,
+    done: bool,
+}
+
+impl<'a> MSeed3RecordRefIter<'a> {
+    pub fn new(bytes: &'a [u8]) -> MSeed3RecordRefIter<'a> {
+        MSeed3RecordRefIter {
+            bytes,
+            position: 0,
+            record_offset: 0,
+            done: false,
+        }
+    }
+
+    /// Byte offset within the slice of the most recently returned record.
+    pub fn record_offset(&self) -> usize {
+        self.record_offset
+    }
+
+    /// Offset of the next record.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+}
+
+impl<'a> Iterator for MSeed3RecordRefIter<'a> {
+    type Item = Result<MSeed3RecordRef<'a>, MSeedError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done || self.position >= self.bytes.len() {
+            return None;
+        }
+        self.record_offset = self.position;
+        let result = MSeed3RecordRef::from_bytes(&self.bytes[self.position..]);
+        match &result {
+            Ok(rec) => self.position += rec.record_bytes.len(),
+            Err(_) => self.done = true,
+        }
+        Some(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_encoding::DataEncoding;
+    use chrono::{DateTime, Utc};
+    use serde_json::json;
+    use std::io::{BufWriter, Write};
+
+    fn write_records(records: &[MSeed3Record]) -> Result<Vec<u8>, MSeedError> {
+        let mut out = Vec::new();
+        {
+            let mut buf_writer = BufWriter::new(&mut out);
+            for rec in records {
+                rec.write_to(&mut buf_writer)?;
+            }
+            buf_writer.flush()?;
+        }
+        Ok(out)
+    }
+
+    #[test]
+    fn iter_records() -> Result<(), MSeedError> {
+        let start = "2014-11-28T12:00:09Z".parse::<DateTime<Utc>>()?;
+        let mut with_eh = MSeed3Record::from_ints(start, 10.0, vec![4, 5]);
+        with_eh
+            .mut_fdsn_headers()
+            .insert(String::from("Sequence"), json!(7));
+        let records = vec![MSeed3Record::from_ints(start, 10.0, vec![1, 2, 3]), with_eh];
+        let bytes = write_records(&records)?;
+        let mut iter = MSeed3RecordRef::iter(&bytes);
+
+        let first = iter.next().unwrap()?;
+        assert_eq!(iter.record_offset(), 0);
+        assert_eq!(first.identifier, "FDSN:XX_STA_00_B_H_Z");
+        assert_eq!(first.extra_headers, "");
+        assert_eq!(first.payload.len(), 12);
+        first.verify_crc()?;
+        assert!(first.parse_extra_headers()?.is_empty());
+
+        let second = iter.next().unwrap()?;
+        assert_eq!(iter.record_offset(), first.record_bytes().len());
+        assert_eq!(second.extra_headers, r#"{"FDSN":{"Sequence":7}}"#);
+        second.verify_crc()?;
+        let rec = second.to_record()?;
+        assert_eq!(rec.get_fdsn_headers().unwrap()["Sequence"], json!(7));
+        assert_eq!(rec.header.num_samples, 2);
+
+        assert!(iter.next().is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn lazy_crc() -> Result<(), MSeedError> {
+        let start = "2014-11-28T12:00:09Z".parse::<DateTime<Utc>>()?;
+        let mut bytes = write_records(&[MSeed3Record::from_ints(start, 10.0, vec![1, 2, 3])])?;
+        let last = bytes.len() - 1;
+        bytes[last] = 99;
+        let rec = MSeed3RecordRef::from_bytes(&bytes)?;
+        assert!(matches!(
+            rec.verify_crc(),
+            Err(MSeedError::CrcInvalid(_, _))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn truncated() -> Result<(), MSeedError> {
+        let start = "2014-11-28T12:00:09Z".parse::<DateTime<Utc>>()?;
+        let bytes = write_records(&[MSeed3Record::from_ints(start, 10.0, vec![1, 2, 3])])?;
+        let mut iter = MSeed3RecordRef::iter(&bytes[..bytes.len() - 1]);
+        assert!(matches!(
+            iter.next(),
+            Some(Err(MSeedError::InsufficientRecordBytes(_, _)))
+        ));
+        assert!(iter.next().is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn overflowing_lengths() -> Result<(), MSeedError> {
+        let start = "2014-11-28T12:00:09Z".parse::<DateTime<Utc>>()?;
+        let bytes = write_records(&[MSeed3Record::from_ints(start, 10.0, vec![1, 2, 3])])?;
+        // 4 bytes per sample overflows u32
+        let mut num_samples = bytes.clone();
+        num_samples[24..28].copy_from_slice(&0x4000_0001_u32.to_le_bytes());
+        assert!(matches!(
+            MSeed3RecordRef::from_bytes(&num_samples),
+            Err(MSeedError::DataLengthOverflow(0x4000_0001, 3))
+        ));
+        // compressed, so data length is not checked against the samples, but record
+        // size overflows u32
+        let mut data_length = bytes.clone();
+        data_length[15] = DataEncoding::STEIM2.value();
+        data_length[36..40].copy_from_slice(&0xFFFF_FFF0_u32.to_le_bytes());
+        assert!(matches!(
+            MSeed3RecordRef::from_bytes(&data_length),
+            Err(MSeedError::RecordSizeOverflow(_, 0xFFFF_FFF0))
+        ));
+        let mut iter = MSeed3RecordRef::iter(&data_length);
+        assert!(iter.next().unwrap().is_err());
+        assert!(iter.next().is_none());
+        Ok(())
+    }
+}
@@ -0,0 +1,411 @@
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use std::fmt;
+use std::io::BufRead;
+
+use crate::fdsn_source_identifier::{SourceIdentifier, PREFIX};
+use crate::header::MSeed3Header;
+use crate::mseed_error::MSeedError;
+use crate::record::MSeed3Record;
+use crate::time_window::{glob_match, SourceIdentifierMatcher};
+
+/// Pattern selecting records by source identifier, with `*` and `?` globs in each
+/// component, and optionally by time range and publication version.
+///
+/// #Example
+///
+/// ```
+/// # use mseed3::MSeedError;
+/// # fn main() -> Result<(), MSeedError> {
+/// use mseed3::{SidPattern, SourceIdentifier, SourceIdentifierMatcher};
+/// let pattern = SidPattern::parse("FDSN:IU_*_00_B_H_?")?;
+/// assert!(pattern.matches_identifier(&SourceIdentifier::from("FDSN:IU_ANMO_00_B_H_Z")));
+/// assert!(!pattern.matches_identifier(&SourceIdentifier::from("FDSN:IU_ANMO_10_B_H_Z")));
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SidPattern {
+    pub network: String,
+    pub station: String,
+    pub location: String,
+    pub band: String,
+    pub source: String,
+    pub subsource: String,
+    /// Records must have samples at or after start.
+    pub start: Option<DateTime<Utc>>,
+    /// Records must have samples at or before end.
+    pub end: Option<DateTime<Utc>>,
+    pub publication_version: Option<u8>,
+}
+
+impl SidPattern {
+    /// Pattern matching every record.
+    pub fn new() -> SidPattern {
+        let any = || String::from("*");
+        SidPattern {
+            network: any(),
+            station: any(),
+            location: any(),
+            band: any(),
+            source: any(),
+            subsource: any(),
+            start: None,
+            end: None,
+            publication_version: None,
+        }
+    }
+
+    /// Parses a source identifier glob like `FDSN:IU_ANMO_*_B_H_?`. If the last
+    /// component is `*`, any components after it may be left off, so `FDSN:IU_*`
+    /// matches all of the IU network. A location of `--` matches an empty location.
+    pub fn parse(pattern: &str) -> Result<SidPattern, MSeedError> {
+        let bad = || {
+            MSeedError::Selection(format!(
+                "cannot parse source identifier pattern {}",
+                pattern
+            ))
+        };
+        let body = pattern.strip_prefix(PREFIX).ok_or_else(bad)?;
+        let mut parts: Vec<String> = body.split('_').map(String::from).collect();
+        if parts.len() > 6 || (parts.len() < 6 && parts.last().map(String::as_str) != Some("*")) {
+            return Err(bad());
+        }
+        parts.resize(6, String::from("*"));
+        let mut sid = SidPattern::new();
+        let [network, station, location, band, source, subsource]: [String; 6] =
+            parts.try_into().map_err(|_| bad())?;
+        sid.network = network;
+        sid.station = station;
+        sid.location = empty_location(location);
+        sid.band = band;
+        sid.source = source;
+        sid.subsource = subsource;
+        Ok(sid)
+    }
+
+    /// Pattern from SEED network, station, location and channel globs. A three character
+    /// channel is split into band, source and subsource, `*` matches any channel, and
+    /// otherwise a leading or trailing `*` covers the components left out, so `B*`
+    /// matches any channel with band B and `*Z` any with subsource Z.
+    pub fn from_nslc(
+        network: &str,
+        station: &str,
+        location: &str,
+        channel: &str,
+    ) -> Result<SidPattern, MSeedError> {
+        let chars: Vec<String> = channel.chars().map(String::from).collect();
+        let any = || String::from("*");
+        let (band, source, subsource) = match chars.len() {
+            3 => (chars[0].clone(), chars[1].clone(), chars[2].clone()),
+            1 if channel == "*" => (any(), any(), any()),
+            2 if chars[1] == "*" => (chars[0].clone(), any(), any()),
+            2 if chars[0] == "*" => (any(), any(), chars[1].clone()),
+            _ => {
+                return Err(MSeedError::Selection(format!(
+                    "cannot split channel pattern {} into band, source and subsource",
+                    channel
+                )))
+            }
+        };
+        Ok(SidPattern {
+            network: String::from(network),
+            station: String::from(station),
+            location: empty_location(String::from(location)),
+            band,
+            source,
+            subsource,
+            ..SidPattern::new()
+        })
+    }
+
+    /// True if the identifier, start time range and publication version of the header
+    /// all match.
+    pub fn matches_header(&self, identifier: &SourceIdentifier, header: &MSeed3Header) -> bool {
+        if self
+            .publication_version
+            .is_some_and(|v| v != header.publication_version)
+        {
+            return false;
+        }
+        if self.start.is_some() || self.end.is_some() {
+            let start = match header.try_start_as_utc() {
+                Ok(start) => start,
+                Err(_) => return false,
+            };
+            if self.start.is_some_and(|s| header.end_time() < s)
+                || self.end.is_some_and(|e| start > e)
+            {
+                return false;
+            }
+        }
+        self.matches_identifier(identifier)
+    }
+
+    pub fn matches_record(&self, rec: &MSeed3Record) -> bool {
+        self.matches_header(&rec.identifier, &rec.header)
+    }
+}
+
+impl Default for SidPattern {
+    fn default() -> Self {
+        SidPattern::new()
+    }
+}
+
+/// matches_identifier ignores the time range and publication version, matches_header
+/// checks them too.
+impl SourceIdentifierMatcher for SidPattern {
+    fn matches_header(&self, identifier: &SourceIdentifier, header: &MSeed3Header) -> bool {
+        SidPattern::matches_header(self, identifier, header)
+    }
+
+    fn matches_identifier(&self, identifier: &SourceIdentifier) -> bool {
+        match identifier {
+            SourceIdentifier::Fdsn(sid) => {
+                glob_match(&self.network, &sid.network)
+                    && glob_match(&self.station, &sid.station)
+                    && glob_match(&self.location, &sid.location)
+                    && glob_match(&self.band, &sid.band)
+                    && glob_match(&self.source, &sid.source)
+                    && glob_match(&self.subsource, &sid.subsource)
+            }
+            // cannot be split into components, so only matches the match all pattern
+            SourceIdentifier::Raw(_) => [
+                &self.network,
+                &self.station,
+                &self.location,
+                &self.band,
+                &self.source,
+                &self.subsource,
+            ]
+            .iter()
+            .all(|p| p.chars().all(|c| c == '*')),
+        }
+    }
+}
+
+impl fmt::Display for SidPattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}{}_{}_{}_{}_{}_{}",
+            PREFIX,
+            self.network,
+            self.station,
+            self.location,
+            self.band,
+            self.source,
+            self.subsource
+        )?;
+        let time = |t: Option<DateTime<Utc>>| match t {
+            Some(t) => t.format("%Y-%m-%dT%H:%M:%S%.fZ").to_string(),
+            None => String::from("*"),
+        };
+        write!(f, " {} {}", time(self.start), time(self.end))?;
+        if let Some(v) = self.publication_version {
+            write!(f, " {}", v)?;
+        }
+        Ok(())
+    }
+}
+
+fn empty_location(location: String) -> String {
+    if location == "--" {
+        String::new()
+    } else {
+        location
+    }
+}
+
+/// A list of SidPatterns, matching a record if any pattern does. Read from selection
+/// files in the format used by libmseed and dataselect, one selection per line:
+///
+/// ```text
+/// # SourceID             [Starttime            [Endtime              [Pubversion]]]
+/// FDSN:IU_ANMO_*_B_H_?   2010-01-01T00:00:00   2010-01-02T00:00:00   2
+/// FDSN:IU_COLA_*
+/// ```
+///
+/// Lines in the older form `Network Station Location Channel [Quality [Starttime
+/// [Endtime]]]` are also accepted, see SidPattern::from_nslc. A quality code is ignored
+/// unless it is a number, taken as a publication version. A time of `*` is open.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SelectionList {
+    pub patterns: Vec<SidPattern>,
+}
+
+impl SelectionList {
+    pub fn new() -> SelectionList {
+        SelectionList::default()
+    }
+
+    pub fn parse(text: &str) -> Result<SelectionList, MSeedError> {
+        let mut patterns = Vec::new();
+        for (line_num, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            patterns.push(
+                parse_selection_line(line)
+                    .map_err(|e| MSeedError::Selection(format!("line {}: {}", line_num + 1, e)))?,
+            );
+        }
+        Ok(SelectionList { patterns })
+    }
+
+    pub fn from_reader<R: BufRead>(reader: &mut R) -> Result<SelectionList, MSeedError> {
+        let mut text = String::new();
+        reader.read_to_string(&mut text)?;
+        SelectionList::parse(&text)
+    }
+
+    pub fn matches_header(&self, identifier: &SourceIdentifier, header: &MSeed3Header) -> bool {
+        self.patterns
+            .iter()
+            .any(|p| p.matches_header(identifier, header))
+    }
+
+    pub fn matches_record(&self, rec: &MSeed3Record) -> bool {
+        self.matches_header(&rec.identifier, &rec.header)
+    }
+}
+
+/// Matches if any pattern does. matches_identifier ignores time ranges and publication
+/// versions, matches_header checks them too.
+impl SourceIdentifierMatcher for SelectionList {
+    fn matches_header(&self, identifier: &SourceIdentifier, header: &MSeed3Header) -> bool {
+        SelectionList::matches_header(self, identifier, header)
+    }
+
+    fn matches_identifier(&self, identifier: &SourceIdentifier) -> bool {
+        self.patterns
+            .iter()
+            .any(|p| p.matches_identifier(identifier))
+    }
+}
+
+fn parse_selection_line(line: &str) -> Result<SidPattern, String> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    let (mut pattern, rest) = if fields[0].starts_with(PREFIX) {
+        if fields.len() > 4 {
+            return Err(format!("too many fields: {}", line));
+        }
+        let mut pattern = SidPattern::parse(fields[0]).map_err(|e| e.to_string())?;
+        if let Some(v) = fields.get(3) {
+            pattern.publication_version = parse_version(v)?;
+        }
+        (pattern, &fields[1..fields.len().min(3)])
+    } else {
+        if fields.len() < 4 || fields.len() > 7 {
+            return Err(format!(
+                "expected source identifier or NSLC codes: {}",
+                line
+            ));
+        }
+        let mut pattern = SidPattern::from_nslc(fields[0], fields[1], fields[2], fields[3])
+            .map_err(|e| e.to_string())?;
+        if let Some(quality) = fields.get(4) {
+            if quality.chars().all(|c| c.is_ascii_digit()) {
+                pattern.publication_version = parse_version(quality)?;
+            }
+        }
+        (pattern, &fields[fields.len().min(5)..])
+    };
+    if let Some(start) = rest.first() {
+        pattern.start = parse_selection_time(start)?;
+    }
+    if let Some(end) = rest.get(1) {
+        pattern.end = parse_selection_time(end)?;
+    }
+    Ok(pattern)
+}
+
+fn parse_version(field: &str) -> Result<Option<u8>, String> {
+    if field == "*" {
+        return Ok(None);
+    }
+    field
+        .parse::<u8>()
+        .map(Some)
+        .map_err(|_| format!("bad publication version {}", field))
+}
+
+/// Parses an ISO8601 time, with or without a trailing Z, or a date only, or `*` for none.
+fn parse_selection_time(field: &str) -> Result<Option<DateTime<Utc>>, String> {
+    if field == "*" {
+        return Ok(None);
+    }
+    let s = field.trim_end_matches('Z');
+    if let Ok(t) = NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%S%.f") {
+        return Ok(Some(t.and_utc()));
+    }
+    if let Ok(d) = NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+        return Ok(d.and_hms_opt(0, 0, 0).map(|t| t.and_utc()));
+    }
+    Err(format!("bad time {}", field))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    fn rec(id: &str, start: &str) -> MSeed3Record {
+        let start = start.parse::<DateTime<Utc>>().unwrap();
+        let mut rec = MSeed3Record::from_ints(start, 1.0, vec![1, 2, 3, 4, 5]);
+        rec.identifier = SourceIdentifier::from(id);
+        rec
+    }
+
+    #[test]
+    fn parse_pattern() -> Result<(), MSeedError> {
+        let p = SidPattern::parse("FDSN:IU_*")?;
+        assert_eq!(p.network, "IU");
+        assert_eq!(p.subsource, "*");
+        assert!(p.matches_identifier(&SourceIdentifier::from("FDSN:IU_ANMO__L_H_Z")));
+        let p = SidPattern::parse("FDSN:IU_ANMO_--_L_H_?")?;
+        assert!(p.matches_identifier(&SourceIdentifier::from("FDSN:IU_ANMO__L_H_Z")));
+        assert!(!p.matches_identifier(&SourceIdentifier::from("FDSN:IU_ANMO_00_L_H_Z")));
+        assert!(!p.matches_identifier(&SourceIdentifier::from("XX.ANMO")));
+        assert!(SidPattern::new().matches_identifier(&SourceIdentifier::from("XX.ANMO")));
+        assert!(SidPattern::parse("FDSN:IU_ANMO").is_err());
+        assert!(SidPattern::parse("IU_*").is_err());
+
+        let p = SidPattern::from_nslc("IU", "*", "00", "B*")?;
+        assert_eq!(p.to_string(), "FDSN:IU_*_00_B_*_* * *");
+        assert!(SidPattern::from_nslc("IU", "*", "00", "BHZZ").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn selection_file() -> Result<(), MSeedError> {
+        let text = "# comment\n\
+            FDSN:IU_ANMO_*_B_H_? 2020-01-01T00:00:00 2020-01-01T01:00:00Z 1\n\
+            \n\
+            CO HODGE -- LH? * 2020-01-02 *\n";
+        let list = SelectionList::from_reader(&mut text.as_bytes())?;
+        assert_eq!(list.patterns.len(), 2);
+        assert_eq!(list.patterns[0].publication_version, Some(1));
+        assert_eq!(list.patterns[1].location, "");
+        assert_eq!(list.patterns[1].end, None);
+
+        let mut anmo = rec("FDSN:IU_ANMO_00_B_H_Z", "2020-01-01T00:30:00Z");
+        assert!(!list.matches_record(&anmo));
+        anmo.header.publication_version = 1;
+        assert!(list.matches_record(&anmo));
+        anmo.header
+            .set_start_from_utc(anmo.header.get_start_as_utc() + Duration::hours(1));
+        assert!(!list.matches_record(&anmo));
+        assert!(list.matches_identifier(&anmo.identifier));
+
+        assert!(list.matches_record(&rec("FDSN:CO_HODGE__L_H_Z", "2020-01-02T00:00:00Z")));
+        // last sample just before the start
+        assert!(!list.matches_record(&rec("FDSN:CO_HODGE__L_H_Z", "2020-01-01T23:59:55Z")));
+        assert!(list.matches_record(&rec("FDSN:CO_HODGE__L_H_Z", "2020-01-01T23:59:56Z")));
+
+        let err = SelectionList::parse("FDSN:IU_ANMO_*_B_H_? yesterday").unwrap_err();
+        assert!(err.to_string().contains("line 1"), "{}", err);
+        Ok(())
+    }
+}
@@ -55,25 +55,42 @@ pub fn decode_with_bias(b: &[u8], num_samples: u32) -> Result<Vec<i32>, MSeedErr
         )));
     }
     let nsamp = num_samples as usize;
-    let mut samples = Vec::with_capacity(nsamp);
     let num_frames = b.len() / 64;
-    let mut start = 0;
+    // at most 4 differences in each of 15 words per frame, so a corrupt header cannot
+    // allocate more than the data could hold
+    let mut samples = Vec::with_capacity(nsamp.min(num_frames * 60));
     let mut end = 0;
     let mut last_value = 0;
 
-    for i in 0..num_frames {
+    if nsamp == 0 {
+        return Ok(samples);
+    }
+
+    'frames: for i in 0..num_frames {
         let temp_samples = extract_samples(b, i * 64)?; // returns only differences except for frame 0
-                                                        // d(0) is byte 0 by default
         let mut ts_itr = temp_samples.iter();
         if i == 0 {
             // special case for first frame
-            start = *ts_itr.next().unwrap(); // X(0) is byte 1 for frame 0
-            samples.push(start);
-            last_value = start;
-            end = *ts_itr.next().unwrap(); // X(n) is byte 2 for frame 0
+            match (ts_itr.next(), ts_itr.next(), ts_itr.next()) {
+                (Some(x0), Some(xn), Some(_d0)) => {
+                    // X(0) is word 1 and X(n) is word 2 for frame 0, d(0) is relative to
+                    // the previous record and so is skipped in favor of X(0)
+                    samples.push(*x0);
+                    last_value = *x0;
+                    end = *xn;
+                }
+                _ => {
+                    return Err(MSeedError::Compression(String::from(
+                        "first frame missing integration constants",
+                    )))
+                }
+            }
         }
         for s in ts_itr {
-            last_value += s;
+            if samples.len() == nsamp {
+                break 'frames;
+            }
+            last_value = last_value.wrapping_add(*s);
             samples.push(last_value)
         }
     } // end for each frame...
@@ -84,8 +101,12 @@ pub fn decode_with_bias(b: &[u8], num_samples: u32) -> Result<Vec<i32>, MSeedErr
             num_samples
         )));
     }
-    assert_eq!(samples[0], start);
-    assert_eq!(samples[samples.len() - 1], end);
+    if last_value != end {
+        return Err(MSeedError::Compression(format!(
+            "Last sample decompressed doesn't match reverse integration constant: {} != X(N) {}",
+            last_value, end
+        )));
+    }
     Ok(samples)
 }
 
@@ -109,14 +130,33 @@ pub fn decode(b: &[u8], num_samples: u32) -> Result<Vec<i32>, MSeedError> {
 * will be nulls.
 * @param samples the data points represented as signed integers
 * @param frames the number of Steim frames to use in the encoding, 0 for unlimited
-* @param bias offset for use as a constant for the first difference, otherwise
-* set to 0
 * @return SteimFrameBlock containing encoded byte array
 * @throws SteimException samples array is zero size
 * @throws SteimException number of frames is not a positive value
 * @throws SteimException cannot encode more than 63 frames
 */
 pub fn encode(samples: &[i32], frames: usize) -> Result<SteimFrameBlock, MSeedError> {
+    match samples.first() {
+        // bias of the first sample makes d(0) zero
+        Some(first) => encode_with_bias(samples, frames, *first),
+        None => Err(MSeedError::Compression(String::from(
+            "samples array is zero size",
+        ))),
+    }
+}
+
+/**
+ * Encode the array of integer values into a Steim 1 compressed byte frame block,
+ * calculating the first difference, d(0), from <b>bias</b>, usually the last
+ * sample of the previous record.
+ *
+ * see encode
+ */
+pub fn encode_with_bias(
+    samples: &[i32],
+    frames: usize,
+    bias: i32,
+) -> Result<SteimFrameBlock, MSeedError> {
     if samples.is_empty() {
         return Err(MSeedError::Compression(String::from(
             "samples array is zero size",
@@ -137,47 +177,37 @@ pub fn encode(samples: &[i32], frames: usize) -> Result<SteimFrameBlock, MSeedEr
     // the frame_block fills up.
     //
     // now begin looping over differences
-    // iterator produces first sample, then differences to all remaining values
-    let diff_iter = samples.iter().scan(0, |state, &x| {
-        let d = x - *state;
+    // iterator produces d(0) relative to the bias, then differences
+    // to all remaining values
+    let diff_iter = samples.iter().scan(bias, |state, &x| {
+        let d = x.wrapping_sub(*state);
         *state = x;
         Some(d)
     });
 
     let mut num_samples = 0;
     let by_four = ByFours::new(diff_iter);
-    let mut first_sample = true;
+    let mut frame = SteimFrame::new();
+    frame.set_word(u32::from_be_bytes(samples[0].to_be_bytes()), 0, 0);
+    let mut frame_idx = 2; //skip past the last sample in second word
 
-    'outer: loop {
-        let mut frame = SteimFrame::new();
-        let mut frame_idx = 0;
-        for chunk in by_four {
-            if first_sample {
-                match chunk {
-                    Steim1Word::One(v) => frame.set_word(u32::from_be_bytes(v.to_be_bytes()), 0, 0),
-                    v => panic!("first sample must be one 4-byte value, but {:?}", v),
-                }
-                first_sample = false;
-                frame_idx += 2; //skip past the last sample in second word
-            } else {
-                frame_idx = chunk.add_to_frame(&mut frame, frame_idx);
-            }
-            num_samples += chunk.num_samples();
-            if frame_idx == 15 {
-                // filled the frame, push a new one
-                if frame_block.steim_frame.len() == frames {
-                    // zero means unlimited, but len() always >=1, so ok
-                    frame_block.steim_frame.push(frame);
-                    break 'outer;
-                }
+    for chunk in by_four {
+        frame_idx = chunk.add_to_frame(&mut frame, frame_idx);
+        num_samples += chunk.num_samples();
+        if frame_idx == 15 {
+            // filled the frame, push a new one
+            frame_block.steim_frame.push(frame);
+            frame = SteimFrame::new();
+            frame_idx = 0;
+            if frame_block.steim_frame.len() == frames {
+                // zero means unlimited, but len() always >=1, so ok
                 break;
             }
         }
-        if frame_idx > 0 {
-            // last partially filled the frame, push
-            frame_block.steim_frame.push(frame);
-        }
-        break;
+    }
+    if frame_idx > 0 {
+        // last partially filled the frame, push
+        frame_block.steim_frame.push(frame);
     }
     frame_block.num_samples = num_samples;
     assert_ne!(frame_block.steim_frame.len(), 0);
@@ -205,7 +235,7 @@ fn extract_samples(bytes: &[u8], offset: usize) -> Result<Vec<i32>, MSeedError>
     for i in 1..16 {
         // i is the word number of the frame starting at 0
         //curr_nibble = (nibbles >>> (30 - i*2 ) ) & 0x03; // count from top to bottom each nibble in W(0)
-        let curr_nibble = (nibbles >> (32 - i * 2)) & 0x03; // count from top to bottom each nibble in W(0)
+        let curr_nibble = (nibbles >> (30 - i * 2)) & 0x03; // count from top to bottom each nibble in W(0)
                                                             // Rule appears to be:
                                                             // only check for byte-swap on actual value-atoms, so a 32-bit word in of itself
                                                             // is not swapped, but two 16-bit short *values* are or a single
@@ -229,7 +259,7 @@ fn extract_samples(bytes: &[u8], offset: usize) -> Result<Vec<i32>, MSeedError>
             1 => {
                 //"1 means 4 one byte differences");
                 for n in 0..4 {
-                    temp.push((bytes[offset_idx + (i * 4) + n] as i8) as i32);
+                    temp.push((bytes[offset_idx + n] as i8) as i32);
                 }
             }
             2 => {
@@ -262,7 +292,6 @@ where
 {
     diff_iter: I,
     prev: VecDeque<i32>,
-    first: bool,
 }
 impl<I> ByFours<I>
 where
@@ -272,7 +301,6 @@ where
         ByFours::<I> {
             diff_iter,
             prev: VecDeque::new(),
-            first: true,
         }
     }
 }
@@ -283,11 +311,6 @@ where
     type Item = Steim1Word;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.first {
-            self.first = false;
-            // first is always single 4-byte value
-            return Some(Steim1Word::One(self.diff_iter.next()?));
-        }
         while self.prev.len() < 4 {
             match &self.diff_iter.next() {
                 Some(v) => self.prev.push_back(*v),
@@ -324,7 +347,7 @@ where
                 self.prev.pop_front()? as i8,
                 self.prev.pop_front()? as i8,
             ));
-        } else if self.prev.len() > 2 && ok_i16(self.prev[0]) && ok_i16(self.prev[1]) {
+        } else if self.prev.len() >= 2 && ok_i16(self.prev[0]) && ok_i16(self.prev[1]) {
             // two two-byte values
             return Some(Steim1Word::Two(
                 self.prev.pop_front()? as i16,
@@ -398,6 +421,39 @@ impl Steim1Word {
 mod tests {
     use super::*;
 
+    #[test]
+    fn frame_nibbles() -> Result<(), MSeedError> {
+        // W(0) holds a 2-bit nibble per word, its own in the top 2 bits, so W(3) with two
+        // 2-byte differences has nibble 10 in bits 25-24 and W(4) with one 4-byte
+        // difference has 11 in bits 23-22
+        let mut frame = SteimFrame::new();
+        frame.set_word(7, 0, 0);
+        frame.set_word(4, 0, 1);
+        frame.set_word(u32::from_be_bytes([0, 1, 0xff, 0xfe]), 2, 2);
+        frame.set_word((-70000_i32) as u32, 3, 3);
+        let mut frame_block = SteimFrameBlock::new(1);
+        frame_block.steim_frame.push(frame);
+        let bytes = frame_block.get_encoded_data()?;
+        assert_eq!(&bytes[0..4], &[0b0000_0010, 0b1100_0000, 0, 0]);
+        assert_eq!(extract_samples(&bytes, 0)?, vec![7, 4, 1, -2, -70000]);
+        Ok(())
+    }
+
+    #[test]
+    fn decode_wraps() -> Result<(), MSeedError> {
+        // corrupt or crafted differences must not overflow
+        let mut frame = SteimFrame::new();
+        frame.set_word(i32::MAX as u32, 0, 0);
+        frame.set_word(i32::MIN as u32, 0, 1);
+        frame.set_word(0, 3, 2);
+        frame.set_word(1, 3, 3);
+        let mut frame_block = SteimFrameBlock::new(1);
+        frame_block.steim_frame.push(frame);
+        let samples = decode(&frame_block.get_encoded_data()?, 2)?;
+        assert_eq!(samples, vec![i32::MAX, i32::MIN]);
+        Ok(())
+    }
+
     #[test]
     fn diff_iter() {
         let data = [1, -1, -1, -1, 20, -300, 160, -18000];
@@ -423,22 +479,19 @@ mod tests {
         let mut found = 0;
         let mut byfour = ByFours::new(diff_iter);
 
-        if let Steim1Word::One(_) = byfour.next().unwrap() {
-            // first should be single
-            found += 1;
-            if let Steim1Word::Four(_, _, _, _) = byfour.next().unwrap() {
-                // next 4 1-byte values, -1, -1, -1, 20
-                found += 4;
+        if let Steim1Word::Four(_, _, _, _) = byfour.next().unwrap() {
+            // first 4 1-byte values, 1, -2, 0, 0
+            found += 4;
+            if let Steim1Word::Two(_, _) = byfour.next().unwrap() {
+                // next 2 2-byte values, 21, -320
+                found += 2;
                 if let Steim1Word::Two(_, _) = byfour.next().unwrap() {
-                    // next 2 2-byte values, -300, 160
+                    // then 2 2-byte values, 460, -17840
                     found += 2;
-                    if let Steim1Word::One(_) = byfour.next().unwrap() {
-                        // then single value -18000
-                        found += 1;
-                    }
                 }
             }
         }
+        assert!(byfour.next().is_none());
         assert_eq!(found, data.len());
         Ok(())
     }
@@ -461,8 +514,9 @@ mod tests {
         let frame_data = extract_samples(&enc_bytes[0..64], 0)?;
         assert_eq!(frame_data[0], 1);
         assert_eq!(frame_data[1], -40000); // last sample
-        for i in 2..frame_data.len() {
-            assert_eq!(frame_data[i], data[i - 1] - data[i - 2], "i: {} ", i);
+        assert_eq!(frame_data[2], 0); // d(0), no previous sample
+        for i in 3..(data.len() + 2) {
+            assert_eq!(frame_data[i], data[i - 2] - data[i - 3], "i: {} ", i);
         }
         let rt_data = decode(
             &frame_block.get_encoded_data()?,
@@ -474,4 +528,25 @@ mod tests {
         }
         Ok(())
     }
+
+    #[test]
+    fn multi_frame_round_trip() -> Result<(), MSeedError> {
+        let data: Vec<i32> = (0..1000)
+            .map(|i| ((i as f64 / 10.0).sin() * 1000.0) as i32)
+            .collect();
+        let frame_block = encode(&data, 0)?;
+        assert!(frame_block.steim_frame.len() > 1);
+        assert_eq!(frame_block.num_samples, data.len());
+        let rt_data = decode(&frame_block.get_encoded_data()?, data.len() as u32)?;
+        assert_eq!(rt_data, data);
+
+        let frame_block = encode(&data, 2)?;
+        assert_eq!(frame_block.steim_frame.len(), 2);
+        let rt_data = decode(
+            &frame_block.get_encoded_data()?,
+            frame_block.num_samples as u32,
+        )?;
+        assert_eq!(rt_data, data[0..frame_block.num_samples]);
+        Ok(())
+    }
 }
@@ -0,0 +1,433 @@
+/*!
+ * Decoding and encoding of Steim2-compressed data blocks to or from an array of integer values.
+ * <p>
+ * Steim compression scheme Copyrighted by Dr. Joseph Steim.<p>
+ * <dl>
+ * <dt>Reference material found in:</dt>
+ * <dd>
+ * Appendix B of SEED Reference Manual, 2nd Ed., pp. 119-125
+ * <i>Federation of Digital Seismic Networks, et al.</i>
+ * February, 1993
+ * </dd>
+ * </dl>
+ *
+ * Each 32-bit data word is described by a 2-bit nibble in W(0) of its frame. For nibbles
+ * 2 and 3 the top 2 bits of the word itself, dnib, select how the remaining bits are split:
+ * ```text
+ * nibble dnib  differences
+ *   01    --   4 x 8 bit
+ *   10    01   1 x 30 bit
+ *   10    10   2 x 15 bit
+ *   10    11   3 x 10 bit
+ *   11    00   5 x 6 bit
+ *   11    01   6 x 5 bit
+ *   11    10   7 x 4 bit
+ * ```
+ */
+
+use crate::mseed_error::MSeedError;
+use crate::steim_frame_block::{SteimFrame, SteimFrameBlock};
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+
+/**
+ *  Decode the indicated number of samples from the provided byte array and
+ *  return an integer array of the decompressed values. The first difference, d(0),
+ *  is relative to the last sample of the previous record and so is ignored, the
+ *  integration constant X(0) is used as the first sample instead.
+ *  @param b input byte array to be decoded, must be a multiple of 64 bytes
+ *  @param num_samples the number of samples that can be decoded from array
+ *  <b>b</b>
+ *  @return int array of length <b>num_samples</b>.
+ */
+pub fn decode(b: &[u8], num_samples: u32) -> Result<Vec<i32>, MSeedError> {
+    if !b.len().is_multiple_of(64) {
+        return Err(MSeedError::Compression(format!(
+            "encoded data length is not multiple of 64 bytes ({})",
+            b.len()
+        )));
+    }
+    let nsamp = num_samples as usize;
+    let num_frames = b.len() / 64;
+    // at most 7 differences in each of 15 words per frame, so a corrupt header cannot
+    // allocate more than the data could hold
+    let mut samples = Vec::with_capacity(nsamp.min(num_frames * 105));
+    if nsamp == 0 {
+        return Ok(samples);
+    }
+    let mut start = 0;
+    let mut end = 0;
+    let mut last_value = 0_i32;
+    let mut diffs = Vec::with_capacity(7);
+
+    'frames: for f in 0..num_frames {
+        let frame = &b[f * 64..(f + 1) * 64];
+        let nibbles = read_be_u32(frame, 0);
+        for w in 1..16 {
+            let curr_nibble = (nibbles >> (30 - 2 * w)) & 0x03;
+            let word = read_be_u32(frame, 4 * w);
+            diffs.clear();
+            match curr_nibble {
+                0 => {
+                    // integration constants X(0) and X(N) are only in the first frame
+                    if f == 0 && w == 1 {
+                        start = word as i32;
+                    } else if f == 0 && w == 2 {
+                        end = word as i32;
+                    }
+                }
+                1 => unpack_word(word, 4, 8, &mut diffs),
+                2 => match word >> 30 {
+                    1 => unpack_word(word, 1, 30, &mut diffs),
+                    2 => unpack_word(word, 2, 15, &mut diffs),
+                    3 => unpack_word(word, 3, 10, &mut diffs),
+                    dnib => {
+                        return Err(MSeedError::Compression(format!(
+                            "invalid dnib {} for nibble 2 in frame {} word {}",
+                            dnib, f, w
+                        )))
+                    }
+                },
+                3 => match word >> 30 {
+                    0 => unpack_word(word, 5, 6, &mut diffs),
+                    1 => unpack_word(word, 6, 5, &mut diffs),
+                    2 => unpack_word(word, 7, 4, &mut diffs),
+                    dnib => {
+                        return Err(MSeedError::Compression(format!(
+                            "invalid dnib {} for nibble 3 in frame {} word {}",
+                            dnib, f, w
+                        )))
+                    }
+                },
+                _ => {
+                    panic!("Cannot happen");
+                }
+            }
+            for d in &diffs {
+                if samples.is_empty() {
+                    // d(0) is ignored, X(0) is first sample
+                    last_value = start;
+                } else {
+                    last_value = last_value.wrapping_add(*d);
+                }
+                samples.push(last_value);
+                if samples.len() == nsamp {
+                    break 'frames;
+                }
+            }
+        }
+    }
+    if samples.len() != nsamp {
+        return Err(MSeedError::Compression(format!(
+            "Number of samples decompressed doesn't match number in header: decomp: {} != {}, header",
+            samples.len(),
+            num_samples
+        )));
+    }
+    if last_value != end {
+        return Err(MSeedError::Compression(format!(
+            "Last sample decompressed doesn't match reverse integration constant: {} != X(N) {}",
+            last_value, end
+        )));
+    }
+    Ok(samples)
+}
+
+/**
+ * Encode the array of integer values into a Steim 2 compressed frame block, with
+ * no previous sample so the first difference, d(0), is zero.
+ *
+ * see encode_with_bias
+ */
+pub fn encode(samples: &[i32], frames: usize) -> Result<SteimFrameBlock, MSeedError> {
+    match samples.first() {
+        // bias of the first sample makes d(0) zero
+        Some(first) => encode_with_bias(samples, frames, *first),
+        None => Err(MSeedError::Compression(String::from(
+            "samples array is zero size",
+        ))),
+    }
+}
+
+/**
+ * Encode the array of integer values into a Steim 2 compressed frame block.
+ * <b>frames</b> represents the maximum number of frames to be written.
+ * This number should be determined from the desired logical record length
+ * <i>minus</i> the size of the record header (modulo 64).
+ * If the frames fill up before <b>samples</b> is exhausted, the returned block
+ * holds only the samples that fit, see num_samples on the frame block, and the
+ * remaining samples should be encoded into the next record.
+ * Each word is packed with as many differences as will fit.
+ * @param samples the data points represented as signed integers
+ * @param frames the number of Steim frames to use in the encoding, 0 for unlimited
+ * @param bias previous sample, usually the last sample of the prior record, for use
+ * in calculating the first difference
+ * @return SteimFrameBlock containing encoded byte array
+ * @throws MSeedError samples array is zero size
+ * @throws MSeedError a difference cannot be represented in 30 bits
+ */
+pub fn encode_with_bias(
+    samples: &[i32],
+    frames: usize,
+    bias: i32,
+) -> Result<SteimFrameBlock, MSeedError> {
+    if samples.is_empty() {
+        return Err(MSeedError::Compression(String::from(
+            "samples array is zero size",
+        )));
+    }
+    // iterator produces d(0) relative to the bias, then differences to all remaining
+    // values, only as far as the frames can hold
+    let mut diff_iter = samples.iter().scan(bias, |state, &x| {
+        let d = x.wrapping_sub(*state);
+        *state = x;
+        Some(d)
+    });
+    // next differences not yet packed, enough for the largest packing
+    let mut pending = VecDeque::with_capacity(7);
+
+    let mut frame_block = SteimFrameBlock::new(2);
+    let mut num_samples = 0;
+    while num_samples < samples.len() && (frames == 0 || frame_block.steim_frame.len() < frames) {
+        let mut frame = SteimFrame::new();
+        // first frame holds X(0) and X(N) in first two words
+        let mut frame_idx = 0;
+        if frame_block.steim_frame.is_empty() {
+            frame.set_word(u32::from_be_bytes(samples[0].to_be_bytes()), 0, 0);
+            frame_idx = 2;
+        }
+        while frame_idx < 15 && num_samples < samples.len() {
+            while pending.len() < 7 {
+                match diff_iter.next() {
+                    Some(d) => pending.push_back(d),
+                    None => break,
+                }
+            }
+            let remaining = pending.make_contiguous();
+            let packing = PACKINGS.iter().find(|p| p.fits(remaining)).ok_or_else(|| {
+                MSeedError::Compression(format!(
+                    "difference {} at sample {} too large for steim2",
+                    remaining[0], num_samples
+                ))
+            })?;
+            frame.set_word(packing.pack(remaining), packing.nibble, frame_idx);
+            pending.drain(..packing.count);
+            num_samples += packing.count;
+            frame_idx += 1;
+        }
+        frame_block.steim_frame.push(frame);
+    }
+    frame_block.num_samples = num_samples;
+    frame_block.reverse_integration_constant(samples[num_samples - 1]);
+    Ok(frame_block)
+}
+
+/// One of the seven ways of packing differences into a Steim2 data word.
+struct Steim2Packing {
+    count: usize,
+    bits: u32,
+    nibble: u32,
+    dnib: Option<u32>,
+}
+
+/// Packings ordered by most differences per word first.
+const PACKINGS: [Steim2Packing; 7] = [
+    Steim2Packing {
+        count: 7,
+        bits: 4,
+        nibble: 3,
+        dnib: Some(2),
+    },
+    Steim2Packing {
+        count: 6,
+        bits: 5,
+        nibble: 3,
+        dnib: Some(1),
+    },
+    Steim2Packing {
+        count: 5,
+        bits: 6,
+        nibble: 3,
+        dnib: Some(0),
+    },
+    Steim2Packing {
+        count: 4,
+        bits: 8,
+        nibble: 1,
+        dnib: None,
+    },
+    Steim2Packing {
+        count: 3,
+        bits: 10,
+        nibble: 2,
+        dnib: Some(3),
+    },
+    Steim2Packing {
+        count: 2,
+        bits: 15,
+        nibble: 2,
+        dnib: Some(2),
+    },
+    Steim2Packing {
+        count: 1,
+        bits: 30,
+        nibble: 2,
+        dnib: Some(1),
+    },
+];
+
+impl Steim2Packing {
+    /// True if there are at least count differences and each fits in bits.
+    fn fits(&self, diffs: &[i32]) -> bool {
+        diffs.len() >= self.count && diffs[..self.count].iter().all(|&d| fits_bits(d, self.bits))
+    }
+
+    /// Packs the first count differences into a word, high bits first, with the dnib on top.
+    fn pack(&self, diffs: &[i32]) -> u32 {
+        let mask = (1_u32 << self.bits) - 1;
+        let mut word = match self.dnib {
+            Some(dnib) => dnib << 30,
+            None => 0,
+        };
+        for (i, &d) in diffs[..self.count].iter().enumerate() {
+            let shift = (self.count - 1 - i) as u32 * self.bits;
+            word |= ((d as u32) & mask) << shift;
+        }
+        word
+    }
+}
+
+/// True if v can be represented as a two's complement integer of the given number of bits.
+fn fits_bits(v: i32, bits: u32) -> bool {
+    let limit = 1_i32 << (bits - 1);
+    (-limit..limit).contains(&v)
+}
+
+/// Extracts count sign extended values of bits size each from the low bits of word,
+/// first value in the highest bits.
+fn unpack_word(word: u32, count: u32, bits: u32, out: &mut Vec<i32>) {
+    let mask = (1_u32 << bits) - 1;
+    for i in 0..count {
+        let shift = (count - 1 - i) * bits;
+        let v = (word >> shift) & mask;
+        // shift up to sign bit and back to sign extend
+        out.push(((v << (32 - bits)) as i32) >> (32 - bits));
+    }
+}
+
+fn read_be_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_be_bytes(<[u8; 4]>::try_from(&bytes[offset..offset + 4]).unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unpack_sign_extend() {
+        let mut out = Vec::new();
+        unpack_word(0x0000_000F, 1, 4, &mut out);
+        assert_eq!(out[0], -1);
+        out.clear();
+        unpack_word(0x2000_0000 | 0x1FFF_FFFF, 1, 30, &mut out);
+        assert_eq!(out[0], -1);
+        out.clear();
+        unpack_word(0x0102_03FF, 4, 8, &mut out);
+        assert_eq!(out, vec![1, 2, 3, -1]);
+    }
+
+    #[test]
+    fn pack_unpack_all_cases() {
+        for p in &PACKINGS {
+            let max = (1_i32 << (p.bits - 1)) - 1;
+            let min = -(1_i32 << (p.bits - 1));
+            let diffs: Vec<i32> = (0..p.count)
+                .map(|i| if i % 2 == 0 { max } else { min })
+                .collect();
+            assert!(p.fits(&diffs));
+            let word = p.pack(&diffs);
+            if let Some(dnib) = p.dnib {
+                assert_eq!(word >> 30, dnib);
+            }
+            let mut out = Vec::new();
+            unpack_word(word, p.count as u32, p.bits, &mut out);
+            assert_eq!(out, diffs, "count {} bits {}", p.count, p.bits);
+        }
+    }
+
+    #[test]
+    fn data_round_trip() -> Result<(), MSeedError> {
+        let data = [
+            1, -1, -1, -1, 200, -300, 16000, -18000, 20000, -40000, 3, 4, 5, 6, 7, 8, 9, 10, 10,
+            10, 40, 70, 100, 1_000_000, -99999999, 12, 11,
+        ];
+        let frame_block = encode(&data, 0)?;
+        assert_eq!(data.len(), frame_block.num_samples);
+        assert_eq!(frame_block.steim_version, 2);
+        let enc_bytes = frame_block.get_encoded_data()?;
+        assert_eq!(enc_bytes.len() % 64, 0);
+        // X(0) and X(N)
+        assert_eq!(read_be_u32(&enc_bytes, 4) as i32, data[0]);
+        assert_eq!(read_be_u32(&enc_bytes, 8) as i32, data[data.len() - 1]);
+        let rt_data = decode(&enc_bytes, frame_block.num_samples as u32)?;
+        assert_eq!(rt_data, data);
+        Ok(())
+    }
+
+    #[test]
+    fn multi_frame_round_trip() -> Result<(), MSeedError> {
+        let data: Vec<i32> = (0..2000)
+            .map(|i| ((i as f64 / 20.0).sin() * 10000.0) as i32)
+            .collect();
+        let frame_block = encode(&data, 0)?;
+        assert!(frame_block.steim_frame.len() > 1);
+        let rt_data = decode(&frame_block.get_encoded_data()?, data.len() as u32)?;
+        assert_eq!(rt_data, data);
+        Ok(())
+    }
+
+    #[test]
+    fn limited_frames() -> Result<(), MSeedError> {
+        let data: Vec<i32> = (0..2000).map(|i| i * i).collect();
+        let frame_block = encode(&data, 3)?;
+        assert_eq!(frame_block.steim_frame.len(), 3);
+        assert!(frame_block.num_samples < data.len());
+        let rt_data = decode(
+            &frame_block.get_encoded_data()?,
+            frame_block.num_samples as u32,
+        )?;
+        assert_eq!(rt_data, data[0..frame_block.num_samples]);
+        Ok(())
+    }
+
+    #[test]
+    fn header_num_samples_too_large() -> Result<(), MSeedError> {
+        let frame_block = encode(&[1, 2, 3], 0)?;
+        let enc_bytes = frame_block.get_encoded_data()?;
+        // capacity is bounded by the frames, not the corrupt sample count
+        assert!(matches!(
+            decode(&enc_bytes, u32::MAX),
+            Err(MSeedError::Compression(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn too_big_difference() {
+        let data = [0, i32::MAX];
+        assert!(encode(&data, 0).is_err());
+    }
+
+    #[test]
+    fn bias_first_difference() -> Result<(), MSeedError> {
+        let data = [100, 101, 102];
+        let frame_block = encode_with_bias(&data, 0, 99)?;
+        let enc_bytes = frame_block.get_encoded_data()?;
+        // d(0) = 100 - 99 packed with the other differences, 3 x 10 bit in W(3)
+        assert_eq!((read_be_u32(&enc_bytes, 0) >> 24) & 0x03, 2);
+        assert_eq!(read_be_u32(&enc_bytes, 12) >> 30, 3);
+        assert_eq!(read_be_u32(&enc_bytes, 12) & 0x3FF, 1);
+        assert_eq!(decode(&enc_bytes, 3)?, data);
+        Ok(())
+    }
+}
@@ -24,10 +24,19 @@ impl SteimFrame {
     pub fn is_empty(&self) -> bool {
         self.nibbles == 0
     }
+    /// Sets the data word at idx, 0 to 14, which is word W(idx+1) of the frame, along with
+    /// its 2-bit nibble in the W(0) control word. W(0) is not included in the words array.
     pub fn set_word(&mut self, word: u32, nibble: u32, idx: usize) {
+        assert!(idx < 15, "word idx must be 0..15, {}", idx);
         self.words[idx] = word;
-        assert!(idx < 16, "nubble idx must be 0..16, {}", idx);
-        self.nibbles += nibble << (30 - 2 * idx as u32)
+        self.nibbles += nibble << (28 - 2 * idx as u32)
+    }
+    /// Number of words used, up to the last data word, which always has a non-zero nibble.
+    pub(crate) fn num_words(&self) -> usize {
+        (0..15)
+            .rev()
+            .find(|idx| (self.nibbles >> (28 - 2 * idx)) & 0x03 != 0)
+            .map_or(0, |idx| idx as usize + 1)
     }
 }
 
@@ -86,6 +95,15 @@ impl SteimFrameBlock {
      * @return byte array containing the encoded, compressed data
      * @throws IOException from called method(s)
      */
+    /// Number of words used in all frames, not counting the W(0) nibble words. Every
+    /// frame but the last is full.
+    pub(crate) fn num_words(&self) -> usize {
+        match self.steim_frame.last() {
+            Some(last) => 15 * (self.steim_frame.len() - 1) + last.num_words(),
+            None => 0,
+        }
+    }
+
     pub fn get_encoded_data(&self) -> Result<Vec<u8>, MSeedError> {
         let mut encoded_data = Vec::new();
         for f in &self.steim_frame {
@@ -0,0 +1,113 @@
+use chrono::{DateTime, Duration, Utc};
+use serde_json::Number;
+
+use crate::mseed_error::MSeedError;
+use crate::record::MSeed3Record;
+
+/// Converts seconds to a Duration, rounded to the nanosecond.
+fn seconds_to_duration(seconds: f64) -> Duration {
+    Duration::nanoseconds((seconds * 1e9).round() as i64)
+}
+
+impl MSeed3Record {
+    /// The time correction in seconds from `FDSN.Time.Correction`, None if not present.
+    pub fn get_time_correction(&self) -> Result<Option<f64>, MSeedError> {
+        Ok(self
+            .get_fdsn_time()?
+            .and_then(|time| time.correction)
+            .and_then(|c| c.as_f64()))
+    }
+
+    /// True if the record has a time correction. In miniSEED 3 `FDSN.Time.Correction`
+    /// records a correction that has already been applied to the header start time, unlike
+    /// miniSEED 2 where an activity flag shows if it was applied, so a correction that is
+    /// present is always applied.
+    pub fn is_time_corrected(&self) -> Result<bool, MSeedError> {
+        Ok(self.get_time_correction()?.is_some_and(|c| c != 0.0))
+    }
+
+    /// Start time with the time correction applied. As the correction in a miniSEED 3
+    /// record has already been applied, this is the header start time, but unlike
+    /// get_start_as_utc it errors if the time correction header is not valid.
+    pub fn get_corrected_start(&self) -> Result<DateTime<Utc>, MSeedError> {
+        self.get_time_correction()?;
+        self.header.try_start_as_utc()
+    }
+
+    /// Start time before the time correction was applied, as recorded by the digitizer.
+    pub fn get_uncorrected_start(&self) -> Result<DateTime<Utc>, MSeedError> {
+        let correction = self.get_time_correction()?.unwrap_or(0.0);
+        Ok(self.header.try_start_as_utc()? - seconds_to_duration(correction))
+    }
+
+    /// Applies a further time correction in seconds, shifting the header start time and
+    /// adding to `FDSN.Time.Correction` so the total correction applied is kept. Other
+    /// `FDSN.Time` headers are unchanged.
+    pub fn apply_time_correction(&mut self, seconds: f64) -> Result<(), MSeedError> {
+        let start = self.header.try_start_as_utc()?;
+        let mut time = self.get_fdsn_time()?.unwrap_or_default();
+        let total = time.correction.and_then(|c| c.as_f64()).unwrap_or(0.0) + seconds;
+        time.correction = if total == 0.0 {
+            None
+        } else {
+            Number::from_f64(total)
+        };
+        self.set_fdsn_time(&time)?;
+        self.header
+            .set_start_from_utc(start + seconds_to_duration(seconds));
+        Ok(())
+    }
+
+    /// Reverts the time correction, restoring the uncorrected start time and removing
+    /// `FDSN.Time.Correction`.
+    pub fn remove_time_correction(&mut self) -> Result<(), MSeedError> {
+        match self.get_time_correction()? {
+            Some(correction) => self.apply_time_correction(-correction),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fdsn_headers::FdsnTime;
+    use std::io::{BufReader, BufWriter, Write};
+
+    #[test]
+    fn apply_and_remove() -> Result<(), MSeedError> {
+        let start = "2020-01-01T00:00:00Z".parse::<DateTime<Utc>>()?;
+        let mut rec = MSeed3Record::from_ints(start, 10.0, vec![1, 2, 3]);
+        rec.set_fdsn_time(&FdsnTime {
+            quality: Some(Number::from(90)),
+            ..Default::default()
+        })?;
+        assert!(!rec.is_time_corrected()?);
+        assert_eq!(rec.get_uncorrected_start()?, start);
+
+        rec.apply_time_correction(0.25)?;
+        rec.apply_time_correction(-0.0125)?;
+        let mut bytes = Vec::new();
+        {
+            let mut buf_writer = BufWriter::new(&mut bytes);
+            rec.write_to(&mut buf_writer)?;
+            buf_writer.flush()?;
+        }
+        let mut rec = MSeed3Record::from_reader(&mut BufReader::new(&bytes[..]))?;
+        assert!(rec.is_time_corrected()?);
+        assert_eq!(rec.get_time_correction()?, Some(0.2375));
+        let corrected = start + Duration::microseconds(237_500);
+        assert_eq!(rec.get_corrected_start()?, corrected);
+        assert_eq!(rec.header.get_start_as_utc(), corrected);
+        assert_eq!(rec.get_uncorrected_start()?, start);
+        assert_eq!(
+            rec.get_fdsn_time()?.unwrap().quality,
+            Some(Number::from(90))
+        );
+
+        rec.remove_time_correction()?;
+        assert_eq!(rec.get_time_correction()?, None);
+        assert_eq!(rec.header.get_start_as_utc(), start);
+        Ok(())
+    }
+}
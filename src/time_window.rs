@@ -0,0 +1,474 @@
+use chrono::{DateTime, Duration, Utc};
+use std::convert::TryFrom;
+use std::io::{ErrorKind, Read, Seek, SeekFrom};
+
+use crate::data_encoding::DataEncoding;
+use crate::decoded_timeseries::DecodedTimeseries;
+use crate::encoded_timeseries::EncodedTimeseries;
+use crate::fdsn_source_identifier::SourceIdentifier;
+use crate::header::{sample_offset, MSeed3Header, FIXED_HEADER_SIZE};
+use crate::mseed_error::MSeedError;
+use crate::record::MSeed3Record;
+
+/// Selects records by source identifier, and optionally by other fields of the header.
+pub trait SourceIdentifierMatcher {
+    fn matches_identifier(&self, identifier: &SourceIdentifier) -> bool;
+
+    /// True if the record with the identifier and fixed header matches. By default only the
+    /// identifier is checked, matchers that also select by time or publication version,
+    /// like SelectionList, check those here.
+    fn matches_header(&self, identifier: &SourceIdentifier, _header: &MSeed3Header) -> bool {
+        self.matches_identifier(identifier)
+    }
+}
+
+impl<T: SourceIdentifierMatcher + ?Sized> SourceIdentifierMatcher for &T {
+    fn matches_identifier(&self, identifier: &SourceIdentifier) -> bool {
+        (**self).matches_identifier(identifier)
+    }
+
+    fn matches_header(&self, identifier: &SourceIdentifier, header: &MSeed3Header) -> bool {
+        (**self).matches_header(identifier, header)
+    }
+}
+
+/// Matches only the identical identifier.
+impl SourceIdentifierMatcher for SourceIdentifier {
+    fn matches_identifier(&self, identifier: &SourceIdentifier) -> bool {
+        self == identifier
+    }
+}
+
+/// Matches the whole identifier string against a glob, where `*` matches any number of
+/// characters and `?` matches exactly one, like `FDSN:IU_*_00_B_H_?`.
+impl SourceIdentifierMatcher for str {
+    fn matches_identifier(&self, identifier: &SourceIdentifier) -> bool {
+        glob_match(self, &identifier.to_string())
+    }
+}
+
+impl SourceIdentifierMatcher for String {
+    fn matches_identifier(&self, identifier: &SourceIdentifier) -> bool {
+        self.as_str().matches_identifier(identifier)
+    }
+}
+
+/// True if the text matches the glob pattern, `*` matching any number of characters and
+/// `?` matching exactly one.
+pub(crate) fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // position of the last star in pattern, and the text position it was matched at
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, t));
+            p += 1;
+        } else if let Some((star_p, star_t)) = star {
+            // let the star absorb one more character and retry
+            p = star_p + 1;
+            t = star_t + 1;
+            star = Some((star_p, t));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// Iterator over the records in a seekable input that match the identifier and overlap the
+/// time window, start inclusive and end exclusive. Records are selected with the matcher's
+/// matches_header, so the time ranges and publication versions of a SelectionList also
+/// apply. Only the fixed header and identifier of
+/// other records are read, their extra headers and payload are seeked past. A record
+/// overlaps if any sample time lies within the window, and records without samples
+/// overlap if their start does.
+///
+/// If trim is set, returned records are cut to only the samples within the window. As the
+/// samples must be decoded to do this, trimmed records have the uncompressed encoding of
+/// the decoded samples, so steim records become INT32.
+///
+/// Iteration stops after the first error.
+///
+/// #Example
+///
+/// ```
+/// # use mseed3::MSeedError;
+/// # fn main() -> Result<(), MSeedError> {
+/// # use chrono::{DateTime, Duration, Utc};
+/// # use mseed3::MSeed3Record;
+/// # let start = "2014-11-28T12:00:00Z".parse::<DateTime<Utc>>()?;
+/// # let mut bytes = Vec::new();
+/// # {
+/// #     let mut buf_writer = std::io::BufWriter::new(&mut bytes);
+/// #     MSeed3Record::from_ints(start, 1.0, (0..100).collect()).write_to(&mut buf_writer)?;
+/// # }
+/// let file = std::io::Cursor::new(bytes);
+/// let mut reader = mseed3::TimeWindowReader::new(
+///     file,
+///     "FDSN:XX_STA_*",
+///     start + Duration::seconds(10),
+///     start + Duration::seconds(20),
+/// );
+/// reader.trim = true;
+/// for record in reader {
+///     assert_eq!(record?.header.num_samples, 10);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct TimeWindowReader<R: Read + Seek, M: SourceIdentifierMatcher> {
+    reader: R,
+    matcher: M,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    /// Trim returned records to the samples within the window.
+    pub trim: bool,
+    position: u64,
+    record_offset: u64,
+    done: bool,
+}
+
+impl<R: Read + Seek, M: SourceIdentifierMatcher> TimeWindowReader<R, M> {
+    pub fn new(
+        reader: R,
+        matcher: M,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> TimeWindowReader<R, M> {
+        TimeWindowReader {
+            reader,
+            matcher,
+            start,
+            end,
+            trim: false,
+            position: 0,
+            record_offset: 0,
+            done: false,
+        }
+    }
+
+    /// Byte offset from the start of the input of the most recently returned record.
+    pub fn record_offset(&self) -> u64 {
+        self.record_offset
+    }
+
+    /// Unwraps this reader, returning the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    /// Reads the fixed header, or None at the end of the input.
+    fn read_fixed_header(&mut self) -> Result<Option<[u8; FIXED_HEADER_SIZE]>, MSeedError> {
+        let mut buffer = [0; FIXED_HEADER_SIZE];
+        let mut filled = 0;
+        while filled < FIXED_HEADER_SIZE {
+            match self.reader.read(&mut buffer[filled..]) {
+                Ok(0) if filled == 0 => return Ok(None),
+                Ok(0) => return Err(std::io::Error::from(ErrorKind::UnexpectedEof).into()),
+                Ok(n) => filled += n,
+                Err(e) if e.kind() == ErrorKind::Interrupted => (),
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(Some(buffer))
+    }
+
+    fn next_record(&mut self) -> Result<Option<MSeed3Record>, MSeedError> {
+        loop {
+            let buffer = match self.read_fixed_header()? {
+                Some(buffer) => buffer,
+                None => return Ok(None),
+            };
+            let header = MSeed3Header::try_from(&buffer)?;
+            let record_size = header.checked_record_size()? as u64;
+            let mut id_bytes = vec![0; header.raw_identifier_length() as usize];
+            self.reader.read_exact(&mut id_bytes)?;
+            let identifier = SourceIdentifier::try_from(id_bytes)?;
+            let record_offset = self.position;
+            let header_bytes = (FIXED_HEADER_SIZE + header.raw_identifier_length() as usize) as u64;
+            self.position += record_size;
+            if self.matcher.matches_header(&identifier, &header)
+                && overlaps(&header, self.start, self.end)
+            {
+                self.reader
+                    .seek(SeekFrom::Current(-(header_bytes as i64)))?;
+                let mut take = (&mut self.reader).take(record_size);
+                let rec = MSeed3Record::from_reader(&mut std::io::BufReader::new(&mut take))?;
+                self.record_offset = record_offset;
+                if self.trim {
+                    return Ok(Some(rec.trim_to_window(self.start, self.end)?));
+                }
+                return Ok(Some(rec));
+            }
+            let skip = record_size - header_bytes;
+            self.reader.seek(SeekFrom::Current(skip as i64))?;
+        }
+    }
+}
+
+impl<R: Read + Seek, M: SourceIdentifierMatcher> Iterator for TimeWindowReader<R, M> {
+    type Item = Result<MSeed3Record, MSeedError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        match self.next_record() {
+            Ok(Some(record)) => Some(Ok(record)),
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+/// True if any sample of the record is within the window, or for records without samples,
+/// if the start is.
+fn overlaps(header: &MSeed3Header, start: DateTime<Utc>, end: DateTime<Utc>) -> bool {
+    let rec_start = header.get_start_as_utc();
+    if header.num_samples == 0 {
+        return rec_start >= start && rec_start < end;
+    }
+    rec_start < end && header.end_time() >= start
+}
+
+/// Index of the first sample at or after the time, at most num_samples.
+fn first_index_at_or_after(
+    rec_start: DateTime<Utc>,
+    sample_rate_period: f64,
+    time: DateTime<Utc>,
+    num_samples: usize,
+) -> usize {
+    if time <= rec_start {
+        return 0;
+    }
+    let period = sample_offset(sample_rate_period, 1);
+    if period <= Duration::zero() {
+        return 0;
+    }
+    // bounds the search below, for example for a window ending at DateTime::MAX_UTC
+    if rec_start + sample_offset(sample_rate_period, num_samples) < time {
+        return num_samples;
+    }
+    let diff = (time - rec_start).num_nanoseconds().unwrap_or(i64::MAX);
+    let mut index = (diff / period.num_nanoseconds().unwrap_or(i64::MAX)) as usize;
+    // sample_offset rounds to the nanosecond, so step to the exact boundary
+    while index > 0 && rec_start + sample_offset(sample_rate_period, index - 1) >= time {
+        index -= 1;
+    }
+    while rec_start + sample_offset(sample_rate_period, index) < time {
+        index += 1;
+    }
+    index
+}
+
+impl MSeed3Record {
+    /// Copy of the record containing only the samples within the window, start inclusive
+    /// and end exclusive. The start time is moved to the first remaining sample. Data is
+    /// decoded and stored uncompressed, so steim records become INT32. Text and opaque
+    /// records have no sample times and are returned unchanged.
+    pub fn trim_to_window(
+        &self,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<MSeed3Record, MSeedError> {
+        let samples = self.decode_samples()?;
+        if let DecodedTimeseries::Text(_) = samples {
+            return Ok(self.clone());
+        }
+        let rate = self.header.sample_rate_period;
+        let rec_start = self.header.get_start_as_utc();
+        let num_samples = samples.len();
+        let first = first_index_at_or_after(rec_start, rate, start, num_samples);
+        let last = first_index_at_or_after(rec_start, rate, end, num_samples).max(first);
+        let (encoding, encoded_data) = match samples {
+            DecodedTimeseries::Int16(v) => (
+                DataEncoding::INT16,
+                EncodedTimeseries::Int16(v[first..last].to_vec()),
+            ),
+            DecodedTimeseries::Int32(v) => (
+                DataEncoding::INT32,
+                EncodedTimeseries::Int32(v[first..last].to_vec()),
+            ),
+            DecodedTimeseries::Float32(v) => (
+                DataEncoding::FLOAT32,
+                EncodedTimeseries::Float32(v[first..last].to_vec()),
+            ),
+            DecodedTimeseries::Float64(v) => (
+                DataEncoding::FLOAT64,
+                EncodedTimeseries::Float64(v[first..last].to_vec()),
+            ),
+            DecodedTimeseries::Text(_) => unreachable!("text returned above"),
+        };
+        let mut header = MSeed3Header::new(
+            rec_start + sample_offset(rate, first),
+            encoding,
+            rate,
+            last - first,
+        );
+        header.flags = self.header.flags;
+        header.publication_version = self.header.publication_version;
+        Ok(MSeed3Record::new(
+            header,
+            self.identifier.clone(),
+            Some(self.extra_headers.clone()),
+            encoded_data,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sid_pattern::SelectionList;
+    use std::io::{BufReader, BufWriter, Cursor, Write};
+
+    fn write_records(records: &[MSeed3Record]) -> Result<Vec<u8>, MSeedError> {
+        let mut out = Vec::new();
+        {
+            let mut buf_writer = BufWriter::new(&mut out);
+            for rec in records {
+                rec.write_to(&mut buf_writer)?;
+            }
+            buf_writer.flush()?;
+        }
+        Ok(out)
+    }
+
+    fn record(id: &str, start: DateTime<Utc>, first: i32) -> MSeed3Record {
+        let mut rec = MSeed3Record::from_ints(start, 10.0, (first..first + 100).collect());
+        rec.identifier = SourceIdentifier::from(id);
+        rec
+    }
+
+    #[test]
+    fn glob() {
+        assert!(glob_match("FDSN:IU_*_00_B_H_?", "FDSN:IU_ANMO_00_B_H_Z"));
+        assert!(glob_match("*", ""));
+        assert!(glob_match("a*b*c", "aXbYbc"));
+        assert!(!glob_match("FDSN:IU_*_00_B_H_?", "FDSN:IU_ANMO_10_B_H_Z"));
+        assert!(!glob_match("a?", "a"));
+    }
+
+    #[test]
+    fn extract_window() -> Result<(), MSeedError> {
+        let start = "2020-01-01T00:00:00Z".parse::<DateTime<Utc>>()?;
+        let ten_sec = Duration::seconds(10);
+        let records = vec![
+            record("FDSN:XX_ABC_00_B_H_Z", start, 0),
+            record("FDSN:XX_ABC_00_B_H_N", start, 0),
+            record("FDSN:XX_ABC_00_B_H_Z", start + ten_sec, 100),
+            record("FDSN:XX_ABC_00_B_H_Z", start + ten_sec * 2, 200),
+        ];
+        let bytes = write_records(&records)?;
+        let win_start = start + Duration::milliseconds(9_500);
+        let win_end = start + Duration::milliseconds(10_500);
+
+        let mut reader = TimeWindowReader::new(
+            BufReader::new(Cursor::new(&bytes[..])),
+            SourceIdentifier::from("FDSN:XX_ABC_00_B_H_Z"),
+            win_start,
+            win_end,
+        );
+        let found: Vec<MSeed3Record> = (&mut reader).collect::<Result<_, _>>()?;
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[1].header.get_start_as_utc(), start + ten_sec);
+        assert_eq!(
+            reader.record_offset(),
+            (records[0].get_record_size() * 2) as u64
+        );
+
+        let mut reader = TimeWindowReader::new(
+            Cursor::new(&bytes[..]),
+            "FDSN:XX_ABC_00_B_H_?",
+            win_start,
+            win_end,
+        );
+        reader.trim = true;
+        let found: Vec<MSeed3Record> = reader.collect::<Result<_, _>>()?;
+        assert_eq!(found.len(), 3);
+        assert_eq!(found[0].header.get_start_as_utc(), win_start);
+        assert_eq!(
+            found[0].decode_samples()?,
+            DecodedTimeseries::Int32((95..100).collect())
+        );
+        assert_eq!(found[1].identifier.to_string(), "FDSN:XX_ABC_00_B_H_N");
+        assert_eq!(
+            found[2].decode_samples()?,
+            DecodedTimeseries::Int32((100..105).collect())
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn selection_list() -> Result<(), MSeedError> {
+        let start = "2020-01-01T00:00:00Z".parse::<DateTime<Utc>>()?;
+        let ten_sec = Duration::seconds(10);
+        let mut republished = record("FDSN:XX_ABC_00_B_H_N", start, 0);
+        republished.header.publication_version = 2;
+        let records = vec![
+            record("FDSN:XX_ABC_00_B_H_Z", start, 0),
+            record("FDSN:XX_ABC_00_B_H_N", start, 0),
+            republished,
+            record("FDSN:XX_ABC_00_B_H_Z", start + ten_sec, 100),
+            record("FDSN:XX_ABC_00_B_H_Z", start + ten_sec * 2, 200),
+        ];
+        let bytes = write_records(&records)?;
+        let selections = SelectionList::parse(
+            "FDSN:XX_ABC_00_B_H_Z 2020-01-01T00:00:15Z *\n\
+             FDSN:XX_ABC_00_B_H_N * * 2\n",
+        )?;
+        let reader = TimeWindowReader::new(
+            Cursor::new(&bytes[..]),
+            &selections,
+            start,
+            start + ten_sec * 3,
+        );
+        let found: Vec<MSeed3Record> = reader.collect::<Result<_, _>>()?;
+        let found: Vec<(String, u8, DateTime<Utc>)> = found
+            .iter()
+            .map(|r| {
+                (
+                    r.identifier.to_string(),
+                    r.header.publication_version,
+                    r.header.get_start_as_utc(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (String::from("FDSN:XX_ABC_00_B_H_N"), 2, start),
+                (String::from("FDSN:XX_ABC_00_B_H_Z"), 0, start + ten_sec),
+                (String::from("FDSN:XX_ABC_00_B_H_Z"), 0, start + ten_sec * 2),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn trim_unbounded() -> Result<(), MSeedError> {
+        let start = "2020-01-01T00:00:00Z".parse::<DateTime<Utc>>()?;
+        let rec = record("FDSN:XX_ABC_00_B_H_Z", start, 0);
+        let trimmed = rec.trim_to_window(start + Duration::seconds(5), DateTime::<Utc>::MAX_UTC)?;
+        assert_eq!(
+            trimmed.decode_samples()?,
+            DecodedTimeseries::Int32((50..100).collect())
+        );
+        let trimmed = rec.trim_to_window(DateTime::<Utc>::MIN_UTC, DateTime::<Utc>::MAX_UTC)?;
+        assert_eq!(trimmed.header.num_samples, 100);
+        let trimmed = rec.trim_to_window(DateTime::<Utc>::MAX_UTC, DateTime::<Utc>::MAX_UTC)?;
+        assert_eq!(trimmed.header.num_samples, 0);
+        Ok(())
+    }
+}
@@ -0,0 +1,279 @@
+use chrono::{DateTime, Duration, Utc};
+use std::fmt;
+
+use crate::data_encoding::DataEncoding;
+use crate::decoded_timeseries::DecodedTimeseries;
+use crate::fdsn_source_identifier::SourceIdentifier;
+use crate::header::sample_offset;
+use crate::mseed_error::MSeedError;
+use crate::record::MSeed3Record;
+
+/// Default relative tolerance for sample rates to be considered equal, as in libmseed.
+pub const DEFAULT_SAMPLE_RATE_TOLERANCE: f64 = 0.0001;
+
+/// Continuous decoded samples from one or more records of the same channel.
+#[derive(Debug, Clone)]
+pub struct Trace {
+    pub identifier: SourceIdentifier,
+    pub start: DateTime<Utc>,
+    /// Positive is a rate in samples per second, negative is a period in seconds.
+    pub sample_rate_period: f64,
+    /// Encoding of the records the samples came from.
+    pub encoding: DataEncoding,
+    pub publication_version: u8,
+    pub samples: DecodedTimeseries,
+}
+
+impl Trace {
+    /// Create a trace from the decoded samples of a single record.
+    pub fn from_record(rec: &MSeed3Record) -> Result<Trace, MSeedError> {
+        Ok(Trace {
+            identifier: rec.identifier.clone(),
+            start: rec.header.get_start_as_utc(),
+            sample_rate_period: rec.header.sample_rate_period,
+            encoding: rec.header.encoding.clone(),
+            publication_version: rec.header.publication_version,
+            samples: rec.decode_samples()?,
+        })
+    }
+
+    pub fn num_samples(&self) -> usize {
+        self.samples.len()
+    }
+
+    /// Time of the last sample, or the start if there are no samples.
+    pub fn end(&self) -> DateTime<Utc> {
+        let n = self.num_samples().max(1);
+        self.start + sample_offset(self.sample_rate_period, n - 1)
+    }
+
+    /// Expected time of the sample following the last sample.
+    pub fn next_sample_time(&self) -> DateTime<Utc> {
+        self.start + sample_offset(self.sample_rate_period, self.num_samples())
+    }
+
+    /// True if the other trace has the same identifier, encoding and, within the relative
+    /// tolerance, sample rate, so could be joined with this one.
+    fn is_compatible(&self, other: &Trace, sample_rate_tolerance: f64) -> bool {
+        self.identifier == other.identifier
+            && self.encoding.value() == other.encoding.value()
+            && self.publication_version == other.publication_version
+            && !matches!(self.samples, DecodedTimeseries::Text(_))
+            && rates_match(
+                self.sample_rate_period,
+                other.sample_rate_period,
+                sample_rate_tolerance,
+            )
+    }
+}
+
+impl fmt::Display for Trace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {} {} samples",
+            self.identifier,
+            self.start.format("%Y-%m-%dT%H:%M:%S%.9fZ"),
+            self.end().format("%Y-%m-%dT%H:%M:%S%.9fZ"),
+            self.num_samples()
+        )
+    }
+}
+
+fn rates_match(a: f64, b: f64, tolerance: f64) -> bool {
+    if a == b {
+        return true;
+    }
+    // compare as rates, so a rate and the equivalent period match
+    let to_rate = |v: f64| if v < 0.0 { -1.0 / v } else { v };
+    let (a, b) = (to_rate(a), to_rate(b));
+    (a - b).abs() <= tolerance * a.abs().max(b.abs())
+}
+
+/// Appends the samples, which must be the same type as traces only join when their
+/// encodings match.
+fn append_samples(first: &mut DecodedTimeseries, second: &DecodedTimeseries) {
+    match (first, second) {
+        (DecodedTimeseries::Int16(a), DecodedTimeseries::Int16(b)) => a.extend_from_slice(b),
+        (DecodedTimeseries::Int32(a), DecodedTimeseries::Int32(b)) => a.extend_from_slice(b),
+        (DecodedTimeseries::Float32(a), DecodedTimeseries::Float32(b)) => a.extend_from_slice(b),
+        (DecodedTimeseries::Float64(a), DecodedTimeseries::Float64(b)) => a.extend_from_slice(b),
+        _ => unreachable!("only traces with the same encoding are joined"),
+    }
+}
+
+/// Traces assembled from records, similar to the MS3TraceList of libmseed. A record joins
+/// an existing trace of the same identifier, publication version, encoding and sample rate
+/// when its first sample is within the time tolerance of the sample expected after the end
+/// of the trace, or its last sample is just before the start. Otherwise, as at a gap,
+/// overlap, sample rate or encoding change, the record begins a new trace.
+///
+/// #Example
+///
+/// ```
+/// # use mseed3::MSeedError;
+/// # fn main() -> Result<(), MSeedError> {
+/// use chrono::{DateTime, Duration, Utc};
+/// use mseed3::{MSeed3Record, TraceList};
+/// let start = "2014-11-28T12:00:00Z".parse::<DateTime<Utc>>()?;
+/// let records = vec![
+///     MSeed3Record::from_ints(start, 10.0, vec![1, 2, 3]),
+///     MSeed3Record::from_ints(start + Duration::milliseconds(300), 10.0, vec![4, 5]),
+///     MSeed3Record::from_ints(start + Duration::seconds(5), 10.0, vec![6]),
+/// ];
+/// let traces = TraceList::from_records(&records)?;
+/// assert_eq!(traces.traces.len(), 2);
+/// assert_eq!(traces.traces[0].num_samples(), 5);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct TraceList {
+    pub traces: Vec<Trace>,
+    /// Largest difference from the expected time for a record to join a trace. None uses
+    /// half of the sample period.
+    pub time_tolerance: Option<Duration>,
+    /// Largest relative difference in sample rate for a record to join a trace.
+    pub sample_rate_tolerance: f64,
+}
+
+impl TraceList {
+    pub fn new() -> TraceList {
+        TraceList {
+            traces: Vec::new(),
+            time_tolerance: None,
+            sample_rate_tolerance: DEFAULT_SAMPLE_RATE_TOLERANCE,
+        }
+    }
+
+    /// Assemble traces from all the records, using the default tolerances.
+    pub fn from_records<'a, I>(records: I) -> Result<TraceList, MSeedError>
+    where
+        I: IntoIterator<Item = &'a MSeed3Record>,
+    {
+        let mut list = TraceList::new();
+        for rec in records {
+            list.add_record(rec)?;
+        }
+        Ok(list)
+    }
+
+    fn tolerance_for(&self, sample_rate_period: f64) -> Duration {
+        self.time_tolerance
+            .unwrap_or_else(|| sample_offset(sample_rate_period, 1) / 2)
+    }
+
+    /// Decodes the record and adds its samples, joining an existing trace if possible.
+    pub fn add_record(&mut self, rec: &MSeed3Record) -> Result<(), MSeedError> {
+        self.add_trace(Trace::from_record(rec)?);
+        Ok(())
+    }
+
+    /// Index of a trace that the trace could be joined to, at either end.
+    fn find_joinable(&self, trace: &Trace) -> Option<usize> {
+        let tolerance = self.tolerance_for(trace.sample_rate_period);
+        self.traces.iter().position(|t| {
+            t.is_compatible(trace, self.sample_rate_tolerance)
+                && ((t.next_sample_time() - trace.start).abs() <= tolerance
+                    || (trace.next_sample_time() - t.start).abs() <= tolerance)
+        })
+    }
+
+    /// Adds the trace, joining it to the end or start of existing traces if possible. A
+    /// trace that fills a gap joins the traces on both sides.
+    pub fn add_trace(&mut self, trace: Trace) {
+        let tolerance = self.tolerance_for(trace.sample_rate_period);
+        let mut trace = trace;
+        let mut index: Option<usize> = None;
+        while let Some(i) = self.find_joinable(&trace) {
+            let mut existing = self.traces.remove(i);
+            if (existing.next_sample_time() - trace.start).abs() <= tolerance {
+                append_samples(&mut existing.samples, &trace.samples);
+                trace = existing;
+            } else {
+                append_samples(&mut trace.samples, &existing.samples);
+            }
+            index = Some(index.map_or(i, |j| j.min(i)));
+        }
+        match index {
+            Some(i) => self.traces.insert(i, trace),
+            None => self.traces.push(trace),
+        }
+    }
+
+    /// The traces for an identifier, in the order they were created.
+    pub fn traces_for(&self, identifier: &SourceIdentifier) -> Vec<&Trace> {
+        self.traces
+            .iter()
+            .filter(|t| &t.identifier == identifier)
+            .collect()
+    }
+}
+
+impl Default for TraceList {
+    fn default() -> Self {
+        TraceList::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn start() -> DateTime<Utc> {
+        "2020-01-01T00:00:00Z".parse::<DateTime<Utc>>().unwrap()
+    }
+
+    #[test]
+    fn join_and_split() -> Result<(), MSeedError> {
+        let ms = Duration::milliseconds;
+        let other_rate = MSeed3Record::from_ints(start() + ms(500), 20.0, vec![6]);
+        let records = vec![
+            MSeed3Record::from_ints(start(), 10.0, vec![1, 2, 3]),
+            // slightly late, within half a sample
+            MSeed3Record::from_ints(start() + ms(310), 10.0, vec![4, 5]),
+            other_rate,
+            // encoding change
+            MSeed3Record::from_floats(start() + ms(500), 10.0, vec![6.0]),
+            // gap
+            MSeed3Record::from_ints(start() + ms(900), 10.0, vec![10]),
+        ];
+        let list = TraceList::from_records(&records)?;
+        assert_eq!(list.traces.len(), 4);
+        assert_eq!(
+            list.traces[0].samples,
+            DecodedTimeseries::Int32(vec![1, 2, 3, 4, 5])
+        );
+        assert_eq!(list.traces[0].end(), start() + ms(400));
+        assert_eq!(list.traces[1].sample_rate_period, 20.0);
+        assert_eq!(
+            list.traces[2].encoding.value(),
+            DataEncoding::FLOAT32.value()
+        );
+        assert_eq!(list.traces[3].start, start() + ms(900));
+        Ok(())
+    }
+
+    #[test]
+    fn fill_gap_out_of_order() -> Result<(), MSeedError> {
+        let ms = Duration::milliseconds;
+        let mut list = TraceList::new();
+        list.time_tolerance = Some(ms(1));
+        list.add_record(&MSeed3Record::from_ints(start(), 10.0, vec![1, 2]))?;
+        list.add_record(&MSeed3Record::from_ints(start() + ms(400), 10.0, vec![5]))?;
+        assert_eq!(list.traces.len(), 2);
+        // fills the gap, joining both
+        list.add_record(&MSeed3Record::from_ints(
+            start() + ms(200),
+            10.0,
+            vec![3, 4],
+        ))?;
+        assert_eq!(list.traces.len(), 1);
+        assert_eq!(
+            list.traces[0].samples,
+            DecodedTimeseries::Int32(vec![1, 2, 3, 4, 5])
+        );
+        assert_eq!(list.traces[0].start, start());
+        Ok(())
+    }
+}
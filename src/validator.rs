@@ -0,0 +1,690 @@
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt;
+use std::io::{BufWriter, Read, Write};
+
+use crate::band_code::check_band_code;
+use crate::data_encoding::DataEncoding;
+use crate::extra_header_schema::ExtraHeaderValidator;
+use crate::fdsn_source_identifier::{FdsnSourceIdentifier, PREFIX};
+use crate::header::{MSeed3Header, FIXED_HEADER_SIZE};
+use crate::header_flags::HeaderFlags;
+use crate::mseed_error::MSeedError;
+use crate::record::MSeed3Record;
+use crate::record_ref::MSeed3RecordRef;
+use crate::{steim1, steim2};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+/// A problem found by the validator. The offset is of the problem field from the start of
+/// the validated bytes, record_offset is the start of the record containing it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ValidationIssue {
+    pub severity: Severity,
+    pub record_offset: usize,
+    pub offset: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} at byte {} (record at {}): {}",
+            self.severity, self.offset, self.record_offset, self.message
+        )
+    }
+}
+
+/// Errors and warnings found validating one or more records.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct ValidationReport {
+    pub issues: Vec<ValidationIssue>,
+    /// Number of records checked.
+    pub num_records: usize,
+    /// Number of bytes checked.
+    pub num_bytes: usize,
+}
+
+impl ValidationReport {
+    /// True if there are no errors, warnings are allowed.
+    pub fn is_valid(&self) -> bool {
+        self.errors().next().is_none()
+    }
+
+    pub fn errors(&self) -> impl Iterator<Item = &ValidationIssue> {
+        self.issues.iter().filter(|i| i.severity == Severity::Error)
+    }
+
+    pub fn warnings(&self) -> impl Iterator<Item = &ValidationIssue> {
+        self.issues
+            .iter()
+            .filter(|i| i.severity == Severity::Warning)
+    }
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{} records, {} bytes, {} errors, {} warnings",
+            self.num_records,
+            self.num_bytes,
+            self.errors().count(),
+            self.warnings().count()
+        )?;
+        for issue in &self.issues {
+            writeln!(f, "  {}", issue)?;
+        }
+        Ok(())
+    }
+}
+
+/// Checks records against the rules of the miniSEED 3 specification, continuing past
+/// problems where possible so that all of them are reported. Validation of a byte buffer
+/// stops only when a record length can no longer be trusted, for example a missing
+/// record indicator or a truncated record.
+#[derive(Debug, Clone)]
+pub struct RecordValidator {
+    pub check_crc: bool,
+    /// Schemas for the extra headers, None to skip schema checks.
+    pub schema: Option<ExtraHeaderValidator>,
+    /// Warn if the band code of an FDSN identifier does not match the sample rate.
+    pub check_band_code: bool,
+}
+
+impl RecordValidator {
+    /// Validator that checks the CRC and the FDSN extra headers against the bundled schema.
+    pub fn new() -> RecordValidator {
+        RecordValidator {
+            check_crc: true,
+            schema: Some(ExtraHeaderValidator::new()),
+            check_band_code: true,
+        }
+    }
+
+    /// Validates all records in the bytes, for example a whole file.
+    pub fn validate_bytes(&self, bytes: &[u8]) -> ValidationReport {
+        let mut report = ValidationReport {
+            num_bytes: bytes.len(),
+            ..Default::default()
+        };
+        let mut offset = 0;
+        while offset < bytes.len() {
+            report.num_records += 1;
+            match self.check_record(&bytes[offset..], offset, &mut report.issues) {
+                Some(len) => offset += len,
+                None => break,
+            }
+        }
+        report
+    }
+
+    /// Reads everything from the reader and validates it.
+    pub fn validate_reader<R: Read>(&self, reader: &mut R) -> Result<ValidationReport, MSeedError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        Ok(self.validate_bytes(&bytes))
+    }
+
+    /// Validates a record in memory, as it would be written.
+    pub fn validate_record(&self, record: &MSeed3Record) -> Result<ValidationReport, MSeedError> {
+        let mut bytes = Vec::new();
+        {
+            let mut buf_writer = BufWriter::new(&mut bytes);
+            record.write_to(&mut buf_writer)?;
+            buf_writer.flush()?;
+        }
+        Ok(self.validate_bytes(&bytes))
+    }
+
+    /// Checks the record at the start of bytes, returning its length if the following
+    /// record can be found.
+    fn check_record(
+        &self,
+        bytes: &[u8],
+        record_offset: usize,
+        issues: &mut Vec<ValidationIssue>,
+    ) -> Option<usize> {
+        let mut issue = |severity: Severity, field_offset: usize, message: String| {
+            issues.push(ValidationIssue {
+                severity,
+                record_offset,
+                offset: record_offset + field_offset,
+                message,
+            })
+        };
+        let error = Severity::Error;
+        let warning = Severity::Warning;
+        if bytes.len() < FIXED_HEADER_SIZE {
+            issue(
+                error,
+                0,
+                format!(
+                    "truncated header, {} bytes but fixed header is {}",
+                    bytes.len(),
+                    FIXED_HEADER_SIZE
+                ),
+            );
+            return None;
+        }
+        let mut fixed = [0; FIXED_HEADER_SIZE];
+        fixed.copy_from_slice(&bytes[..FIXED_HEADER_SIZE]);
+        // time fields are checked one by one below
+        let header = match MSeed3Header::parse_unchecked_time(&fixed) {
+            Ok(header) => header,
+            Err(e) => {
+                issue(error, 0, e.to_string());
+                return None;
+            }
+        };
+
+        let flags = HeaderFlags::from(header.flags);
+        if flags.has_reserved_bits() {
+            issue(
+                warning,
+                3,
+                format!("reserved flag bits set: {:#010b}", flags.reserved_bits()),
+            );
+        }
+        if header.year < 1900 || header.year > 2100 {
+            issue(warning, 8, format!("unlikely year {}", header.year));
+        }
+        if NaiveDate::from_yo_opt(header.year as i32, header.day_of_year as u32).is_none() {
+            issue(
+                error,
+                10,
+                format!(
+                    "day of year {} not valid for year {}",
+                    header.day_of_year, header.year
+                ),
+            );
+        }
+        if header.hour > 23 {
+            issue(error, 12, format!("hour {} not in 0-23", header.hour));
+        }
+        if header.minute > 59 {
+            issue(error, 13, format!("minute {} not in 0-59", header.minute));
+        }
+        if header.second > 60 {
+            issue(error, 14, format!("second {} not in 0-60", header.second));
+        } else if header.second == 60 && (header.hour != 23 || header.minute != 59) {
+            issue(
+                error,
+                14,
+                format!(
+                    "leap second 60 at {:02}:{:02} instead of 23:59",
+                    header.hour, header.minute
+                ),
+            );
+        }
+        if header.nanosecond >= 1_000_000_000 {
+            let message = if header.second == 59 && header.nanosecond < 2_000_000_000 {
+                format!(
+                    "nanosecond {} not less than 1e9, a leap second should use second 60",
+                    header.nanosecond
+                )
+            } else {
+                format!("nanosecond {} not less than 1e9", header.nanosecond)
+            };
+            issue(error, 4, message);
+        }
+        if let DataEncoding::UNKNOWN(val) = header.encoding {
+            issue(error, 15, format!("unknown data encoding {}", val));
+        }
+        if !header.sample_rate_period.is_finite() {
+            issue(
+                error,
+                16,
+                format!(
+                    "sample rate/period {} is not finite",
+                    header.sample_rate_period
+                ),
+            );
+        } else if header.sample_rate_period == 0.0
+            && header.num_samples > 0
+            && !matches!(header.encoding, DataEncoding::TEXT | DataEncoding::OPAQUE)
+        {
+            issue(
+                error,
+                16,
+                format!(
+                    "sample rate is zero but record has {} samples",
+                    header.num_samples
+                ),
+            );
+        }
+        if header.raw_identifier_length() == 0 {
+            issue(error, 33, String::from("identifier length is zero"));
+        }
+
+        let record_size = match header.checked_record_size() {
+            Ok(record_size) => record_size as usize,
+            Err(e) => {
+                issue(error, 36, e.to_string());
+                return None;
+            }
+        };
+        if bytes.len() < record_size {
+            issue(
+                error,
+                0,
+                format!(
+                    "truncated record, {} bytes but header gives record length {}",
+                    bytes.len(),
+                    record_size
+                ),
+            );
+            return None;
+        }
+        let record = match MSeed3RecordRef::with_header(bytes, header.clone()) {
+            Ok(record) => Some(record),
+            Err(MSeedError::Utf8Error(e)) => {
+                issue(
+                    error,
+                    FIXED_HEADER_SIZE,
+                    format!("identifier or extra headers not UTF-8: {}", e),
+                );
+                None
+            }
+            Err(MSeedError::DataLength(..)) | Err(MSeedError::DataLengthOverflow(..)) => {
+                issue(
+                    error,
+                    36,
+                    format!(
+                        "data length {} does not match {} samples of {}",
+                        header.raw_data_length(),
+                        header.num_samples,
+                        header.encoding
+                    ),
+                );
+                None
+            }
+            Err(e) => {
+                issue(error, 0, e.to_string());
+                None
+            }
+        };
+        let record = match record {
+            Some(record) => record,
+            None => return Some(record_size),
+        };
+
+        if self.check_crc {
+            let crc = record.calc_crc();
+            if crc != header.crc {
+                issue(
+                    error,
+                    28,
+                    format!(
+                        "CRC invalid, calculated {:#0X} but header has {:#0X}",
+                        crc, header.crc
+                    ),
+                );
+            }
+        }
+
+        let id_offset = FIXED_HEADER_SIZE;
+        if record.identifier.starts_with(PREFIX) {
+            match FdsnSourceIdentifier::parse(record.identifier) {
+                Ok(sid) => {
+                    if self.check_band_code {
+                        if let Some(message) = check_band_code(&sid, header.sample_rate_period) {
+                            issue(warning, id_offset, message);
+                        }
+                    }
+                }
+                Err(e) => issue(
+                    error,
+                    id_offset,
+                    format!(
+                        "identifier {} does not follow FDSN rules: {}",
+                        record.identifier, e
+                    ),
+                ),
+            }
+        } else {
+            issue(
+                warning,
+                id_offset,
+                format!(
+                    "identifier {} is not an FDSN source identifier",
+                    record.identifier
+                ),
+            );
+        }
+
+        let eh_offset = id_offset + record.identifier.len();
+        if !record.extra_headers.is_empty() {
+            match serde_json::from_str::<Value>(record.extra_headers) {
+                Ok(Value::Object(map)) => {
+                    if let Some(schema) = &self.schema {
+                        for v in schema.validate(&map) {
+                            issue(error, eh_offset, format!("extra header {}", v));
+                        }
+                    }
+                }
+                Ok(_) => issue(
+                    error,
+                    eh_offset,
+                    String::from("extra headers not a JSON object"),
+                ),
+                Err(e) => issue(
+                    error,
+                    eh_offset,
+                    format!("extra headers not valid JSON: {}", e),
+                ),
+            }
+        }
+
+        let data_offset = eh_offset + record.extra_headers.len();
+        let payload = record.payload;
+        match header.encoding {
+            DataEncoding::TEXT => {
+                if let Err(e) = std::str::from_utf8(payload) {
+                    issue(
+                        error,
+                        data_offset + e.valid_up_to(),
+                        String::from("text payload not UTF-8"),
+                    );
+                }
+            }
+            DataEncoding::STEIM1 | DataEncoding::STEIM2 => {
+                if payload.len() % 64 != 0 {
+                    issue(
+                        error,
+                        data_offset,
+                        format!(
+                            "Steim payload {} bytes, not a multiple of 64",
+                            payload.len()
+                        ),
+                    );
+                } else if header.num_samples > 0 {
+                    for (f, frame) in payload.chunks_exact(64).enumerate() {
+                        let nibbles = u32::from_be_bytes([frame[0], frame[1], frame[2], frame[3]]);
+                        if nibbles >> 30 != 0 {
+                            issue(
+                                warning,
+                                data_offset + f * 64,
+                                format!("Steim frame {} nibble for W0 is not 00", f),
+                            );
+                        }
+                        if f == 0 && (nibbles >> 26) & 0x0F != 0 {
+                            issue(
+                                error,
+                                data_offset,
+                                String::from(
+                                    "Steim frame 0 nibbles for X(0) and X(N) words are not 00",
+                                ),
+                            );
+                        }
+                    }
+                    // most differences in the 15 data words of a frame
+                    let per_frame = if matches!(header.encoding, DataEncoding::STEIM1) {
+                        60
+                    } else {
+                        105
+                    };
+                    let max_samples = payload.len() / 64 * per_frame;
+                    if header.num_samples as usize > max_samples {
+                        issue(
+                            error,
+                            24,
+                            format!(
+                                "{} samples more than {} Steim frames can hold",
+                                header.num_samples,
+                                payload.len() / 64
+                            ),
+                        );
+                    } else {
+                        let decoded = if matches!(header.encoding, DataEncoding::STEIM1) {
+                            steim1::decode(payload, header.num_samples)
+                        } else {
+                            steim2::decode(payload, header.num_samples)
+                        };
+                        if let Err(e) = decoded {
+                            issue(error, data_offset, e.to_string());
+                        }
+                    }
+                }
+            }
+            DataEncoding::OPAQUE if header.num_samples != 0 => {
+                issue(
+                    warning,
+                    24,
+                    format!("opaque payload with {} samples", header.num_samples),
+                );
+            }
+            _ => (),
+        }
+        Some(record_size)
+    }
+}
+
+impl Default for RecordValidator {
+    fn default() -> Self {
+        RecordValidator::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::record_packer::RecordPacker;
+    use crate::SourceIdentifier;
+    use chrono::{DateTime, Utc};
+    use serde_json::json;
+
+    fn to_bytes(records: &[MSeed3Record]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        {
+            let mut buf_writer = BufWriter::new(&mut bytes);
+            for rec in records {
+                rec.write_to(&mut buf_writer).unwrap();
+            }
+            buf_writer.flush().unwrap();
+        }
+        bytes
+    }
+
+    #[test]
+    fn valid_records() -> Result<(), MSeedError> {
+        let start = "2014-11-28T12:00:09Z".parse::<DateTime<Utc>>()?;
+        let packer = RecordPacker::new(
+            SourceIdentifier::from("FDSN:XX_STA_00_B_H_Z"),
+            20.0,
+            DataEncoding::STEIM2,
+        );
+        let samples: Vec<i32> = (0..500).map(|i| (i * 13) % 200).collect();
+        let mut records = packer.pack_ints(start, &samples)?;
+        records.push(MSeed3Record::from_ints(start, 20.0, vec![1, 2, 3]));
+        let bytes = to_bytes(&records);
+        let report = RecordValidator::new().validate_bytes(&bytes);
+        assert!(report.is_valid(), "{}", report);
+        assert_eq!(report.num_records, records.len());
+        assert_eq!(report.warnings().count(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn header_field_errors() -> Result<(), MSeedError> {
+        let start = "2014-11-28T12:00:09Z".parse::<DateTime<Utc>>()?;
+        let mut rec = MSeed3Record::from_ints(start, 20.0, vec![1, 2, 3]);
+        rec.header.hour = 24;
+        rec.header.day_of_year = 366;
+        rec.header.nanosecond = 1_500_000_000;
+        rec.header.flags = 0x10;
+        rec.mut_fdsn_headers()
+            .insert(String::from("Sequence"), json!("one"));
+        let first = to_bytes(&[rec]);
+        let second = to_bytes(&[MSeed3Record::from_ints(start, 0.0, vec![1, 2])]);
+        let mut bytes = first.clone();
+        bytes.extend_from_slice(&second);
+        let report = RecordValidator::new().validate_bytes(&bytes);
+        assert_eq!(report.num_records, 2);
+        let errors: Vec<(usize, usize)> = report
+            .errors()
+            .map(|i| (i.record_offset, i.offset))
+            .collect();
+        assert_eq!(
+            errors,
+            vec![
+                (0, 10),
+                (0, 12),
+                (0, 4),
+                (0, 40 + 20),
+                (first.len(), first.len() + 16)
+            ]
+        );
+        assert_eq!(report.warnings().next().unwrap().offset, 3);
+        Ok(())
+    }
+
+    #[test]
+    fn leap_second_errors() -> Result<(), MSeedError> {
+        let start = "2016-12-31T23:59:59Z".parse::<DateTime<Utc>>()?;
+        let mut rec = MSeed3Record::from_ints(start, 20.0, vec![1, 2, 3]);
+        rec.header.second = 60;
+        let report = RecordValidator::new().validate_bytes(&to_bytes(&[rec.clone()]));
+        assert!(report.is_valid(), "{}", report);
+        // second 60 only at the end of a day, and not as an extra second of nanoseconds
+        rec.header.minute = 58;
+        let report = RecordValidator::new().validate_bytes(&to_bytes(&[rec.clone()]));
+        let errors: Vec<usize> = report.errors().map(|i| i.offset).collect();
+        assert_eq!(errors, vec![14]);
+        rec.header.minute = 59;
+        rec.header.second = 59;
+        rec.header.nanosecond = 1_500_000_000;
+        let report = RecordValidator::new().validate_bytes(&to_bytes(&[rec]));
+        let errors: Vec<usize> = report.errors().map(|i| i.offset).collect();
+        assert_eq!(errors, vec![4]);
+        Ok(())
+    }
+
+    #[test]
+    fn steim_num_samples_too_large() -> Result<(), MSeedError> {
+        let start = "2014-11-28T12:00:09Z".parse::<DateTime<Utc>>()?;
+        for encoding in [DataEncoding::STEIM1, DataEncoding::STEIM2] {
+            let packer = RecordPacker::new(
+                SourceIdentifier::from("FDSN:XX_STA_00_B_H_Z"),
+                20.0,
+                encoding,
+            );
+            let records = packer.pack_ints(start, &[1, 2, 3])?;
+            let mut bytes = to_bytes(&records);
+            bytes[24..28].copy_from_slice(&u32::MAX.to_le_bytes());
+            let validator = RecordValidator {
+                check_crc: false,
+                ..RecordValidator::new()
+            };
+            let report = validator.validate_bytes(&bytes);
+            let errors: Vec<&ValidationIssue> = report.errors().collect();
+            assert_eq!(errors.len(), 1, "{}", report);
+            assert_eq!(errors[0].offset, 24);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn band_code_warning() -> Result<(), MSeedError> {
+        let start = "2014-11-28T12:00:09Z".parse::<DateTime<Utc>>()?;
+        // fake channel has band code B, for 10-80 Hz
+        let bytes = to_bytes(&[MSeed3Record::from_ints(start, 100.0, vec![1, 2, 3])]);
+        let mut validator = RecordValidator::new();
+        let report = validator.validate_bytes(&bytes);
+        assert!(report.is_valid(), "{}", report);
+        let warnings: Vec<&ValidationIssue> = report.warnings().collect();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].offset, FIXED_HEADER_SIZE);
+        assert!(
+            warnings[0].message.contains("band code B"),
+            "{}",
+            warnings[0]
+        );
+        validator.check_band_code = false;
+        assert_eq!(validator.validate_bytes(&bytes).warnings().count(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn payload_errors() -> Result<(), MSeedError> {
+        let start = "2014-11-28T12:00:09Z".parse::<DateTime<Utc>>()?;
+        let packer = RecordPacker::new(
+            SourceIdentifier::from("FDSN:XX_STA_00_B_H_Z"),
+            20.0,
+            DataEncoding::STEIM1,
+        );
+        let mut rec = packer.pack_ints(start, &[1, 2, 3, 4, 5])?.remove(0);
+        let mut bytes = to_bytes(&[rec.clone()]);
+        let data_offset = bytes.len() - 64;
+        // corrupt X(N)
+        bytes[data_offset + 11] ^= 0x01;
+        let report = RecordValidator::new().validate_bytes(&bytes);
+        let messages: Vec<&str> = report.errors().map(|i| i.message.as_str()).collect();
+        assert_eq!(messages.len(), 2, "{}", report);
+        assert!(messages[0].starts_with("CRC invalid"));
+        assert!(messages[1].contains("X(N)"));
+
+        rec.header.encoding = DataEncoding::TEXT;
+        rec.encoded_data = crate::EncodedTimeseries::Raw(vec![b'a', 0xff]);
+        rec.header.num_samples = 2;
+        let bytes = to_bytes(&[rec]);
+        let report = RecordValidator::new().validate_bytes(&bytes);
+        let issue = report.errors().next().unwrap();
+        assert_eq!(issue.message, "text payload not UTF-8");
+        assert_eq!(issue.offset, bytes.len() - 1);
+        Ok(())
+    }
+
+    #[test]
+    fn truncated() -> Result<(), MSeedError> {
+        let start = "2014-11-28T12:00:09Z".parse::<DateTime<Utc>>()?;
+        let bytes = to_bytes(&[MSeed3Record::from_ints(start, 20.0, vec![1, 2, 3])]);
+        let report = RecordValidator::new().validate_bytes(&bytes[..bytes.len() - 2]);
+        assert!(!report.is_valid());
+        assert!(report.issues[0].message.starts_with("truncated record"));
+        Ok(())
+    }
+
+    #[test]
+    fn overflowing_lengths() -> Result<(), MSeedError> {
+        let start = "2014-11-28T12:00:09Z".parse::<DateTime<Utc>>()?;
+        let bytes = to_bytes(&[MSeed3Record::from_ints(start, 20.0, vec![1, 2, 3])]);
+        let validator = RecordValidator {
+            check_crc: false,
+            ..RecordValidator::new()
+        };
+        // 4 bytes per sample overflows u32
+        let mut num_samples = bytes.clone();
+        num_samples[24..28].copy_from_slice(&0x4000_0001_u32.to_le_bytes());
+        let report = validator.validate_bytes(&num_samples);
+        let errors: Vec<&ValidationIssue> = report.errors().collect();
+        assert_eq!(errors.len(), 1, "{}", report);
+        assert_eq!(errors[0].offset, 36);
+        assert!(errors[0].message.contains("1073741825 samples"));
+        // compressed, so the record size overflows u32 instead
+        let mut data_length = bytes.clone();
+        data_length[15] = DataEncoding::STEIM2.value();
+        data_length[36..40].copy_from_slice(&0xFFFF_FFF0_u32.to_le_bytes());
+        let report = validator.validate_bytes(&data_length);
+        let errors: Vec<&ValidationIssue> = report.errors().collect();
+        assert_eq!(errors.len(), 1, "{}", report);
+        assert_eq!(errors[0].offset, 36);
+        assert_eq!(report.num_records, 1);
+        Ok(())
+    }
+}
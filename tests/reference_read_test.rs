@@ -93,3 +93,35 @@ pub fn read_ref_json(filename: &str) -> serde_json::Result<Value> {
 
     serde_json::from_str(&contents)
 }
+
+#[test]
+fn test_ref_steim2_decode() -> Result<(), MSeedError> {
+    let file = File::open("tests/reference-data/reference-sinusoid-steim2.mseed3")?;
+    let mut buf_reader = BufReader::new(file);
+    let steim2_rec = mseed3::MSeed3Record::from_reader(&mut buf_reader)?;
+    let file = File::open("tests/reference-data/reference-sinusoid-int32.mseed3")?;
+    let mut buf_reader = BufReader::new(file);
+    let int32_rec = mseed3::MSeed3Record::from_reader(&mut buf_reader)?;
+    let steim2_bytes = match &steim2_rec.encoded_data {
+        mseed3::EncodedTimeseries::Raw(v) => v,
+        _ => panic!("expected raw bytes"),
+    };
+    let decoded = mseed3::steim2::decode(steim2_bytes, steim2_rec.header.num_samples)?;
+    let expected: Vec<i32> = match &int32_rec.encoded_data {
+        mseed3::EncodedTimeseries::Raw(v) => v
+            .chunks_exact(4)
+            .map(|b| i32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect(),
+        _ => panic!("expected raw bytes"),
+    };
+    assert_eq!(decoded, expected);
+    // and back again
+    let frame_block = mseed3::steim2::encode(&decoded, 0)?;
+    assert_eq!(frame_block.num_samples, decoded.len());
+    let round_trip = mseed3::steim2::decode(
+        &frame_block.get_encoded_data()?,
+        frame_block.num_samples as u32,
+    )?;
+    assert_eq!(round_trip, expected);
+    Ok(())
+}